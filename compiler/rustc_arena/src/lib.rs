@@ -21,12 +21,14 @@
 use smallvec::SmallVec;
 
 use std::alloc::Layout;
+use std::any;
 use std::cell::{Cell, RefCell};
 use std::cmp;
 use std::marker::{PhantomData, Send};
 use std::mem::{self, MaybeUninit};
 use std::ptr;
 use std::slice;
+use std::str;
 
 #[inline(never)]
 #[cold]
@@ -46,6 +48,9 @@ pub struct TypedArena<T> {
     /// A vector of arena chunks.
     chunks: RefCell<Vec<TypedArenaChunk<T>>>,
 
+    /// The number of objects the first chunk can hold.
+    first_chunk_capacity: usize,
+
     /// Marker indicating that dropping the arena causes its owned
     /// instances of `T` to be dropped.
     _own: PhantomData<T>,
@@ -80,6 +85,14 @@ fn start(&mut self) -> *mut T {
         MaybeUninit::slice_as_mut_ptr(&mut self.storage)
     }
 
+    // Returns whether `addr` is in the storage of the chunk, which is empty for zero-sized
+    // elements.
+    #[inline]
+    fn contains(&self, addr: usize) -> bool {
+        let start = self.storage.as_ptr() as usize;
+        start <= addr && addr < start + self.storage.len() * mem::size_of::<T>()
+    }
+
     // Returns a pointer to the end of the allocated space.
     #[inline]
     fn end(&mut self) -> *mut T {
@@ -111,6 +124,7 @@ fn default() -> TypedArena<T> {
             ptr: Cell::new(ptr::null_mut()),
             end: Cell::new(ptr::null_mut()),
             chunks: RefCell::new(vec![]),
+            first_chunk_capacity: PAGE / cmp::max(1, mem::size_of::<T>()),
             _own: PhantomData,
         }
     }
@@ -126,8 +140,17 @@ impl<I, T> IterExt<T> for I
 {
     #[inline]
     default fn alloc_from_iter(self, arena: &TypedArena<T>) -> &mut [T] {
-        let vec: SmallVec<[_; 8]> = self.into_iter().collect();
-        vec.alloc_from_iter(arena)
+        let iter = self.into_iter();
+        match iter.size_hint() {
+            // Zero-sized objects have no chunk to be staged in.
+            (min, Some(max)) if min == max && mem::size_of::<T>() != 0 => {
+                arena.alloc_from_exact_size_iter(iter, min)
+            }
+            _ => {
+                let vec: SmallVec<[_; 8]> = iter.collect();
+                vec.alloc_from_iter(arena)
+            }
+        }
     }
 }
 
@@ -183,6 +206,14 @@ fn alloc_from_iter(mut self, arena: &TypedArena<A::Item>) -> &mut [A::Item] {
 }
 
 impl<T> TypedArena<T> {
+    /// Creates a new `TypedArena` whose first chunk can hold at least `capacity` objects,
+    /// instead of a page worth of them.
+    pub fn with_capacity(capacity: usize) -> TypedArena<T> {
+        let mut arena = TypedArena::default();
+        arena.first_chunk_capacity = capacity;
+        arena
+    }
+
     /// Allocates an object in the `TypedArena`, returning a reference to it.
     #[inline]
     pub fn alloc(&self, object: T) -> &mut T {
@@ -242,6 +273,110 @@ pub fn alloc_from_iter<I: IntoIterator<Item = T>>(&self, iter: I) -> &mut [T] {
         iter.alloc_from_iter(self)
     }
 
+    /// Returns whether `ptr` points into one of the chunks of the arena. This is always false
+    /// when `T` is zero-sized, since its objects don't take any space in the chunks.
+    pub fn contains<U: ?Sized>(&self, ptr: *const U) -> bool {
+        let addr = ptr as *const u8 as usize;
+        self.chunks.borrow().iter().any(|chunk| chunk.contains(addr))
+    }
+
+    /// Returns an iterator over the objects allocated in the arena, in allocation order.
+    ///
+    /// This borrows the arena mutably, so that no reference returned by `alloc` is still alive.
+    ///
+    /// Panics if `T` is zero-sized or doesn't need dropping: the chunks only keep track of how
+    /// many objects they hold when these have to be dropped.
+    pub fn iter_allocated(&mut self) -> impl Iterator<Item = &T> {
+        assert!(mem::needs_drop::<T>() && mem::size_of::<T>() != 0);
+
+        let end = self.ptr.get() as usize;
+        let chunks = &*self.chunks.get_mut();
+        let last = chunks.len().saturating_sub(1);
+        chunks.iter().enumerate().flat_map(move |(i, chunk)| {
+            // The last chunk is partially filled, up to `self.ptr`.
+            let len = if i == last {
+                (end - chunk.storage.as_ptr() as usize) / mem::size_of::<T>()
+            } else {
+                chunk.entries
+            };
+            unsafe { MaybeUninit::slice_assume_init_ref(&chunk.storage[..len]) }.iter()
+        })
+    }
+
+    /// Drops all the objects in the arena and frees all its chunks, returning it to the state
+    /// of a new arena. The next allocation starts over with a chunk of the initial capacity,
+    /// so that an arena which was only briefly large gives its memory back.
+    pub fn reset_and_shrink(&mut self) {
+        let capacity = self.first_chunk_capacity;
+        // Dropping the previous arena drops its objects and chunks.
+        *self = TypedArena::with_capacity(capacity);
+    }
+
+    /// Allocates the objects produced by an iterator which said it would produce `len` of them.
+    /// If it produces fewer, only these are allocated, and if it produces more, the extra ones
+    /// aren't taken from it.
+    ///
+    /// The iterator may allocate in the arena itself, so the objects can't be written where
+    /// they'll end up while it runs. They are written at the end of the current chunk, which
+    /// `self.end` is moved before, and then moved to their place once they're all produced.
+    /// `T` mustn't be zero-sized, since a fresh arena has no chunk to write them to.
+    #[inline]
+    fn alloc_from_exact_size_iter<I: Iterator<Item = T>>(
+        &self,
+        mut iter: I,
+        len: usize,
+    ) -> &mut [T] {
+        if len == 0 {
+            return &mut [];
+        }
+        self.ensure_capacity(len);
+
+        // Drops the objects written so far if the iterator panics, and gives the end of the
+        // chunk back to the arena, unless the arena moved to another chunk in the meantime.
+        struct Staging<'a, T> {
+            arena: &'a TypedArena<T>,
+            start: *mut T,
+            end: *mut T,
+            len: usize,
+        }
+
+        impl<T> Drop for Staging<'_, T> {
+            fn drop(&mut self) {
+                unsafe { ptr::drop_in_place(slice::from_raw_parts_mut(self.start, self.len)) }
+                if self.arena.end.get() == self.start {
+                    self.arena.end.set(self.end);
+                }
+            }
+        }
+
+        let end = self.end.get();
+        let start = unsafe { end.sub(len) };
+        self.end.set(start);
+        let mut staging = Staging { arena: self, start, end, len: 0 };
+        while staging.len < len {
+            match iter.next() {
+                Some(value) => unsafe {
+                    ptr::write(start.add(staging.len), value);
+                    staging.len += 1;
+                },
+                None => break,
+            }
+        }
+
+        // The objects are moved out of the staging area below, so it mustn't drop them.
+        let len = mem::replace(&mut staging.len, 0);
+        drop(staging);
+        if len == 0 {
+            return &mut [];
+        }
+        unsafe {
+            let start_ptr = self.alloc_raw_slice(len);
+            // The objects may be moved to a place overlapping their staging area.
+            ptr::copy(start, start_ptr, len);
+            slice::from_raw_parts_mut(start_ptr, len)
+        }
+    }
+
     /// Grows the arena.
     #[inline(never)]
     #[cold]
@@ -266,7 +401,7 @@ fn grow(&self, additional: usize) {
                 new_cap = last_chunk.storage.len().min(HUGE_PAGE / elem_size / 2);
                 new_cap *= 2;
             } else {
-                new_cap = PAGE / elem_size;
+                new_cap = self.first_chunk_capacity;
             }
             // Also ensure that this chunk can fit `additional`.
             new_cap = cmp::max(additional, new_cap);
@@ -336,6 +471,9 @@ pub struct DroplessArena {
 
     /// A vector of arena chunks.
     chunks: RefCell<Vec<TypedArenaChunk<u8>>>,
+
+    /// The size of the first chunk, in bytes.
+    first_chunk_capacity: usize,
 }
 
 unsafe impl Send for DroplessArena {}
@@ -347,11 +485,18 @@ fn default() -> DroplessArena {
             start: Cell::new(ptr::null_mut()),
             end: Cell::new(ptr::null_mut()),
             chunks: Default::default(),
+            first_chunk_capacity: PAGE,
         }
     }
 }
 
 impl DroplessArena {
+    /// Creates a new `DroplessArena` whose first chunk is at least `capacity` bytes large,
+    /// instead of a page.
+    pub fn with_capacity(capacity: usize) -> DroplessArena {
+        DroplessArena { first_chunk_capacity: capacity, ..DroplessArena::default() }
+    }
+
     #[inline(never)]
     #[cold]
     fn grow(&self, additional: usize) {
@@ -368,7 +513,7 @@ fn grow(&self, additional: usize) {
                 new_cap = last_chunk.storage.len().min(HUGE_PAGE / 2);
                 new_cap *= 2;
             } else {
-                new_cap = PAGE;
+                new_cap = self.first_chunk_capacity;
             }
             // Also ensure that this chunk can fit `additional`.
             new_cap = cmp::max(additional, new_cap);
@@ -401,9 +546,33 @@ fn alloc_raw_without_grow(&self, layout: Layout) -> Option<*mut u8> {
         }
     }
 
+    /// Returns whether `ptr` points into one of the chunks of the arena.
+    pub fn contains<U: ?Sized>(&self, ptr: *const U) -> bool {
+        let addr = ptr as *const u8 as usize;
+        self.chunks.borrow().iter().any(|chunk| chunk.contains(addr))
+    }
+
+    /// Returns the number of bytes allocated for the chunks of the arena.
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.storage.len()).sum()
+    }
+
+    /// Returns the number of bytes left unused in the current chunk. They are wasted if the next
+    /// allocation doesn't fit in them, since allocations never go back to previous chunks.
+    pub fn wasted_bytes(&self) -> usize {
+        self.end.get() as usize - self.start.get() as usize
+    }
+
+    /// Frees all the chunks of the arena, returning it to the state of a new arena. The next
+    /// allocation starts over with a chunk of the initial capacity.
+    pub fn reset_and_shrink(&mut self) {
+        let capacity = self.first_chunk_capacity;
+        *self = DroplessArena::with_capacity(capacity);
+    }
+
     #[inline]
     pub fn alloc_raw(&self, layout: Layout) -> *mut u8 {
-        assert!(layout.size() != 0);
+        assert!(layout.size() != 0, "`DroplessArena` cannot allocate zero bytes");
         loop {
             if let Some(a) = self.alloc_raw_without_grow(layout) {
                 break a;
@@ -416,7 +585,8 @@ pub fn alloc_raw(&self, layout: Layout) -> *mut u8 {
 
     #[inline]
     pub fn alloc<T>(&self, object: T) -> &mut T {
-        assert!(!mem::needs_drop::<T>());
+        assert_dropless::<T>();
+        assert_not_zero_sized::<T>();
 
         let mem = self.alloc_raw(Layout::for_value::<T>(&object)) as *mut T;
 
@@ -439,9 +609,13 @@ pub fn alloc_slice<T>(&self, slice: &[T]) -> &mut [T]
     where
         T: Copy,
     {
-        assert!(!mem::needs_drop::<T>());
-        assert!(mem::size_of::<T>() != 0);
-        assert!(!slice.is_empty());
+        assert_dropless::<T>();
+        assert_not_zero_sized::<T>();
+        assert!(
+            !slice.is_empty(),
+            "`DroplessArena` cannot allocate an empty slice of `{}`",
+            any::type_name::<T>()
+        );
 
         let mem = self.alloc_raw(Layout::for_value::<[T]>(slice)) as *mut T;
 
@@ -451,6 +625,19 @@ pub fn alloc_slice<T>(&self, slice: &[T]) -> &mut [T]
         }
     }
 
+    /// Allocates a copy of `string` in the `DroplessArena`, returning a reference to it.
+    /// Empty strings aren't allocated.
+    #[inline]
+    pub fn alloc_str(&self, string: &str) -> &str {
+        if string.is_empty() {
+            return "";
+        }
+        let bytes = self.alloc_slice(string.as_bytes());
+
+        // SAFETY: the bytes were copied from a `&str`, so they are valid UTF-8.
+        unsafe { str::from_utf8_unchecked(bytes) }
+    }
+
     #[inline]
     unsafe fn write_from_iter<T, I: Iterator<Item = T>>(
         &self,
@@ -476,8 +663,8 @@ unsafe fn write_from_iter<T, I: Iterator<Item = T>>(
     #[inline]
     pub fn alloc_from_iter<T, I: IntoIterator<Item = T>>(&self, iter: I) -> &mut [T] {
         let iter = iter.into_iter();
-        assert!(mem::size_of::<T>() != 0);
-        assert!(!mem::needs_drop::<T>());
+        assert_not_zero_sized::<T>();
+        assert_dropless::<T>();
 
         let size_hint = iter.size_hint();
 
@@ -515,6 +702,26 @@ pub fn alloc_from_iter<T, I: IntoIterator<Item = T>>(&self, iter: I) -> &mut [T]
     }
 }
 
+// Checks that `DroplessArena` can hold objects of type `T`, since it never drops them.
+#[inline]
+fn assert_dropless<T>() {
+    assert!(
+        !mem::needs_drop::<T>(),
+        "`DroplessArena` cannot hold `{}`, which needs to be dropped; use `TypedArena` instead",
+        any::type_name::<T>()
+    );
+}
+
+// Checks that `T` isn't zero-sized, since `DroplessArena` can't allocate zero bytes.
+#[inline]
+fn assert_not_zero_sized<T>() {
+    assert!(
+        mem::size_of::<T>() != 0,
+        "`DroplessArena` cannot allocate the zero-sized type `{}`",
+        any::type_name::<T>()
+    );
+}
+
 /// Calls the destructor for an object when dropped.
 struct DropType {
     drop_fn: unsafe fn(*mut u8),
@@ -713,6 +920,11 @@ pub fn alloc_slice<T: ::std::marker::Copy>(&self, value: &[T]) -> &mut [T] {
                 self.dropless.alloc_slice(value)
             }
 
+            #[inline]
+            pub fn alloc_str(&self, string: &str) -> &str {
+                self.dropless.alloc_str(string)
+            }
+
             pub fn alloc_from_iter<'a, T: ArenaAllocatable<'tcx, U>, U>(
                 &'a self,
                 iter: impl ::std::iter::IntoIterator<Item = T>,
@@ -1,5 +1,5 @@
 extern crate test;
-use super::TypedArena;
+use super::{DroplessArena, TypedArena};
 use std::cell::Cell;
 use test::Bencher;
 
@@ -111,6 +111,33 @@ pub fn test_noncopy() {
     }
 }
 
+#[test]
+pub fn test_typed_arena_iter_allocated() {
+    let mut arena = TypedArena::default();
+    assert_eq!(arena.iter_allocated().count(), 0);
+
+    for i in 0..10000 {
+        arena.alloc(i.to_string());
+    }
+    arena.alloc_from_iter((10000..10100).map(|i| i.to_string()));
+    arena.alloc("last".to_string());
+
+    let strings: Vec<&String> = arena.iter_allocated().collect();
+    assert_eq!(strings.len(), 10101);
+    for (i, string) in strings[..10100].iter().enumerate() {
+        assert_eq!(**string, i.to_string());
+    }
+    assert_eq!(strings[10100], "last");
+}
+
+#[test]
+#[should_panic]
+pub fn test_typed_arena_iter_allocated_copy() {
+    let mut arena = TypedArena::default();
+    arena.alloc(1);
+    let _ = arena.iter_allocated();
+}
+
 #[test]
 pub fn test_typed_arena_zero_sized() {
     let arena = TypedArena::default();
@@ -119,6 +146,118 @@ pub fn test_typed_arena_zero_sized() {
     }
 }
 
+#[test]
+#[should_panic(expected = "assertion failed: mem::size_of::<T>() != 0")]
+pub fn test_typed_arena_zero_sized_from_exact_size_iter() {
+    let arena = TypedArena::default();
+    arena.alloc_from_iter((0..10).map(|_| ()));
+}
+
+#[test]
+pub fn test_dropless_arena_alloc_str() {
+    let arena = DroplessArena::default();
+    assert_eq!(arena.alloc_str(""), "");
+    assert!(arena.chunks.borrow().is_empty());
+
+    let strings: Vec<&str> = (0..1000).map(|i| arena.alloc_str(&format!("string {}", i))).collect();
+    for (i, string) in strings.iter().enumerate() {
+        assert_eq!(*string, format!("string {}", i));
+    }
+}
+
+#[test]
+pub fn test_dropless_arena_memory_usage() {
+    let arena = DroplessArena::default();
+    assert_eq!(arena.allocated_bytes(), 0);
+    assert_eq!(arena.wasted_bytes(), 0);
+
+    arena.alloc(0u8);
+    let allocated = arena.allocated_bytes();
+    assert!(allocated > 0);
+    assert_eq!(arena.wasted_bytes(), allocated - 1);
+
+    // Allocating more than what's left in the chunk grows the arena, wasting the last byte.
+    arena.alloc_slice(&vec![0u8; allocated]);
+    assert!(arena.allocated_bytes() >= 2 * allocated);
+    assert_eq!(arena.wasted_bytes(), arena.allocated_bytes() - 2 * allocated);
+}
+
+#[test]
+pub fn test_arena_with_capacity() {
+    let arena = TypedArena::with_capacity(10);
+    arena.alloc(0u64);
+    assert_eq!(arena.chunks.borrow()[0].storage.len(), 10);
+
+    let arena = TypedArena::with_capacity(100000);
+    arena.alloc(0u64);
+    assert_eq!(arena.chunks.borrow()[0].storage.len(), 100000);
+
+    let arena = DroplessArena::with_capacity(16);
+    arena.alloc(0u8);
+    assert_eq!(arena.allocated_bytes(), 16);
+
+    // The first chunk still fits the first allocation.
+    let arena = DroplessArena::with_capacity(16);
+    arena.alloc_slice(&[0u8; 100]);
+    assert_eq!(arena.allocated_bytes(), 100);
+}
+
+#[test]
+pub fn test_dropless_arena_reset_and_shrink() {
+    let mut arena = DroplessArena::with_capacity(16);
+    arena.alloc_slice(&[0u8; 10000]);
+    assert_eq!(arena.allocated_bytes(), 10000);
+
+    arena.reset_and_shrink();
+    assert_eq!((arena.allocated_bytes(), arena.wasted_bytes()), (0, 0));
+
+    arena.alloc(0u8);
+    assert_eq!(arena.allocated_bytes(), 16);
+}
+
+#[test]
+pub fn test_arena_contains() {
+    let arena = TypedArena::default();
+    let boxed = Box::new(Point { x: 1, y: 2, z: 3 });
+    assert!(!arena.contains(&*boxed));
+    let points: Vec<*const Point> =
+        (0..1000).map(|_| arena.alloc(Point { x: 1, y: 2, z: 3 }) as *const Point).collect();
+    assert!(points.iter().all(|&point| arena.contains(point)));
+    assert!(!arena.contains(&*boxed));
+
+    let arena = TypedArena::default();
+    let unit = arena.alloc(());
+    assert!(!arena.contains(unit));
+
+    let arena = DroplessArena::default();
+    let string = arena.alloc_str("hello");
+    assert!(arena.contains(string));
+    assert!(!arena.contains("hello"));
+}
+
+#[test]
+#[should_panic(
+    expected = "`DroplessArena` cannot hold `alloc::string::String`, which needs to be dropped"
+)]
+pub fn test_dropless_arena_needs_drop() {
+    let arena = DroplessArena::default();
+    arena.alloc(String::new());
+}
+
+#[test]
+#[should_panic(expected = "`DroplessArena` cannot allocate the zero-sized type `()`")]
+pub fn test_dropless_arena_zero_sized() {
+    let arena = DroplessArena::default();
+    arena.alloc_slice(&[(), ()]);
+}
+
+#[test]
+#[should_panic(expected = "`DroplessArena` cannot allocate an empty slice of `u32`")]
+pub fn test_dropless_arena_empty_slice() {
+    let arena = DroplessArena::default();
+    arena.alloc_slice::<u32>(&[]);
+}
+
 #[test]
 pub fn test_typed_arena_clear() {
     let mut arena = TypedArena::default();
@@ -189,6 +328,126 @@ fn test_typed_arena_drop_on_clear() {
     }
 }
 
+#[test]
+fn test_typed_arena_reset_and_shrink() {
+    let counter = Cell::new(0);
+    let mut arena: TypedArena<DropCounter<'_>> = TypedArena::with_capacity(10);
+    for _ in 0..1000 {
+        arena.alloc(DropCounter { count: &counter });
+    }
+    assert!(arena.chunks.borrow().len() > 1);
+
+    arena.reset_and_shrink();
+    assert_eq!(counter.get(), 1000);
+    assert!(arena.chunks.borrow().is_empty());
+
+    arena.alloc(DropCounter { count: &counter });
+    assert_eq!(arena.chunks.borrow()[0].storage.len(), 10);
+    drop(arena);
+    assert_eq!(counter.get(), 1001);
+}
+
+// An iterator that produces fewer objects than it says it will.
+struct ShortIter<I>(I, usize);
+
+impl<I: Iterator> Iterator for ShortIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.1, Some(self.1))
+    }
+}
+
+#[test]
+fn test_typed_arena_alloc_from_exact_size_iter() {
+    let counter = Cell::new(0);
+    {
+        let arena: TypedArena<DropCounter<'_>> = TypedArena::default();
+        let slice = arena.alloc_from_iter((0..10).map(|_| DropCounter { count: &counter }));
+        assert_eq!(slice.len(), 10);
+
+        let iter = (0..3).map(|_| DropCounter { count: &counter });
+        let slice = arena.alloc_from_iter(ShortIter(iter, 5));
+        assert_eq!(slice.len(), 3);
+        arena.alloc(DropCounter { count: &counter });
+    }
+    assert_eq!(counter.get(), 14);
+}
+
+#[test]
+fn test_typed_arena_alloc_from_iter_reentrant() {
+    let counter = Cell::new(0);
+    {
+        let arena: TypedArena<DropCounter<'_>> = TypedArena::default();
+        // Each object allocates enough others to fill a few chunks.
+        let slice = arena.alloc_from_iter((0..10).map(|_| {
+            for _ in 0..200 {
+                arena.alloc(DropCounter { count: &counter });
+            }
+            DropCounter { count: &counter }
+        }));
+        assert_eq!(slice.len(), 10);
+    }
+    assert_eq!(counter.get(), 2010);
+}
+
+#[test]
+fn test_typed_arena_alloc_from_iter_panic() {
+    let counter = Cell::new(0);
+    {
+        let arena: TypedArena<DropCounter<'_>> = TypedArena::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            arena.alloc_from_iter((0..10).map(|i| {
+                arena.alloc(DropCounter { count: &counter });
+                if i == 5 {
+                    panic!("iterator panicked");
+                }
+                DropCounter { count: &counter }
+            }));
+        }));
+        assert!(result.is_err());
+        // The objects produced before the panic are dropped right away.
+        assert_eq!(counter.get(), 5);
+        arena.alloc(DropCounter { count: &counter });
+    }
+    assert_eq!(counter.get(), 12);
+}
+
+#[test]
+fn test_typed_arena_alloc_from_iter_panic_on_third() {
+    let counter = Cell::new(0);
+    let arena: TypedArena<DropCounter<'_>> = TypedArena::default();
+    let make = |i| {
+        if i == 2 {
+            panic!("iterator panicked");
+        }
+        DropCounter { count: &counter }
+    };
+
+    // Both the exact-size iterators, written straight into the chunk, and the others, collected
+    // first, drop the two objects produced before the panic exactly once.
+    for exact_size in [true, false] {
+        counter.set(0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            if exact_size {
+                arena.alloc_from_iter((0..5).map(make));
+            } else {
+                arena.alloc_from_iter((0..5).filter(|_| true).map(make));
+            }
+        }));
+        assert!(result.is_err());
+        assert_eq!(counter.get(), 2);
+    }
+
+    // The arena doesn't hold them anymore.
+    drop(arena);
+    assert_eq!(counter.get(), 2);
+}
+
 thread_local! {
     static DROP_COUNTER: Cell<u32> = Cell::new(0)
 }
@@ -7,6 +7,7 @@
 use rustc_ast::ptr::P;
 use rustc_ast_pretty::pprust;
 use rustc_expand::base::*;
+use rustc_session::parse::feature_err;
 use rustc_session::Session;
 use rustc_span::symbol::{sym, Ident, Symbol};
 use rustc_span::Span;
@@ -199,6 +200,8 @@ pub fn expand_test_or_bench(
         )
     };
 
+    let (source_file, start_line, start_col, end_line, end_col) = get_location_info(cx, &item);
+
     let mut test_const = cx.item(
         sp,
         Ident::new(item.ident.name, sp),
@@ -249,6 +252,14 @@ pub fn expand_test_or_bench(
                                         "ignore",
                                         cx.expr_bool(sp, should_ignore(&cx.sess, &item)),
                                     ),
+                                    // ignore_message: Some("...") | None
+                                    field(
+                                        "ignore_message",
+                                        match ignore_message(&cx.sess, &item) {
+                                            Some(msg) => cx.expr_some(sp, cx.expr_str(sp, msg)),
+                                            None => cx.expr_none(sp),
+                                        },
+                                    ),
                                     // allow_fail: true | false
                                     field(
                                         "allow_fail",
@@ -276,6 +287,12 @@ pub fn expand_test_or_bench(
                                                 cx.expr_path(should_panic_path("YesWithMessage")),
                                                 vec![cx.expr_str(sp, sym)],
                                             ),
+                                            // test::ShouldPanic::YesWithRegex("...")
+                                            ShouldPanic::YesWithRegex(sym) => cx.expr_call(
+                                                sp,
+                                                cx.expr_path(should_panic_path("YesWithRegex")),
+                                                vec![cx.expr_str(sp, sym)],
+                                            ),
                                         },
                                     ),
                                     // test_type: ...
@@ -296,6 +313,18 @@ pub fn expand_test_or_bench(
                                             }
                                         },
                                     ),
+                                    // env: &[]
+                                    field("env", cx.expr_vec_slice(sp, vec![])),
+                                    // source_file: <relative_path_of_source_file>
+                                    field("source_file", cx.expr_str(sp, source_file)),
+                                    // start_line: start line of the test fn identifier.
+                                    field("start_line", cx.expr_usize(sp, start_line)),
+                                    // start_col: start column of the test fn identifier.
+                                    field("start_col", cx.expr_usize(sp, start_col)),
+                                    // end_line: end line of the test fn identifier.
+                                    field("end_line", cx.expr_usize(sp, end_line)),
+                                    // end_col: end column of the test fn identifier.
+                                    field("end_col", cx.expr_usize(sp, end_col)),
                                     // },
                                 ],
                             ),
@@ -350,12 +379,17 @@ fn item_path(mod_path: &[Ident], item_ident: &Ident) -> String {
 enum ShouldPanic {
     No,
     Yes(Option<Symbol>),
+    YesWithRegex(Symbol),
 }
 
 fn should_ignore(sess: &Session, i: &ast::Item) -> bool {
     sess.contains_name(&i.attrs, sym::ignore)
 }
 
+fn ignore_message(sess: &Session, i: &ast::Item) -> Option<Symbol> {
+    sess.first_attr_value_str_by_name(&i.attrs, sym::ignore)
+}
+
 fn should_fail(sess: &Session, i: &ast::Item) -> bool {
     sess.contains_name(&i.attrs, sym::allow_fail)
 }
@@ -366,6 +400,30 @@ fn should_panic(cx: &ExtCtxt<'_>, i: &ast::Item) -> ShouldPanic {
             let sd = &cx.sess.parse_sess.span_diagnostic;
 
             match attr.meta_item_list() {
+                // Handle #[should_panic(expected_regex = "^foo: \\d+$")]
+                Some(list) if list.len() == 1 && list[0].has_name(sym::expected_regex) => {
+                    let regex = list[0].meta_item().and_then(|mi| mi.value_str());
+                    let features = cx.ecfg.features;
+                    if !features.map_or(true, |features| features.should_panic_regex) {
+                        feature_err(
+                            &cx.sess.parse_sess,
+                            sym::should_panic_regex,
+                            list[0].span(),
+                            "`expected_regex` in `#[should_panic]` is unstable",
+                        )
+                        .emit();
+                    }
+                    match regex {
+                        Some(regex) => ShouldPanic::YesWithRegex(regex),
+                        None => {
+                            sd.span_err(
+                                attr.span,
+                                "argument must be of the form: `expected_regex = \"regex\"`",
+                            );
+                            ShouldPanic::Yes(None)
+                        }
+                    }
+                }
                 // Handle #[should_panic(expected = "foo")]
                 Some(list) => {
                     let msg = list
@@ -398,6 +456,17 @@ fn should_panic(cx: &ExtCtxt<'_>, i: &ast::Item) -> ShouldPanic {
     }
 }
 
+/// Returns the source file of the test, and the lines and columns, starting at 1,
+/// where the name of the test function starts and ends in it.
+fn get_location_info(cx: &ExtCtxt<'_>, item: &ast::Item) -> (Symbol, usize, usize, usize, usize) {
+    let span = item.ident.span;
+    let source_map = cx.sess.source_map();
+    let lo = source_map.lookup_char_pos(span.lo());
+    let hi = source_map.lookup_char_pos(span.hi());
+    let source_file = Symbol::intern(&lo.file.name.prefer_remapped().to_string_lossy());
+    (source_file, lo.line, lo.col.0 + 1, hi.line, hi.col.0 + 1)
+}
+
 enum TestType {
     UnitTest,
     IntegrationTest,
@@ -687,6 +687,9 @@ pub fn set(&self, features: &mut Features, span: Span) {
     /// Allows `cfg(target_abi = "...")`.
     (active, cfg_target_abi, "1.55.0", Some(80970), None),
 
+    /// Allows `#[should_panic(expected_regex = "...")]`.
+    (active, should_panic_regex, "1.55.0", None, None),
+
     // -------------------------------------------------------------------------
     // feature-group-end: actual feature gates
     // -------------------------------------------------------------------------
@@ -447,6 +447,10 @@ pub fn crate_source_untracked(&self, cnum: CrateNum) -> CrateSource {
         self.get_crate_data(cnum).source.clone()
     }
 
+    pub fn is_proc_macro_crate_untracked(&self, cnum: CrateNum) -> bool {
+        self.get_crate_data(cnum).is_proc_macro_crate()
+    }
+
     pub fn get_span_untracked(&self, def_id: DefId, sess: &Session) -> Span {
         self.get_crate_data(def_id.krate).get_span(def_id.index, sess)
     }
@@ -531,6 +531,7 @@
         exp2f64,
         expect,
         expected,
+        expected_regex,
         expf32,
         expf64,
         export_name,
@@ -1083,6 +1084,7 @@
         shl,
         shl_assign,
         should_panic,
+        should_panic_regex,
         shr,
         shr_assign,
         simd,
@@ -274,7 +274,7 @@
 pub use self::error::{Error, ErrorKind, Result};
 #[unstable(feature = "internal_output_capture", issue = "none")]
 #[doc(no_inline, hidden)]
-pub use self::stdio::set_output_capture;
+pub use self::stdio::{set_error_capture, set_output_capture};
 #[stable(feature = "rust1", since = "1.0.0")]
 pub use self::stdio::{stderr, stdin, stdout, Stderr, Stdin, Stdout};
 #[unstable(feature = "stdio_locked", issue = "86845")]
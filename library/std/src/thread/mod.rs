@@ -460,6 +460,8 @@ pub unsafe fn spawn_unchecked<'a, F, T>(self, f: F) -> io::Result<JoinHandle<T>>
 
         let output_capture = crate::io::set_output_capture(None);
         crate::io::set_output_capture(output_capture.clone());
+        let error_capture = crate::io::set_error_capture(None);
+        crate::io::set_error_capture(error_capture.clone());
 
         let main = move || {
             if let Some(name) = their_thread.cname() {
@@ -467,6 +469,7 @@ pub unsafe fn spawn_unchecked<'a, F, T>(self, f: F) -> io::Result<JoinHandle<T>>
             }
 
             crate::io::set_output_capture(output_capture);
+            crate::io::set_error_capture(error_capture);
 
             // SAFETY: the stack guard passed is the one for the current thread.
             // This means the current thread's stack and the new thread's stack
@@ -1,13 +1,17 @@
 //! Benchmarking module.
 use super::{
+    cli::DEFAULT_BENCH_SAMPLES,
     event::CompletedTest,
+    helpers::metrics::MetricMap,
     options::BenchMode,
     test_result::TestResult,
     types::{TestDesc, TestId},
     Sender,
 };
 
-use crate::stats;
+use crate::stats::{self, Stats};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
 use std::cmp;
 use std::io;
 use std::panic::{catch_unwind, AssertUnwindSafe};
@@ -23,6 +27,58 @@ pub fn black_box<T>(dummy: T) -> T {
     std::hint::black_box(dummy)
 }
 
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A global allocator that counts the allocations made by each thread, on top
+/// of the allocator it wraps.
+///
+/// Benchmarks only report their allocations per iteration (with
+/// `--bench-count-allocs`) if the benchmark binary installs it:
+///
+/// ```
+/// #![feature(test)]
+/// extern crate test;
+///
+/// #[global_allocator]
+/// static ALLOC: test::bench::CountingAlloc = test::bench::CountingAlloc(std::alloc::System);
+/// # fn main() {}
+/// ```
+pub struct CountingAlloc<A = System>(pub A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        self.0.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
+
+fn count_allocation() {
+    // The counter may already be gone if the thread is being torn down.
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+/// Returns the number of allocations made by the current thread so far through
+/// a `CountingAlloc`.
+pub fn allocation_count() -> u64 {
+    ALLOCATIONS.try_with(|allocations| allocations.get()).unwrap_or(0)
+}
+
 /// Manager of the benchmarking runs.
 ///
 /// This is fed into functions marked with `#[bench]` to allow for
@@ -32,6 +88,11 @@ pub fn black_box<T>(dummy: T) -> T {
 pub struct Bencher {
     mode: BenchMode,
     summary: Option<stats::Summary>,
+    samples: Vec<f64>,
+    alloc_samples: Vec<f64>,
+    metrics: MetricMap,
+    warmup: Duration,
+    sample_count: usize,
     pub bytes: u64,
 }
 
@@ -46,7 +107,80 @@ pub fn iter<T, F>(&mut self, mut inner: F)
             return;
         }
 
-        self.summary = Some(iter(&mut inner));
+        let (summary, samples, alloc_samples) =
+            measure(&mut |k| ns_iter_inner(&mut inner, k), self.warmup, self.sample_count);
+        self.summary = Some(summary);
+        self.samples = samples;
+        self.alloc_samples = alloc_samples;
+    }
+
+    /// Callback for benchmark functions to run in their body, when each
+    /// iteration consumes its input (e.g. sorting a vector).
+    ///
+    /// Only `routine` is timed: its inputs are made by `setup` before the
+    /// iterations, `batch_size` at a time, and its outputs are dropped after
+    /// them. Larger batches make the timing overhead smaller, but keep more
+    /// inputs and outputs in memory at once.
+    pub fn iter_batched<I, O, S, R>(&mut self, mut setup: S, mut routine: R, batch_size: usize)
+    where
+        S: FnMut() -> I,
+        R: FnMut(I) -> O,
+    {
+        if self.mode == BenchMode::Single {
+            ns_iter_batched(&mut setup, &mut routine, batch_size, 1);
+            return;
+        }
+
+        let (summary, samples, alloc_samples) = measure(
+            &mut |k| ns_iter_batched(&mut setup, &mut routine, batch_size, k),
+            self.warmup,
+            self.sample_count,
+        );
+        self.summary = Some(summary);
+        self.samples = samples;
+        self.alloc_samples = alloc_samples;
+    }
+
+    /// Callback for benchmark functions to run in their body, when `inner` is
+    /// a fixed workload which should run exactly once (e.g. processing a large
+    /// file).
+    ///
+    /// The number of iterations isn't scaled to get a stable median, and there
+    /// is no warmup: the time of this single run is reported as the ns/iter.
+    pub fn iter_once<T, F>(&mut self, inner: F)
+    where
+        F: FnOnce() -> T,
+    {
+        let allocations = allocation_count();
+        let start = Instant::now();
+        black_box(inner());
+        let ns = start.elapsed().as_nanos() as f64;
+        let allocs = (allocation_count() - allocations) as f64;
+
+        self.summary = Some(stats::Summary::new(&[ns]));
+        self.samples = vec![ns];
+        self.alloc_samples = vec![allocs];
+    }
+
+    /// Records a metric of the benchmark besides its timing, such as a
+    /// throughput or a number of cache misses, shown next to the ns/iter.
+    ///
+    /// See `MetricMap::insert_metric` for the meaning of `noise`. Reporting a
+    /// metric again replaces its previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` or `noise` is NaN or infinite, since such values
+    /// can't be represented in the JSON output.
+    pub fn report_metric(&mut self, name: &str, value: f64, noise: f64) {
+        assert!(
+            value.is_finite() && noise.is_finite(),
+            "metric `{}` must be finite, got {} (+/- {})",
+            name,
+            value,
+            noise
+        );
+        self.metrics.insert_metric(name, value, noise);
     }
 
     pub fn bench<F>(&mut self, mut f: F) -> Option<stats::Summary>
@@ -62,28 +196,82 @@ pub fn bench<F>(&mut self, mut f: F) -> Option<stats::Summary>
 pub struct BenchSamples {
     pub ns_iter_summ: stats::Summary,
     pub mb_s: usize,
+    /// Requested percentiles of the ns/iter samples, as `(percentile, value)` pairs.
+    pub percentiles: Vec<(f64, f64)>,
+    /// Mean number of allocations per iteration, if requested with
+    /// `--bench-count-allocs`.
+    pub allocs_per_iter: Option<f64>,
+    /// Metrics reported by the benchmark with `Bencher::report_metric`.
+    pub metrics: MetricMap,
 }
 
 pub fn fmt_bench_samples(bs: &BenchSamples) -> String {
+    fmt_bench_samples_with_precision(bs, 0)
+}
+
+/// Same as `fmt_bench_samples`, but shows the timings with `precision` decimals
+/// instead of as whole nanoseconds.
+pub fn fmt_bench_samples_with_precision(bs: &BenchSamples, precision: usize) -> String {
     use std::fmt::Write;
     let mut output = String::new();
 
-    let median = bs.ns_iter_summ.median as usize;
-    let deviation = (bs.ns_iter_summ.max - bs.ns_iter_summ.min) as usize;
+    let median = bs.ns_iter_summ.median;
+    let deviation = bs.ns_iter_summ.max - bs.ns_iter_summ.min;
 
     write!(
         output,
         "{:>11} ns/iter (+/- {})",
-        fmt_thousands_sep(median, ','),
-        fmt_thousands_sep(deviation, ',')
+        fmt_thousands_sep_with_precision(median, precision, ','),
+        fmt_thousands_sep_with_precision(deviation, precision, ',')
     )
     .unwrap();
     if bs.mb_s != 0 {
         write!(output, " = {} MB/s", bs.mb_s).unwrap();
     }
+    if let Some(allocs_per_iter) = bs.allocs_per_iter {
+        write!(output, " ({:.2} allocs/iter)", allocs_per_iter).unwrap();
+    }
+    if !bs.metrics.is_empty() {
+        write!(output, " [{}]", bs.metrics.fmt_metrics()).unwrap();
+    }
     output
 }
 
+/// Default value of `--bench-noise-threshold`, in percent.
+pub const DEFAULT_BENCH_NOISE_THRESHOLD: f64 = 10.0;
+
+/// Whether the deviation of the samples of a benchmark exceeds `threshold`
+/// percent of their median, making the median unreliable.
+pub fn is_noisy(summary: &stats::Summary, threshold: f64) -> bool {
+    let deviation = summary.max - summary.min;
+    deviation * 100.0 > summary.median * threshold
+}
+
+pub fn fmt_bench_percentiles(bs: &BenchSamples) -> String {
+    bs.percentiles
+        .iter()
+        .map(|&(pct, value)| format!("p{} = {} ns", pct, fmt_thousands_sep(value as usize, ',')))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Format a number with thousands separators in its integral part and
+// `precision` decimals. A precision of 0 truncates like `fmt_thousands_sep`.
+fn fmt_thousands_sep_with_precision(n: f64, precision: usize, sep: char) -> String {
+    if precision == 0 {
+        return fmt_thousands_sep(n as usize, sep);
+    }
+    let digits = format!("{:.*}", precision, n);
+    match digits.split_once('.') {
+        Some((integral, fractional)) => match integral.parse() {
+            Ok(integral) => format!("{}.{}", fmt_thousands_sep(integral, sep), fractional),
+            Err(_) => digits,
+        },
+        // Not a finite number.
+        None => digits,
+    }
+}
+
 // Format a number with thousands separators
 fn fmt_thousands_sep(mut n: usize, sep: char) -> String {
     use std::fmt::Write;
@@ -108,23 +296,80 @@ fn fmt_thousands_sep(mut n: usize, sep: char) -> String {
     output
 }
 
-fn ns_iter_inner<T, F>(inner: &mut F, k: u64) -> u64
+// Runs `inner` `k` times, returning the time it took in nanoseconds and the
+// number of allocations it made.
+fn ns_iter_inner<T, F>(inner: &mut F, k: u64) -> (u64, u64)
 where
     F: FnMut() -> T,
 {
+    let allocations = allocation_count();
     let start = Instant::now();
     for _ in 0..k {
         black_box(inner());
     }
-    start.elapsed().as_nanos() as u64
+    let ns = start.elapsed().as_nanos() as u64;
+    (ns, allocation_count() - allocations)
+}
+
+// Same as `ns_iter_inner`, but for `Bencher::iter_batched`: only the time and
+// allocations of `routine` are counted, not those of `setup`, nor the dropping
+// of the outputs.
+pub(crate) fn ns_iter_batched<I, O, S, R>(
+    setup: &mut S,
+    routine: &mut R,
+    batch_size: usize,
+    k: u64,
+) -> (u64, u64)
+where
+    S: FnMut() -> I,
+    R: FnMut(I) -> O,
+{
+    let batch_size = cmp::max(1, batch_size) as u64;
+    let (mut ns, mut allocs) = (0, 0);
+    let mut remaining = k;
+    while remaining > 0 {
+        let batch = cmp::min(batch_size, remaining);
+        let mut inputs = (0..batch).map(|_| setup()).collect::<Vec<_>>();
+        let mut outputs = Vec::with_capacity(batch as usize);
+
+        let allocations = allocation_count();
+        let start = Instant::now();
+        for input in inputs.drain(..) {
+            outputs.push(black_box(routine(input)));
+        }
+        ns += start.elapsed().as_nanos() as u64;
+        allocs += allocation_count() - allocations;
+
+        drop(outputs);
+        remaining -= batch;
+    }
+    (ns, allocs)
 }
 
 pub fn iter<T, F>(inner: &mut F) -> stats::Summary
 where
     F: FnMut() -> T,
 {
+    measure(&mut |k| ns_iter_inner(inner, k), Duration::new(0, 0), DEFAULT_BENCH_SAMPLES).0
+}
+
+// Same as `iter`, but also hands back the samples the returned summary was computed from,
+// along with the allocations per iteration measured for each of these samples. `run` runs
+// the given number of iterations, returning the time it took in nanoseconds and the number
+// of allocations they made. It's first run for `warmup` without being measured. Each round
+// of measurements takes `sample_count` samples.
+pub(crate) fn measure<F>(
+    run: &mut F,
+    warmup: Duration,
+    sample_count: usize,
+) -> (stats::Summary, Vec<f64>, Vec<f64>)
+where
+    F: FnMut(u64) -> (u64, u64),
+{
+    warm_up(run, warmup);
+
     // Initial bench run to get ballpark figure.
-    let ns_single = ns_iter_inner(inner, 1);
+    let (ns_single, _) = run(1);
 
     // Try to estimate iter count for 1ms falling back to 1m
     // iterations if first run took < 1ns.
@@ -139,20 +384,22 @@ pub fn iter<T, F>(inner: &mut F) -> stats::Summary
     n = cmp::max(1, n);
 
     let mut total_run = Duration::new(0, 0);
-    let samples: &mut [f64] = &mut [0.0_f64; 50];
+    let samples: &mut [f64] = &mut vec![0.0_f64; sample_count];
+    let alloc_samples: &mut [f64] = &mut vec![0.0_f64; sample_count];
     loop {
         let loop_start = Instant::now();
 
         for p in &mut *samples {
-            *p = ns_iter_inner(inner, n) as f64 / n as f64;
+            *p = run(n).0 as f64 / n as f64;
         }
 
         stats::winsorize(samples, 5.0);
         let summ = stats::Summary::new(samples);
 
-        for p in &mut *samples {
-            let ns = ns_iter_inner(inner, 5 * n);
+        for (p, a) in samples.iter_mut().zip(&mut *alloc_samples) {
+            let (ns, allocs) = run(5 * n);
             *p = ns as f64 / (5 * n) as f64;
+            *a = allocs as f64 / (5 * n) as f64;
         }
 
         stats::winsorize(samples, 5.0);
@@ -166,13 +413,13 @@ pub fn iter<T, F>(inner: &mut F) -> stats::Summary
             && summ.median_abs_dev_pct < 1.0
             && summ.median - summ5.median < summ5.median_abs_dev
         {
-            return summ5;
+            return (summ5, samples.to_vec(), alloc_samples.to_vec());
         }
 
         total_run += loop_run;
         // Longest we ever run for is 3s.
         if total_run > Duration::from_secs(3) {
-            return summ5;
+            return (summ5, samples.to_vec(), alloc_samples.to_vec());
         }
 
         // If we overflow here just return the results so far. We check a
@@ -182,22 +429,54 @@ pub fn iter<T, F>(inner: &mut F) -> stats::Summary
         n = match n.checked_mul(10) {
             Some(_) => n * 2,
             None => {
-                return summ5;
+                return (summ5, samples.to_vec(), alloc_samples.to_vec());
             }
         };
     }
 }
 
+// Runs the iterations of a benchmark for at least `warmup`, discarding their
+// timings, so that the measurements which follow don't include the cold caches
+// and lazy initializations of the first iterations. The iterations are run
+// through `run`, exactly like the measured ones.
+pub(crate) fn warm_up<F>(run: &mut F, warmup: Duration)
+where
+    F: FnMut(u64) -> (u64, u64),
+{
+    let start = Instant::now();
+    let mut n = 1;
+    while start.elapsed() < warmup {
+        let (ns, _) = run(n);
+        // Batches of about 1ms keep the warmup from running much longer than asked.
+        if ns < 1_000_000 {
+            n = n.saturating_mul(2);
+        }
+    }
+}
+
 pub fn benchmark<F>(
     id: TestId,
     desc: TestDesc,
     monitor_ch: Sender<CompletedTest>,
     nocapture: bool,
+    percentiles: &[f64],
+    count_allocs: bool,
+    warmup: Duration,
+    sample_count: usize,
     f: F,
 ) where
     F: FnMut(&mut Bencher),
 {
-    let mut bs = Bencher { mode: BenchMode::Auto, summary: None, bytes: 0 };
+    let mut bs = Bencher {
+        mode: BenchMode::Auto,
+        summary: None,
+        samples: Vec::new(),
+        alloc_samples: Vec::new(),
+        metrics: MetricMap::new(),
+        warmup,
+        sample_count,
+        bytes: 0,
+    };
 
     let data = Arc::new(Mutex::new(Vec::new()));
 
@@ -215,14 +494,28 @@ pub fn benchmark<F>(
             let ns_iter = cmp::max(ns_iter_summ.median as u64, 1);
             let mb_s = bs.bytes * 1000 / ns_iter;
 
-            let bs = BenchSamples { ns_iter_summ, mb_s: mb_s as usize };
+            let percentiles = sample_percentiles(&bs.samples, percentiles);
+            let allocs_per_iter = if count_allocs { Some(bs.alloc_samples.mean()) } else { None };
+            let bs = BenchSamples {
+                ns_iter_summ,
+                mb_s: mb_s as usize,
+                percentiles,
+                allocs_per_iter,
+                metrics: bs.metrics.clone(),
+            };
             TestResult::TrBench(bs)
         }
         Ok(None) => {
             // iter not called, so no data.
             // FIXME: error in this case?
             let samples: &mut [f64] = &mut [0.0_f64; 1];
-            let bs = BenchSamples { ns_iter_summ: stats::Summary::new(samples), mb_s: 0 };
+            let bs = BenchSamples {
+                ns_iter_summ: stats::Summary::new(samples),
+                mb_s: 0,
+                percentiles: sample_percentiles(samples, percentiles),
+                allocs_per_iter: if count_allocs { Some(0.0) } else { None },
+                metrics: bs.metrics.clone(),
+            };
             TestResult::TrBench(bs)
         }
         Err(_) => TestResult::TrFailed,
@@ -237,6 +530,19 @@ pub fn run_once<F>(f: F)
 where
     F: FnMut(&mut Bencher),
 {
-    let mut bs = Bencher { mode: BenchMode::Single, summary: None, bytes: 0 };
+    let mut bs = Bencher {
+        mode: BenchMode::Single,
+        summary: None,
+        samples: Vec::new(),
+        alloc_samples: Vec::new(),
+        metrics: MetricMap::new(),
+        warmup: Duration::new(0, 0),
+        sample_count: DEFAULT_BENCH_SAMPLES,
+        bytes: 0,
+    };
     bs.bench(f);
 }
+
+fn sample_percentiles(samples: &[f64], percentiles: &[f64]) -> Vec<(f64, f64)> {
+    percentiles.iter().copied().zip(samples.percentiles(percentiles)).collect()
+}
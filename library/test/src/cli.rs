@@ -1,42 +1,132 @@
 //! Module converting command-line arguments into test configuration.
 
 use std::env;
+use std::ffi::OsStr;
+use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use super::bench::DEFAULT_BENCH_NOISE_THRESHOLD;
+use super::helpers::concurrency::get_concurrency;
 use super::helpers::isatty;
-use super::options::{ColorConfig, Options, OutputFormat, RunIgnored};
+use super::helpers::shuffle;
+use super::options::{
+    ColorConfig, LogfileFormat, Options, OutputFormat, RunIgnored, TimeoutAction,
+    DEFAULT_MAX_OUTPUT_WARN,
+};
 use super::time::TestTimeOptions;
+use super::types::TestHook;
 
 #[derive(Debug)]
 pub struct TestOpts {
     pub list: bool,
+    pub dump_opts: bool,
+    pub server: bool,
     pub filters: Vec<String>,
     pub filter_exact: bool,
+    /// Whether to read the names of the tests to run from stdin.
+    pub tests_from_stdin: bool,
+    /// Exact names of the tests to run, filled in by `test_main` from stdin
+    /// when `tests_from_stdin` is set.
+    pub selected_tests: Option<Vec<String>>,
+    pub allow_unknown_tests: bool,
     pub force_run_in_process: bool,
+    pub force_subprocess: bool,
     pub exclude_should_panic: bool,
+    pub only_should_panic: bool,
     pub run_ignored: RunIgnored,
     pub run_tests: bool,
     pub bench_benchmarks: bool,
+    /// Filters out everything but the benchmarks, with `--bench-only`.
+    pub bench_only: bool,
     pub logfile: Option<PathBuf>,
+    pub logfile_format: LogfileFormat,
     pub nocapture: bool,
     pub color: ColorConfig,
     pub format: OutputFormat,
+    /// Number of results written by the terse formatter between two flushes of
+    /// its output.
+    pub terse_flush_interval: usize,
     pub test_threads: Option<usize>,
     pub skip: Vec<String>,
+    pub allowed_panic_messages: Vec<String>,
+    pub timeout_action: TimeoutAction,
+    /// Duration after which a running test fails, killing its process if it
+    /// runs in a subprocess.
+    pub kill_on_timeout: Option<Duration>,
+    /// Seed of the order the tests are shuffled in, with `--shuffle`.
+    pub shuffle_seed: Option<u64>,
+    /// Number of times a failing static test is run again before it's reported
+    /// as failed.
+    pub retries: usize,
+    /// Number of times each static test is run, suffixing its name with `#N`.
+    pub repeat: usize,
+    /// Stops dispatching tests after the first failure, with `--fail-fast`.
+    pub fail_fast: bool,
+    pub flakiness_report: Option<PathBuf>,
     pub time_options: Option<TestTimeOptions>,
+    pub bench_percentiles: Vec<f64>,
+    pub bench_count_allocs: bool,
+    /// Reports the peak memory usage of the tests run in a subprocess, on Linux.
+    pub report_memory: bool,
+    /// Time each benchmark runs for, unmeasured, before its measurements.
+    pub bench_warmup: Duration,
+    /// Number of samples taken by each measurement round of the benchmarks.
+    pub bench_samples: usize,
+    pub hash_names: bool,
+    pub hash_map: Option<PathBuf>,
+    /// Called on the thread of each test, before it runs. It isn't called for
+    /// the benchmarks, nor for the tests run in a subprocess (see
+    /// `RunStrategy::SpawnPrimary`), since the subprocess doesn't get the
+    /// options of the harness. If it panics, the test fails without running.
+    pub before_each: Option<TestHook>,
+    /// Called on the thread of each test once it has run, even if it panicked.
+    /// Like `before_each`, it isn't called for the benchmarks nor for the tests
+    /// run in a subprocess. If it panics, the test fails, unless it already did.
+    pub after_each: Option<TestHook>,
     pub options: Options,
 }
 
 impl TestOpts {
+    /// Whether the output should be colored: `--color` takes precedence over the
+    /// `NO_COLOR` environment variable, which takes precedence over detecting
+    /// whether the output is a terminal.
     pub fn use_color(&self) -> bool {
-        match self.color {
-            ColorConfig::AutoColor => !self.nocapture && isatty::stdout_isatty(),
-            ColorConfig::AlwaysColor => true,
-            ColorConfig::NeverColor => false,
-        }
+        let no_color = env::var_os("NO_COLOR");
+        use_color(self.color, no_color.as_deref(), self.nocapture, isatty::stdout_isatty())
+    }
+
+    /// Whether only the failures should be colorized, with `--color=errors-only`.
+    pub fn errors_only_color(&self) -> bool {
+        matches!(self.color, ColorConfig::ErrorsOnlyColor)
+    }
+
+    /// Renders the fully resolved options in a readable form, along with the
+    /// values derived from them and the environment, for `--dump-opts`.
+    pub fn dump(&self) -> String {
+        format!(
+            "{:#?}\neffective test threads: {}\neffective use color: {}\n",
+            self,
+            self.test_threads.unwrap_or_else(get_concurrency),
+            self.use_color()
+        )
     }
 }
 
+/// Percentiles of the benchmark samples reported when `--bench-percentiles` isn't given.
+pub const DEFAULT_BENCH_PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+/// Number of results between two flushes of the terse output when
+/// `--terse-flush-interval` isn't given.
+pub const DEFAULT_TERSE_FLUSH_INTERVAL: usize = 1;
+
+/// Time the benchmarks run for before being measured when `--bench-warmup-ms` isn't given.
+pub const DEFAULT_BENCH_WARMUP: Duration = Duration::from_millis(100);
+
+/// Number of samples taken by each measurement round of the benchmarks when
+/// `--bench-samples` isn't given.
+pub const DEFAULT_BENCH_SAMPLES: usize = 50;
+
 /// Result of parsing the options.
 pub type OptRes = Result<TestOpts, String>;
 /// Result of parsing the option part.
@@ -47,12 +137,47 @@ fn optgroups() -> getopts::Options {
     opts.optflag("", "include-ignored", "Run ignored and not ignored tests")
         .optflag("", "ignored", "Run only ignored tests")
         .optflag("", "force-run-in-process", "Forces tests to run in-process when panic=abort")
+        .optflag(
+            "",
+            "force-subprocess",
+            "Runs each test in its own process, so that a test exiting the process fails alone",
+        )
         .optflag("", "exclude-should-panic", "Excludes tests marked as should_panic")
+        .optflag("", "only-should-panic", "Run only the tests marked as should_panic")
+        .optflag(
+            "",
+            "tests-from-stdin",
+            "Run only the tests whose exact names are read from stdin, one per line",
+        )
+        .optflag(
+            "",
+            "allow-unknown-tests",
+            "Warn instead of failing when a name read by --tests-from-stdin matches no test",
+        )
         .optflag("", "test", "Run tests and not benchmarks")
         .optflag("", "bench", "Run benchmarks instead of tests")
+        .optflag(
+            "",
+            "bench-only",
+            "Run benchmarks, filtering out the tests instead of reporting them as ignored",
+        )
         .optflag("", "list", "List all tests and benchmarks")
+        .optflag("", "dump-opts", "Print the resolved test options and exit")
+        .optflag(
+            "",
+            "server",
+            "Serve the list and run commands read from stdin as line-delimited JSON",
+        )
         .optflag("h", "help", "Display this message")
         .optopt("", "logfile", "Write logs to the specified file", "PATH")
+        .optopt(
+            "",
+            "logfile-format",
+            "Configure the format of the --logfile:
+            plain = one line per test (default);
+            junit = a JUnit XML report",
+            "plain|junit",
+        )
         .optflag(
             "",
             "nocapture",
@@ -79,15 +204,24 @@ fn optgroups() -> getopts::Options {
             "Display one character per test instead of one line. \
              Alias to --format=terse",
         )
+        .optmulti(
+            "",
+            "allow-panic-message",
+            "Count tests failing with a panic message containing SUBSTR as passed \
+             (this flag can be used multiple times)",
+            "SUBSTR",
+        )
         .optflag("", "exact", "Exactly match filters rather than by substring")
         .optopt(
             "",
             "color",
             "Configure coloring of output:
-            auto   = colorize if stdout is a tty and tests are run on serially (default);
+            auto   = colorize if stdout is a tty and tests are run on serially, unless
+                     NO_COLOR is set (default);
             always = always colorize output;
-            never  = never colorize output;",
-            "auto|always|never",
+            never  = never colorize output;
+            errors-only = colorize only the failures [unstable];",
+            "auto|always|never|errors-only",
         )
         .optopt(
             "",
@@ -100,6 +234,142 @@ fn optgroups() -> getopts::Options {
             "pretty|terse|json|junit",
         )
         .optflag("", "show-output", "Show captured stdout of successful tests")
+        .optflag("", "print-rerun", "Show the command line re-running each failed test")
+        .optflag(
+            "",
+            "report-output-size",
+            "Show the tests whose captured output exceeds the --max-output-warn size",
+        )
+        .optopt(
+            "",
+            "max-output-warn",
+            "Size of the captured output above which a test is shown by
+            --report-output-size (default: 1048576)",
+            "BYTES",
+        )
+        .optflag(
+            "",
+            "report-memory",
+            "Show the peak memory usage of the tests run in a subprocess (Linux only)",
+        )
+        .optopt(
+            "",
+            "terse-flush-interval",
+            "Number of results written by the terse output between two flushes.
+            Larger intervals save writes on large suites, but delay the progress
+            in logs (default: 1)",
+            "N",
+        )
+        .optopt(
+            "",
+            "report-slowest",
+            "Show the N slowest tests, with their execution times, at the end of the run",
+            "N",
+        )
+        .optopt(
+            "",
+            "bench-percentiles",
+            "Comma-separated list of percentiles of the benchmark samples to report
+            in the json output (default: 50,90,99)",
+            "PCT,PCT,..",
+        )
+        .optflag(
+            "",
+            "show-percentiles",
+            "Also show the benchmark percentiles in the pretty and terse output",
+        )
+        .optopt(
+            "",
+            "bench-noise-threshold",
+            "Flag the benchmarks whose deviation exceeds PERCENT percent of their
+            median as having a high variance (default: 10)",
+            "PERCENT",
+        )
+        .optflag(
+            "",
+            "bench-count-allocs",
+            "Report the mean number of allocations per iteration of the benchmarks
+            (requires the benchmarks to be built with test::bench::CountingAlloc
+            as their global allocator)",
+        )
+        .optopt(
+            "",
+            "bench-warmup-ms",
+            "Run each benchmark for MS milliseconds before measuring it (default: 100)",
+            "MS",
+        )
+        .optopt(
+            "",
+            "bench-samples",
+            "Take N samples in each measurement round of the benchmarks. More samples
+            give more stable results, but make the benchmarks slower (default: 50)",
+            "N",
+        )
+        .optflag(
+            "",
+            "hash-names",
+            "Replace the test names with stable hashes in the json and junit output.
+            The captured output and panic messages of the tests are kept as is,
+            and may still contain the names",
+        )
+        .optopt(
+            "",
+            "hash-map",
+            "Write the hash of each test name used by --hash-names to the specified file",
+            "PATH",
+        )
+        .optopt(
+            "",
+            "timeout-action",
+            "What to do with a test running for over 60 seconds:
+            report    = report it, and let it finish (default);
+            kill      = kill the process of the test, which fails (only when
+                        the tests run in subprocesses);
+            backtrace = print the backtrace of the test (only on Unix), then
+                        kill it like `kill`",
+            "report|kill|backtrace",
+        )
+        .optopt(
+            "",
+            "kill-on-timeout",
+            "Fail the tests running for over SECS seconds, killing them when they run
+            in subprocesses (replaces the 60 seconds after which tests are reported)",
+            "SECS",
+        )
+        .optflag("", "shuffle", "Run the tests in a random order")
+        .optopt(
+            "",
+            "shuffle-seed",
+            "Run the tests in a random order, picked from SEED (implies --shuffle)",
+            "SEED",
+        )
+        .optopt(
+            "",
+            "retries",
+            "Run a failing test up to N more times, and only report it as failed
+            if every attempt fails",
+            "N",
+        )
+        .optopt("", "repeat", "Run each test N times, suffixing its name with #N", "N")
+        .optflag(
+            "",
+            "fail-fast",
+            "Stop running new tests after the first failure, letting the running ones finish",
+        )
+        .optopt(
+            "",
+            "flakiness-report",
+            "Write the number of runs that passed and failed of each test to the specified
+            JSON file, flagging the tests with mixed outcomes as flaky",
+            "PATH",
+        )
+        .optopt(
+            "",
+            "time-precision",
+            "Number of decimals of the durations shown in the pretty and terse output
+            (default: 3 for test times, 2 for the suite time, 0 for benchmarks)",
+            "N",
+        )
         .optopt(
             "Z",
             "",
@@ -225,50 +495,155 @@ fn parse_opts_impl(matches: getopts::Matches) -> OptRes {
 
     // Unstable flags
     let force_run_in_process = unstable_optflag!(matches, allow_unstable, "force-run-in-process");
+    let force_subprocess = unstable_optflag!(matches, allow_unstable, "force-subprocess");
+    if force_run_in_process && force_subprocess {
+        return Err("The \"force-run-in-process\" and \"force-subprocess\" flags can't be \
+                    used together"
+            .into());
+    }
     let exclude_should_panic = unstable_optflag!(matches, allow_unstable, "exclude-should-panic");
+    let only_should_panic = unstable_optflag!(matches, allow_unstable, "only-should-panic");
+    if exclude_should_panic && only_should_panic {
+        return Err("The \"exclude-should-panic\" and \"only-should-panic\" flags can't be \
+                    used together"
+            .into());
+    }
+    let allow_unknown_tests = unstable_optflag!(matches, allow_unstable, "allow-unknown-tests");
+    let tests_from_stdin = unstable_optflag!(matches, allow_unstable, "tests-from-stdin");
+    let dump_opts = unstable_optflag!(matches, allow_unstable, "dump-opts");
+    let server = unstable_optflag!(matches, allow_unstable, "server");
+    if server && tests_from_stdin {
+        return Err("The \"server\" and \"tests-from-stdin\" flags both read stdin, and can't \
+                    be used together"
+            .into());
+    }
+    let print_rerun = unstable_optflag!(matches, allow_unstable, "print-rerun");
+    let report_output_size = unstable_optflag!(matches, allow_unstable, "report-output-size");
+    let max_output_warn = get_max_output_warn(&matches, allow_unstable)?;
+    let report_slowest = get_report_slowest(&matches, allow_unstable)?;
+    let report_memory = unstable_optflag!(matches, allow_unstable, "report-memory");
+    let terse_flush_interval = get_terse_flush_interval(&matches, allow_unstable)?;
     let time_options = get_time_options(&matches, allow_unstable)?;
+    let show_percentiles = unstable_optflag!(matches, allow_unstable, "show-percentiles");
+    let bench_percentiles = get_bench_percentiles(&matches, allow_unstable)?;
+    let bench_count_allocs = unstable_optflag!(matches, allow_unstable, "bench-count-allocs");
+    let bench_warmup = get_bench_warmup(&matches, allow_unstable)?;
+    let bench_samples = get_bench_samples(&matches, allow_unstable)?;
+    let time_precision = get_time_precision(&matches, allow_unstable)?;
+    let bench_noise_threshold = get_bench_noise_threshold(&matches, allow_unstable)?;
 
     let include_ignored = matches.opt_present("include-ignored");
     let quiet = matches.opt_present("quiet");
     let exact = matches.opt_present("exact");
     let list = matches.opt_present("list");
     let skip = matches.opt_strs("skip");
+    let allowed_panic_messages = get_allowed_panic_messages(&matches, allow_unstable)?;
 
-    let bench_benchmarks = matches.opt_present("bench");
+    let bench_only = unstable_optflag!(matches, allow_unstable, "bench-only");
+    if bench_only && matches.opt_present("test") {
+        return Err("The \"bench-only\" and \"test\" flags can't be used together".into());
+    }
+    let bench_benchmarks = matches.opt_present("bench") || bench_only;
     let run_tests = !bench_benchmarks || matches.opt_present("test");
 
     let logfile = get_log_file(&matches)?;
+    let logfile_format = get_logfile_format(&matches, allow_unstable)?;
+    if logfile.is_none() && matches.opt_present("logfile-format") {
+        return Err("--logfile-format requires --logfile".into());
+    }
+    let hash_names = unstable_optflag!(matches, allow_unstable, "hash-names");
+    let hash_map = get_hash_map(&matches, allow_unstable)?;
     let run_ignored = get_run_ignored(&matches, include_ignored)?;
     let filters = matches.free.clone();
     let nocapture = get_nocapture(&matches)?;
     let test_threads = get_test_threads(&matches)?;
-    let color = get_color_config(&matches)?;
+    let color = get_color_config(&matches, allow_unstable)?;
     let format = get_format(&matches, quiet, allow_unstable)?;
+    let timeout_action = get_timeout_action(&matches, allow_unstable)?;
+    let kill_on_timeout = get_kill_on_timeout(&matches, allow_unstable)?;
+    let shuffle_seed = get_shuffle_seed(&matches, allow_unstable)?;
+    let retries = get_retries(&matches, allow_unstable)?;
+    let repeat = get_repeat(&matches, allow_unstable)?;
+    let fail_fast = unstable_optflag!(matches, allow_unstable, "fail-fast");
+    let flakiness_report = get_flakiness_report(&matches, allow_unstable)?;
 
-    let options = Options::new().display_output(matches.opt_present("show-output"));
+    let options = Options::new()
+        .display_output(matches.opt_present("show-output"))
+        .show_percentiles(show_percentiles)
+        .time_precision(time_precision)
+        .print_rerun(print_rerun)
+        .bench_noise_threshold(bench_noise_threshold)
+        .report_output_size(report_output_size)
+        .max_output_warn(max_output_warn)
+        .report_slowest(report_slowest);
 
     let test_opts = TestOpts {
         list,
+        dump_opts,
+        server,
         filters,
         filter_exact: exact,
+        tests_from_stdin,
+        selected_tests: None,
+        allow_unknown_tests,
         force_run_in_process,
+        force_subprocess,
         exclude_should_panic,
+        only_should_panic,
         run_ignored,
         run_tests,
         bench_benchmarks,
+        bench_only,
         logfile,
+        logfile_format,
         nocapture,
         color,
         format,
+        terse_flush_interval,
         test_threads,
         skip,
+        allowed_panic_messages,
+        timeout_action,
+        kill_on_timeout,
+        shuffle_seed,
+        retries,
+        repeat,
+        fail_fast,
+        flakiness_report,
         time_options,
+        bench_percentiles,
+        bench_count_allocs,
+        report_memory,
+        bench_warmup,
+        bench_samples,
+        hash_names,
+        hash_map,
+        before_each: None,
+        after_each: None,
         options,
     };
 
     Ok(test_opts)
 }
 
+// Whether the output should be colored, given the `--color` option, the value
+// of the `NO_COLOR` environment variable, which only counts when it isn't empty
+// (see https://no-color.org), and whether the output is a terminal.
+pub(crate) fn use_color(
+    color: ColorConfig,
+    no_color: Option<&OsStr>,
+    nocapture: bool,
+    is_tty: bool,
+) -> bool {
+    match color {
+        ColorConfig::AutoColor => {
+            no_color.map_or(true, |value| value.is_empty()) && !nocapture && is_tty
+        }
+        ColorConfig::AlwaysColor | ColorConfig::ErrorsOnlyColor => true,
+        ColorConfig::NeverColor => false,
+    }
+}
+
 // FIXME: Copied from librustc_ast until linkage errors are resolved. Issue #47566
 fn is_nightly() -> bool {
     // Whether this is a feature-staged build, i.e., on the beta or stable channel
@@ -303,6 +678,193 @@ fn get_time_options(
     Ok(options)
 }
 
+fn get_bench_percentiles(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<Vec<f64>> {
+    let percentiles_str = match matches.opt_str("bench-percentiles") {
+        Some(s) => s,
+        None => return Ok(DEFAULT_BENCH_PERCENTILES.to_vec()),
+    };
+    if !allow_unstable {
+        return Err("The \"bench-percentiles\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    percentiles_str
+        .split(',')
+        .map(|pct_str| match pct_str.trim().parse::<f64>() {
+            Ok(pct) if (0.0..=100.0).contains(&pct) => Ok(pct),
+            _ => Err(format!(
+                "argument for --bench-percentiles must be a comma-separated list of \
+                 numbers between 0 and 100 (was {})",
+                percentiles_str
+            )),
+        })
+        .collect()
+}
+
+fn get_bench_warmup(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<Duration> {
+    let warmup_str = match matches.opt_str("bench-warmup-ms") {
+        Some(s) => s,
+        None => return Ok(DEFAULT_BENCH_WARMUP),
+    };
+    if !allow_unstable {
+        return Err("The \"bench-warmup-ms\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    match warmup_str.parse::<u64>() {
+        Ok(ms) => Ok(Duration::from_millis(ms)),
+        Err(e) => Err(format!(
+            "argument for --bench-warmup-ms must be a number of milliseconds (error: {})",
+            e
+        )),
+    }
+}
+
+fn get_bench_samples(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<usize> {
+    let samples_str = match matches.opt_str("bench-samples") {
+        Some(s) => s,
+        None => return Ok(DEFAULT_BENCH_SAMPLES),
+    };
+    if !allow_unstable {
+        return Err("The \"bench-samples\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    match samples_str.parse::<usize>() {
+        Ok(0) => Err("argument for --bench-samples must be at least 1".into()),
+        Ok(samples) => Ok(samples),
+        Err(e) => {
+            Err(format!("argument for --bench-samples must be a number of samples (error: {})", e))
+        }
+    }
+}
+
+fn get_bench_noise_threshold(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<f64> {
+    let threshold_str = match matches.opt_str("bench-noise-threshold") {
+        Some(s) => s,
+        None => return Ok(DEFAULT_BENCH_NOISE_THRESHOLD),
+    };
+    if !allow_unstable {
+        return Err("The \"bench-noise-threshold\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    match threshold_str.parse::<f64>() {
+        Ok(threshold) if threshold >= 0.0 => Ok(threshold),
+        _ => Err(format!(
+            "argument for --bench-noise-threshold must be a non-negative percentage (was {})",
+            threshold_str
+        )),
+    }
+}
+
+fn get_max_output_warn(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<usize> {
+    let max_output_warn_str = match matches.opt_str("max-output-warn") {
+        Some(s) => s,
+        None => return Ok(DEFAULT_MAX_OUTPUT_WARN),
+    };
+    if !allow_unstable {
+        return Err("The \"max-output-warn\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    match max_output_warn_str.parse::<usize>() {
+        Ok(max_output_warn) => Ok(max_output_warn),
+        Err(e) => Err(format!(
+            "argument for --max-output-warn must be a non-negative number (error: {})",
+            e
+        )),
+    }
+}
+
+fn get_report_slowest(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<Option<usize>> {
+    let report_slowest = match matches.opt_str("report-slowest") {
+        Some(n_str) => match n_str.parse::<usize>() {
+            Ok(0) => return Err("argument for --report-slowest must not be 0".to_string()),
+            Ok(n) => n,
+            Err(e) => {
+                return Err(format!(
+                    "argument for --report-slowest must be a number > 0 (error: {})",
+                    e
+                ));
+            }
+        },
+        None => return Ok(None),
+    };
+    if !allow_unstable {
+        return Err("The \"report-slowest\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    Ok(Some(report_slowest))
+}
+
+fn get_terse_flush_interval(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<usize> {
+    let interval_str = match matches.opt_str("terse-flush-interval") {
+        Some(s) => s,
+        None => return Ok(DEFAULT_TERSE_FLUSH_INTERVAL),
+    };
+    if !allow_unstable {
+        return Err("The \"terse-flush-interval\" flag is only accepted on the nightly \
+                    compiler with -Z unstable-options"
+            .into());
+    }
+
+    match interval_str.parse::<usize>() {
+        Ok(0) => Err("argument for --terse-flush-interval must not be 0".to_string()),
+        Ok(interval) => Ok(interval),
+        Err(e) => {
+            Err(format!("argument for --terse-flush-interval must be a number > 0 (error: {})", e))
+        }
+    }
+}
+
+fn get_time_precision(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<Option<usize>> {
+    let precision_str = match matches.opt_str("time-precision") {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    if !allow_unstable {
+        return Err("The \"time-precision\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    match precision_str.parse::<usize>() {
+        Ok(precision) => Ok(Some(precision)),
+        Err(e) => Err(format!(
+            "argument for --time-precision must be a non-negative number (error: {})",
+            e
+        )),
+    }
+}
+
+fn get_allowed_panic_messages(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<Vec<String>> {
+    let allowed_panic_messages = matches.opt_strs("allow-panic-message");
+    if !allowed_panic_messages.is_empty() && !allow_unstable {
+        return Err("The \"allow-panic-message\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    Ok(allowed_panic_messages)
+}
+
 fn get_test_threads(matches: &getopts::Matches) -> OptPartRes<Option<usize>> {
     let test_threads = match matches.opt_str("test-threads") {
         Some(n_str) => match n_str.parse::<usize>() {
@@ -355,15 +917,48 @@ fn get_format(
     Ok(format)
 }
 
-fn get_color_config(matches: &getopts::Matches) -> OptPartRes<ColorConfig> {
+fn get_timeout_action(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<TimeoutAction> {
+    let timeout_action = match matches.opt_str("timeout-action").as_deref() {
+        None => return Ok(TimeoutAction::Report),
+        Some("report") => TimeoutAction::Report,
+        Some("kill") => TimeoutAction::Kill,
+        Some("backtrace") => TimeoutAction::Backtrace,
+        Some(v) => {
+            return Err(format!(
+                "argument for --timeout-action must be report, kill or backtrace (was {})",
+                v
+            ));
+        }
+    };
+    if !allow_unstable {
+        return Err("The \"timeout-action\" flag is only accepted on the nightly compiler with \
+                    -Z unstable-options"
+            .into());
+    }
+
+    Ok(timeout_action)
+}
+
+fn get_color_config(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<ColorConfig> {
     let color = match matches.opt_str("color").as_deref() {
         Some("auto") | None => ColorConfig::AutoColor,
         Some("always") => ColorConfig::AlwaysColor,
         Some("never") => ColorConfig::NeverColor,
+        Some("errors-only") => {
+            if !allow_unstable {
+                return Err("The \"errors-only\" color mode is only accepted on the nightly \
+                            compiler"
+                    .into());
+            }
+            ColorConfig::ErrorsOnlyColor
+        }
 
         Some(v) => {
             return Err(format!(
-                "argument for --color must be auto, always, or never (was \
+                "argument for --color must be auto, always, never or errors-only (was \
                  {})",
                 v
             ));
@@ -419,8 +1014,158 @@ fn get_allow_unstable(matches: &getopts::Matches) -> OptPartRes<bool> {
     Ok(allow_unstable)
 }
 
+/// Reads the newline-separated test names given to `--tests-from-stdin`,
+/// skipping empty lines.
+pub(crate) fn read_test_names<R: io::BufRead>(reader: R) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let name = line.strip_suffix('\r').unwrap_or(&line);
+        if !name.is_empty() {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
 fn get_log_file(matches: &getopts::Matches) -> OptPartRes<Option<PathBuf>> {
     let logfile = matches.opt_str("logfile").map(|s| PathBuf::from(&s));
 
     Ok(logfile)
 }
+
+fn get_logfile_format(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<LogfileFormat> {
+    let logfile_format = match matches.opt_str("logfile-format").as_deref() {
+        Some("plain") | None => LogfileFormat::Plain,
+        Some("junit") => {
+            if !allow_unstable {
+                return Err("The \"junit\" logfile format is only accepted on the nightly \
+                            compiler with -Z unstable-options"
+                    .into());
+            }
+            LogfileFormat::Junit
+        }
+        Some(v) => {
+            return Err(format!(
+                "argument for --logfile-format must be plain or junit (was {})",
+                v
+            ));
+        }
+    };
+
+    Ok(logfile_format)
+}
+
+fn get_hash_map(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<Option<PathBuf>> {
+    let hash_map = matches.opt_str("hash-map").map(|s| PathBuf::from(&s));
+    if hash_map.is_some() && !allow_unstable {
+        return Err("The \"hash-map\" flag is only accepted on the nightly compiler with \
+                    -Z unstable-options"
+            .into());
+    }
+
+    Ok(hash_map)
+}
+
+fn get_kill_on_timeout(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<Option<Duration>> {
+    let kill_on_timeout = match matches.opt_str("kill-on-timeout") {
+        Some(secs_str) => match secs_str.parse::<u64>() {
+            Ok(0) => return Err("argument for --kill-on-timeout must not be 0".to_string()),
+            Ok(secs) => Duration::from_secs(secs),
+            Err(e) => {
+                return Err(format!(
+                    "argument for --kill-on-timeout must be a number of seconds > 0 (error: {})",
+                    e
+                ));
+            }
+        },
+        None => return Ok(None),
+    };
+    if !allow_unstable {
+        return Err("The \"kill-on-timeout\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    Ok(Some(kill_on_timeout))
+}
+
+fn get_shuffle_seed(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<Option<u64>> {
+    let shuffle = unstable_optflag!(matches, allow_unstable, "shuffle");
+    let shuffle_seed = match matches.opt_str("shuffle-seed") {
+        Some(seed_str) => match seed_str.parse::<u64>() {
+            Ok(seed) => seed,
+            Err(e) => {
+                return Err(format!("argument for --shuffle-seed must be a number (error: {})", e));
+            }
+        },
+        None if shuffle => shuffle::get_shuffle_seed(),
+        None => return Ok(None),
+    };
+    if !allow_unstable {
+        return Err("The \"shuffle-seed\" flag is only accepted on the nightly compiler with \
+                    -Z unstable-options"
+            .into());
+    }
+
+    Ok(Some(shuffle_seed))
+}
+
+fn get_retries(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<usize> {
+    let retries = match matches.opt_str("retries") {
+        Some(n_str) => match n_str.parse::<usize>() {
+            Ok(n) => n,
+            Err(e) => {
+                return Err(format!("argument for --retries must be a number (error: {})", e));
+            }
+        },
+        None => return Ok(0),
+    };
+    if !allow_unstable {
+        return Err("The \"retries\" flag is only accepted on the nightly compiler with \
+                    -Z unstable-options"
+            .into());
+    }
+
+    Ok(retries)
+}
+
+fn get_repeat(matches: &getopts::Matches, allow_unstable: bool) -> OptPartRes<usize> {
+    let repeat = match matches.opt_str("repeat") {
+        Some(n_str) => match n_str.parse::<usize>() {
+            Ok(0) => return Err("argument for --repeat must not be 0".to_string()),
+            Ok(n) => n,
+            Err(e) => {
+                return Err(format!("argument for --repeat must be a number > 0 (error: {})", e));
+            }
+        },
+        None => return Ok(1),
+    };
+    if !allow_unstable {
+        return Err("The \"repeat\" flag is only accepted on the nightly compiler with \
+                    -Z unstable-options"
+            .into());
+    }
+
+    Ok(repeat)
+}
+
+fn get_flakiness_report(
+    matches: &getopts::Matches,
+    allow_unstable: bool,
+) -> OptPartRes<Option<PathBuf>> {
+    let flakiness_report = matches.opt_str("flakiness-report").map(|s| PathBuf::from(&s));
+    if flakiness_report.is_some() && !allow_unstable {
+        return Err("The \"flakiness-report\" flag is only accepted on the nightly compiler \
+                    with -Z unstable-options"
+            .into());
+    }
+
+    Ok(flakiness_report)
+}
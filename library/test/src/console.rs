@@ -1,27 +1,34 @@
 //! Module providing interface for running tests in the console.
 
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io;
 use std::io::prelude::Write;
-use std::time::Instant;
+use std::io::BufWriter;
+use std::path::Path;
 
 use super::{
-    bench::fmt_bench_samples,
+    bench::fmt_bench_samples_with_precision,
     cli::TestOpts,
     event::{CompletedTest, TestEvent},
     filter_tests,
-    formatters::{JsonFormatter, JunitFormatter, OutputFormatter, PrettyFormatter, TerseFormatter},
+    formatters::{
+        hashed_name, joined_output, location_fields, EscapedString, JsonFormatter, JunitFormatter,
+        OutputFormatter, PrettyFormatter, TerseFormatter,
+    },
     helpers::{concurrency::get_concurrency, metrics::MetricMap},
-    options::{Options, OutputFormat},
+    options::{LogfileFormat, Options, OutputFormat, RunIgnored, ShouldPanic},
+    repeated_test_name,
+    reporter::Reporter,
     run_tests,
     test_result::TestResult,
-    time::{TestExecTime, TestSuiteExecTime},
+    time::{self, TestExecTime, TestSuiteExecTime},
     types::{NamePadding, TestDesc, TestDescAndFn},
 };
 
 /// Generic wrapper over stdout.
 pub enum OutputLocation<T> {
-    Pretty(Box<term::StdoutTerminal>),
+    Pretty(Box<dyn term::Terminal<Output = T> + Send>),
     Raw(T),
 }
 
@@ -50,19 +57,31 @@ pub struct ConsoleTestState {
     pub allowed_fail: usize,
     pub filtered_out: usize,
     pub measured: usize,
+    /// Number of tests left out of a run aborted by `--fail-fast`.
+    pub not_run: usize,
     pub exec_time: Option<TestSuiteExecTime>,
     pub metrics: MetricMap,
     pub failures: Vec<(TestDesc, Vec<u8>)>,
     pub not_failures: Vec<(TestDesc, Vec<u8>)>,
     pub time_failures: Vec<(TestDesc, Vec<u8>)>,
+    /// Tests whose captured output exceeds `--max-output-warn`, with its size.
+    pub large_outputs: Vec<(TestDesc, usize)>,
+    /// Execution times of the tests, for `--report-slowest`.
+    pub exec_times: Vec<(TestDesc, TestExecTime)>,
+    /// Outcomes of each test, by name, gathering the runs of `--repeat`.
+    /// Only recorded for `--flakiness-report`.
+    pub outcomes: BTreeMap<String, RunOutcomes>,
+    pub record_outcomes: bool,
+    pub run_ignored: RunIgnored,
     pub options: Options,
 }
 
 impl ConsoleTestState {
     pub fn new(opts: &TestOpts) -> io::Result<ConsoleTestState> {
-        let log_out = match opts.logfile {
-            Some(ref path) => Some(File::create(path)?),
-            None => None,
+        let log_out = match (&opts.logfile, opts.logfile_format) {
+            (Some(path), LogfileFormat::Plain) => Some(File::create(path)?),
+            // The other formats are written by a formatter of their own.
+            _ => None,
         };
 
         Ok(ConsoleTestState {
@@ -74,11 +93,17 @@ pub fn new(opts: &TestOpts) -> io::Result<ConsoleTestState> {
             allowed_fail: 0,
             filtered_out: 0,
             measured: 0,
+            not_run: 0,
             exec_time: None,
             metrics: MetricMap::new(),
             failures: Vec::new(),
             not_failures: Vec::new(),
             time_failures: Vec::new(),
+            large_outputs: Vec::new(),
+            exec_times: Vec::new(),
+            outcomes: BTreeMap::new(),
+            record_outcomes: opts.flakiness_report.is_some(),
+            run_ignored: opts.run_ignored,
             options: opts.options,
         })
     }
@@ -104,6 +129,7 @@ pub fn write_log_result(
         result: &TestResult,
         exec_time: Option<&TestExecTime>,
     ) -> io::Result<()> {
+        let time_precision = self.options.time_precision;
         self.write_log(|| {
             format!(
                 "{} {}",
@@ -112,15 +138,18 @@ pub fn write_log_result(
                     TestResult::TrFailed => "failed".to_owned(),
                     TestResult::TrFailedMsg(ref msg) => format!("failed: {}", msg),
                     TestResult::TrIgnored => "ignored".to_owned(),
+                    TestResult::TrIgnoredMsg(ref msg) => format!("ignored: {}", msg),
                     TestResult::TrAllowedFail => "failed (allowed)".to_owned(),
-                    TestResult::TrBench(ref bs) => fmt_bench_samples(bs),
+                    TestResult::TrBench(ref bs) => {
+                        fmt_bench_samples_with_precision(bs, time_precision.unwrap_or(0))
+                    }
                     TestResult::TrTimedFail => "failed (time limit exceeded)".to_owned(),
                 },
                 test.name,
             )
         })?;
         if let Some(exec_time) = exec_time {
-            self.write_log(|| format!(" <{}>", exec_time))?;
+            self.write_log(|| format!(" <{}>", time::fmt_time(exec_time, time_precision)))?;
         }
         self.write_log(|| "\n")
     }
@@ -130,6 +159,20 @@ fn current_test_count(&self) -> usize {
     }
 }
 
+/// Number of runs of a test which passed and failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunOutcomes {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl RunOutcomes {
+    /// Whether the test both passed and failed over the runs.
+    pub fn is_flaky(&self) -> bool {
+        self.passed > 0 && self.failed > 0
+    }
+}
+
 // List the tests to console, and optionally to logfile. Filters are honored.
 pub fn list_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> io::Result<()> {
     let mut output = match term::stdout() {
@@ -137,7 +180,17 @@ pub fn list_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> io::Res
         Some(t) => OutputLocation::Pretty(t),
     };
 
+    list_tests(opts, tests, &mut output)
+}
+
+// Lists the tests to `output` in the format of `opts`, and optionally to logfile.
+pub(crate) fn list_tests(
+    opts: &TestOpts,
+    tests: Vec<TestDescAndFn>,
+    output: &mut dyn Write,
+) -> io::Result<()> {
     let quiet = opts.format == OutputFormat::Terse;
+    let json = opts.format == OutputFormat::Json;
     let mut st = ConsoleTestState::new(opts)?;
 
     let mut ntest = 0;
@@ -146,20 +199,46 @@ pub fn list_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> io::Res
     for test in filter_tests(&opts, tests) {
         use crate::TestFn::*;
 
-        let TestDescAndFn { desc: TestDesc { name, .. }, testfn } = test;
+        let location = location_fields(&test.desc);
+        let TestDescAndFn { desc: TestDesc { name, ignore, should_panic, .. }, testfn } = test;
 
-        let fntype = match testfn {
+        // The kind of the test is named like in the JSON test events.
+        let (fntype, kind) = match testfn {
             StaticTestFn(..) | DynTestFn(..) => {
                 ntest += 1;
-                "test"
+                ("test", "test")
             }
             StaticBenchFn(..) | DynBenchFn(..) => {
                 nbench += 1;
-                "benchmark"
+                ("benchmark", "bench")
             }
         };
 
-        writeln!(output, "{}: {}", name, fntype)?;
+        if json {
+            let json_name = if opts.hash_names {
+                hashed_name(name.as_slice())
+            } else {
+                name.as_slice().to_string()
+            };
+            let should_panic = match should_panic {
+                ShouldPanic::No => "no",
+                ShouldPanic::Yes => "yes",
+                ShouldPanic::YesWithMessage(_) => "yes_with_message",
+                ShouldPanic::YesWithRegex(_) => "yes_with_regex",
+            };
+            writeln!(
+                output,
+                "{{ \"type\": \"test\", \"name\": \"{}\", \"ignored\": {}, \"kind\": \"{}\", \
+                 \"should_panic\": \"{}\"{} }}",
+                EscapedString(json_name),
+                ignore,
+                kind,
+                should_panic,
+                location
+            )?;
+        } else {
+            writeln!(output, "{}: {}", name, fntype)?;
+        }
         st.write_log(|| format!("{} {}\n", fntype, name))?;
     }
 
@@ -170,7 +249,8 @@ fn plural(count: u32, s: &str) -> String {
         }
     }
 
-    if !quiet {
+    // The JSON listing is made of the tests only.
+    if !quiet && !json {
         if ntest != 0 || nbench != 0 {
             writeln!(output)?;
         }
@@ -184,13 +264,26 @@ fn plural(count: u32, s: &str) -> String {
 // Updates `ConsoleTestState` depending on result of the test execution.
 fn handle_test_result(st: &mut ConsoleTestState, completed_test: CompletedTest) {
     let test = completed_test.desc;
-    let stdout = completed_test.stdout;
+    // The output to stderr is shown after the output to stdout, in its own section.
+    let stdout = joined_output(&test.name, &completed_test.stdout, &completed_test.stderr);
+    if st.options.report_output_size && stdout.len() > st.options.max_output_warn {
+        st.large_outputs.push((test.clone(), stdout.len()));
+    }
+    // Benchmarks aren't ranked, their timings being reported per iteration.
+    if let (Some(_), Some(exec_time)) = (st.options.report_slowest, &completed_test.exec_time) {
+        if !matches!(completed_test.result, TestResult::TrBench(_)) {
+            st.exec_times.push((test.clone(), exec_time.clone()));
+        }
+    }
+    if st.record_outcomes {
+        record_outcome(st, &test, &completed_test.result);
+    }
     match completed_test.result {
         TestResult::TrOk => {
             st.passed += 1;
             st.not_failures.push((test, stdout));
         }
-        TestResult::TrIgnored => st.ignored += 1,
+        TestResult::TrIgnored | TestResult::TrIgnoredMsg(_) => st.ignored += 1,
         TestResult::TrAllowedFail => st.allowed_fail += 1,
         TestResult::TrBench(bs) => {
             st.metrics.insert_metric(
@@ -219,10 +312,11 @@ fn handle_test_result(st: &mut ConsoleTestState, completed_test: CompletedTest)
 
 // Handler for events that occur during test execution.
 // It is provided as a callback to the `run_tests` function.
-fn on_test_event(
+pub(crate) fn on_test_event(
     event: &TestEvent,
     st: &mut ConsoleTestState,
     out: &mut dyn OutputFormatter,
+    reporters: &mut [Box<dyn Reporter>],
 ) -> io::Result<()> {
     match (*event).clone() {
         TestEvent::TeFiltered(ref filtered_tests) => {
@@ -232,26 +326,86 @@ fn on_test_event(
         TestEvent::TeFilteredOut(filtered_out) => {
             st.filtered_out = filtered_out;
         }
+        TestEvent::TeShuffleSeed(seed) => out.write_shuffle_seed(seed)?,
+        TestEvent::TeAborted(not_run) => {
+            st.not_run = not_run;
+        }
+        TestEvent::TeFinished(exec_time) => {
+            st.exec_time = exec_time;
+        }
         TestEvent::TeWait(ref test) => out.write_test_start(test)?,
         TestEvent::TeTimeout(ref test) => out.write_timeout(test)?,
         TestEvent::TeResult(completed_test) => {
             let test = &completed_test.desc;
             let result = &completed_test.result;
             let exec_time = &completed_test.exec_time;
+            let attempt = completed_test.attempt;
+            let max_rss = completed_test.max_rss;
             let stdout = &completed_test.stdout;
+            let stderr = &completed_test.stderr;
 
             st.write_log_result(test, result, exec_time.as_ref())?;
-            out.write_result(test, result, exec_time.as_ref(), &*stdout, st)?;
+            out.write_result(
+                test,
+                result,
+                exec_time.as_ref(),
+                attempt,
+                max_rss,
+                stdout,
+                stderr,
+                st,
+            )?;
             handle_test_result(st, completed_test);
         }
     }
 
+    for reporter in reporters {
+        if let Err(e) = reporter.on_event(event) {
+            eprintln!("warning: test reporter failed: {}", e);
+        }
+    }
+
     Ok(())
 }
 
+// Writes the events of the run to the formatter of a `--logfile` which isn't plain.
+fn write_log_event(
+    event: &TestEvent,
+    st: &ConsoleTestState,
+    log: &mut dyn OutputFormatter,
+) -> io::Result<()> {
+    match *event {
+        TestEvent::TeFiltered(ref filtered_tests) => log.write_run_start(filtered_tests.len()),
+        TestEvent::TeFilteredOut(_) | TestEvent::TeAborted(_) | TestEvent::TeFinished(_) => Ok(()),
+        TestEvent::TeShuffleSeed(seed) => log.write_shuffle_seed(seed),
+        TestEvent::TeWait(ref test) => log.write_test_start(test),
+        TestEvent::TeTimeout(ref test) => log.write_timeout(test),
+        TestEvent::TeResult(ref completed_test) => log.write_result(
+            &completed_test.desc,
+            &completed_test.result,
+            completed_test.exec_time.as_ref(),
+            completed_test.attempt,
+            completed_test.max_rss,
+            &completed_test.stdout,
+            &completed_test.stderr,
+            st,
+        ),
+    }
+}
+
 /// A simple console test runner.
 /// Runs provided tests reporting process and results to the stdout.
 pub fn run_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> io::Result<bool> {
+    run_tests_console_with_reporters(opts, tests, &mut [])
+}
+
+/// Runs the tests like `run_tests_console`, and also hands each test event to
+/// `reporters`, after the output formatter.
+pub fn run_tests_console_with_reporters(
+    opts: &TestOpts,
+    tests: Vec<TestDescAndFn>,
+    reporters: &mut [Box<dyn Reporter>],
+) -> io::Result<bool> {
     let output = match term::stdout() {
         None => OutputLocation::Raw(io::stdout()),
         Some(t) => OutputLocation::Pretty(t),
@@ -269,32 +423,104 @@ pub fn run_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> io::Resu
         OutputFormat::Pretty => Box::new(PrettyFormatter::new(
             output,
             opts.use_color(),
+            opts.errors_only_color(),
+            opts.options.color_theme,
             max_name_len,
             is_multithreaded,
             opts.time_options,
         )),
-        OutputFormat::Terse => {
-            Box::new(TerseFormatter::new(output, opts.use_color(), max_name_len, is_multithreaded))
+        OutputFormat::Terse => Box::new(TerseFormatter::new(
+            output,
+            opts.use_color(),
+            opts.errors_only_color(),
+            opts.options.color_theme,
+            max_name_len,
+            is_multithreaded,
+            opts.terse_flush_interval,
+        )),
+        OutputFormat::Json => Box::new(JsonFormatter::new(output, opts.hash_names)),
+        OutputFormat::Junit => Box::new(JunitFormatter::new(output, opts.hash_names)),
+    };
+    let mut log: Option<Box<dyn OutputFormatter>> = match (&opts.logfile, opts.logfile_format) {
+        (Some(path), LogfileFormat::Junit) => {
+            let file = OutputLocation::Raw(File::create(path)?);
+            Some(Box::new(JunitFormatter::new(file, opts.hash_names)))
         }
-        OutputFormat::Json => Box::new(JsonFormatter::new(output)),
-        OutputFormat::Junit => Box::new(JunitFormatter::new(output)),
+        _ => None,
     };
     let mut st = ConsoleTestState::new(opts)?;
 
-    // Prevent the usage of `Instant` in some cases:
-    // - It's currently not supported for wasm targets.
-    // - We disable it for miri because it's not available when isolation is enabled.
-    let is_instant_supported = !cfg!(target_arch = "wasm32") && !cfg!(miri);
+    if let Some(ref path) = opts.hash_map {
+        write_hash_map(path, &tests)?;
+    }
+
+    run_tests(opts, tests, |x| {
+        if let Some(ref mut log) = log {
+            write_log_event(&x, &st, &mut **log)?;
+        }
+        on_test_event(&x, &mut st, &mut *out, reporters)
+    })?;
 
-    let start_time = is_instant_supported.then(Instant::now);
-    run_tests(opts, tests, |x| on_test_event(&x, &mut st, &mut *out))?;
-    st.exec_time = start_time.map(|t| TestSuiteExecTime(t.elapsed()));
+    assert!(st.current_test_count() + st.not_run == st.total);
 
-    assert!(st.current_test_count() == st.total);
+    if let Some(ref path) = opts.flakiness_report {
+        fs::write(path, flakiness_report(&st))?;
+    }
+
+    if let Some(ref mut log) = log {
+        log.write_run_finish(&st)?;
+    }
 
     out.write_run_finish(&st)
 }
 
+// Writes the hash of the name of each test, as used by `--hash-names`, followed by the name.
+fn write_hash_map(path: &Path, tests: &[TestDescAndFn]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    for test in tests {
+        let name = test.desc.name.as_slice();
+        writeln!(file, "{} {}", hashed_name(name), name)?;
+    }
+    file.flush()
+}
+
+fn record_outcome(st: &mut ConsoleTestState, test: &TestDesc, result: &TestResult) {
+    let passed = match result {
+        TestResult::TrOk => true,
+        TestResult::TrFailed
+        | TestResult::TrFailedMsg(_)
+        | TestResult::TrAllowedFail
+        | TestResult::TrTimedFail => false,
+        TestResult::TrIgnored | TestResult::TrIgnoredMsg(_) | TestResult::TrBench(_) => return,
+    };
+    let name = repeated_test_name(test.name.as_slice()).to_string();
+    let outcomes = st.outcomes.entry(name).or_default();
+    if passed {
+        outcomes.passed += 1;
+    } else {
+        outcomes.failed += 1;
+    }
+}
+
+/// Renders the outcomes of the tests as JSON for `--flakiness-report`, one
+/// line per test.
+pub(crate) fn flakiness_report(st: &ConsoleTestState) -> String {
+    let tests = st
+        .outcomes
+        .iter()
+        .map(|(name, outcomes)| {
+            format!(
+                r#"  {{ "name": "{}", "passed": {}, "failed": {}, "flaky": {} }}"#,
+                EscapedString(name),
+                outcomes.passed,
+                outcomes.failed,
+                outcomes.is_flaky()
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{ \"tests\": [\n{}\n] }}\n", tests.join(",\n"))
+}
+
 // Calculates padding for given test description.
 fn len_if_padded(t: &TestDescAndFn) -> usize {
     match t.testfn.padding() {
@@ -2,9 +2,18 @@
 //! during tests execution process.
 
 use super::test_result::TestResult;
-use super::time::TestExecTime;
+use super::time::{TestExecTime, TestSuiteExecTime};
 use super::types::{TestDesc, TestId};
 
+/// Attempt of a test which can be retried, with `--retries`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempt {
+    /// Number of the attempt, starting at 1.
+    pub number: usize,
+    /// Number of attempts allowed for the test.
+    pub max: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct CompletedTest {
     pub id: TestId,
@@ -12,6 +21,14 @@ pub struct CompletedTest {
     pub result: TestResult,
     pub exec_time: Option<TestExecTime>,
     pub stdout: Vec<u8>,
+    /// The output of the test to stderr, captured apart from `stdout` when
+    /// possible.
+    pub stderr: Vec<u8>,
+    /// The attempt which gave the result, for tests which can be retried.
+    pub attempt: Option<Attempt>,
+    /// Peak resident set size of the test, in bytes, with `--report-memory`.
+    /// It's only known for the tests run in a subprocess on Linux.
+    pub max_rss: Option<u64>,
 }
 
 impl CompletedTest {
@@ -22,7 +39,16 @@ pub fn new(
         exec_time: Option<TestExecTime>,
         stdout: Vec<u8>,
     ) -> Self {
-        Self { id, desc, result, exec_time, stdout }
+        Self {
+            id,
+            desc,
+            result,
+            exec_time,
+            stdout,
+            stderr: Vec::new(),
+            attempt: None,
+            max_rss: None,
+        }
     }
 }
 
@@ -33,4 +59,12 @@ pub enum TestEvent {
     TeResult(CompletedTest),
     TeTimeout(TestDesc),
     TeFilteredOut(usize),
+    /// The tests were shuffled with this seed, with `--shuffle`.
+    TeShuffleSeed(u64),
+    /// The run stopped after a failure, with `--fail-fast`, leaving this many
+    /// tests not run.
+    TeAborted(usize),
+    /// The run is over, after this much wall-clock time, unless `Instant` is
+    /// unsupported on the target.
+    TeFinished(Option<TestSuiteExecTime>),
 }
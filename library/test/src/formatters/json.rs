@@ -1,8 +1,10 @@
 use std::{borrow::Cow, io, io::prelude::Write};
 
-use super::OutputFormatter;
+use super::{reported_name, OutputFormatter};
 use crate::{
+    bench::is_noisy,
     console::{ConsoleTestState, OutputLocation},
+    event::Attempt,
     test_result::TestResult,
     time,
     types::TestDesc,
@@ -10,11 +12,12 @@
 
 pub(crate) struct JsonFormatter<T> {
     out: OutputLocation<T>,
+    hash_names: bool,
 }
 
 impl<T: Write> JsonFormatter<T> {
-    pub fn new(out: OutputLocation<T>) -> Self {
-        Self { out }
+    pub fn new(out: OutputLocation<T>, hash_names: bool) -> Self {
+        Self { out, hash_names }
     }
 
     fn writeln_message(&mut self, s: &str) -> io::Result<()> {
@@ -36,7 +39,10 @@ fn write_event(
         name: &str,
         evt: &str,
         exec_time: Option<&time::TestExecTime>,
+        attempt: Option<Attempt>,
+        max_rss: Option<u64>,
         stdout: Option<Cow<'_, str>>,
+        stderr: Option<Cow<'_, str>>,
         extra: Option<&str>,
     ) -> io::Result<()> {
         // A doc test's name includes a filename which must be escaped for correct json.
@@ -49,9 +55,21 @@ fn write_event(
         if let Some(exec_time) = exec_time {
             self.write_message(&*format!(r#", "exec_time": {}"#, exec_time.0.as_secs_f64()))?;
         }
+        if let Some(attempt) = attempt {
+            self.write_message(&*format!(
+                r#", "attempt": {}, "max_attempts": {}"#,
+                attempt.number, attempt.max
+            ))?;
+        }
+        if let Some(max_rss) = max_rss {
+            self.write_message(&*format!(r#", "max_rss": {}"#, max_rss))?;
+        }
         if let Some(stdout) = stdout {
             self.write_message(&*format!(r#", "stdout": "{}""#, EscapedString(stdout)))?;
         }
+        if let Some(stderr) = stderr {
+            self.write_message(&*format!(r#", "stderr": "{}""#, EscapedString(stderr)))?;
+        }
         if let Some(extra) = extra {
             self.write_message(&*format!(r#", {}"#, extra))?;
         }
@@ -67,10 +85,18 @@ fn write_run_start(&mut self, test_count: usize) -> io::Result<()> {
         ))
     }
 
+    fn write_shuffle_seed(&mut self, seed: u64) -> io::Result<()> {
+        self.writeln_message(&*format!(
+            r#"{{ "type": "suite", "event": "shuffled", "shuffle_seed": {} }}"#,
+            seed
+        ))
+    }
+
     fn write_test_start(&mut self, desc: &TestDesc) -> io::Result<()> {
         self.writeln_message(&*format!(
-            r#"{{ "type": "test", "event": "started", "name": "{}" }}"#,
-            EscapedString(desc.name.as_slice())
+            r#"{{ "type": "test", "event": "started", "name": "{}"{} }}"#,
+            EscapedString(reported_name(desc, self.hash_names)),
+            location_fields(desc)
         ))
     }
 
@@ -79,52 +105,77 @@ fn write_result(
         desc: &TestDesc,
         result: &TestResult,
         exec_time: Option<&time::TestExecTime>,
+        attempt: Option<Attempt>,
+        max_rss: Option<u64>,
         stdout: &[u8],
+        stderr: &[u8],
         state: &ConsoleTestState,
     ) -> io::Result<()> {
-        let display_stdout = state.options.display_output || *result != TestResult::TrOk;
-        let stdout = if display_stdout && !stdout.is_empty() {
-            Some(String::from_utf8_lossy(stdout))
-        } else {
-            None
-        };
+        fn captured(output: &[u8], display: bool) -> Option<Cow<'_, str>> {
+            if display && !output.is_empty() { Some(String::from_utf8_lossy(output)) } else { None }
+        }
+        let display_output = state.options.display_output || *result != TestResult::TrOk;
+        let stdout = captured(stdout, display_output);
+        let stderr = captured(stderr, display_output);
+        let name = reported_name(desc, self.hash_names);
         match *result {
-            TestResult::TrOk => {
-                self.write_event("test", desc.name.as_slice(), "ok", exec_time, stdout, None)
-            }
+            TestResult::TrOk => self.write_event(
+                "test", &name, "ok", exec_time, attempt, max_rss, stdout, stderr, None,
+            ),
 
-            TestResult::TrFailed => {
-                self.write_event("test", desc.name.as_slice(), "failed", exec_time, stdout, None)
-            }
+            TestResult::TrFailed => self.write_event(
+                "test", &name, "failed", exec_time, attempt, max_rss, stdout, stderr, None,
+            ),
 
             TestResult::TrTimedFail => self.write_event(
                 "test",
-                desc.name.as_slice(),
+                &name,
                 "failed",
                 exec_time,
+                attempt,
+                max_rss,
                 stdout,
+                stderr,
                 Some(r#""reason": "time limit exceeded""#),
             ),
 
             TestResult::TrFailedMsg(ref m) => self.write_event(
                 "test",
-                desc.name.as_slice(),
+                &name,
                 "failed",
                 exec_time,
+                attempt,
+                max_rss,
                 stdout,
+                stderr,
                 Some(&*format!(r#""message": "{}""#, EscapedString(m))),
             ),
 
-            TestResult::TrIgnored => {
-                self.write_event("test", desc.name.as_slice(), "ignored", exec_time, stdout, None)
-            }
+            TestResult::TrIgnored => self.write_event(
+                "test", &name, "ignored", exec_time, attempt, max_rss, stdout, stderr, None,
+            ),
+
+            TestResult::TrIgnoredMsg(ref m) => self.write_event(
+                "test",
+                &name,
+                "ignored",
+                exec_time,
+                attempt,
+                max_rss,
+                stdout,
+                stderr,
+                Some(&*format!(r#""message": "{}""#, EscapedString(m))),
+            ),
 
             TestResult::TrAllowedFail => self.write_event(
                 "test",
-                desc.name.as_slice(),
+                &name,
                 "allowed_failure",
                 exec_time,
+                attempt,
+                max_rss,
                 stdout,
+                stderr,
                 None,
             ),
 
@@ -138,15 +189,56 @@ fn write_result(
                     format!(r#", "mib_per_second": {}"#, bs.mb_s)
                 };
 
+                let percentiles = if bs.percentiles.is_empty() {
+                    String::new()
+                } else {
+                    let values = bs
+                        .percentiles
+                        .iter()
+                        .map(|(pct, value)| format!(r#""p{}": {}"#, pct, value))
+                        .collect::<Vec<_>>();
+                    format!(r#", "percentiles": {{ {} }}"#, values.join(", "))
+                };
+
+                let allocs = match bs.allocs_per_iter {
+                    Some(allocs_per_iter) => format!(r#", "allocs_per_iter": {}"#, allocs_per_iter),
+                    None => String::new(),
+                };
+
+                let metrics = if bs.metrics.is_empty() {
+                    String::new()
+                } else {
+                    let values = bs
+                        .metrics
+                        .iter()
+                        .map(|(name, metric)| {
+                            format!(
+                                r#""{}": {{ "value": {}, "noise": {} }}"#,
+                                EscapedString(name),
+                                metric.value(),
+                                metric.noise()
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    format!(r#", "metrics": {{ {} }}"#, values.join(", "))
+                };
+
+                let noisy = is_noisy(&bs.ns_iter_summ, state.options.bench_noise_threshold);
+
                 let line = format!(
                     "{{ \"type\": \"bench\", \
                      \"name\": \"{}\", \
                      \"median\": {}, \
-                     \"deviation\": {}{} }}",
-                    EscapedString(desc.name.as_slice()),
+                     \"deviation\": {}, \
+                     \"noisy\": {}{}{}{}{} }}",
+                    EscapedString(&*name),
                     median,
                     deviation,
-                    mbps
+                    noisy,
+                    mbps,
+                    percentiles,
+                    allocs,
+                    metrics
                 );
 
                 self.writeln_message(&*line)
@@ -157,7 +249,7 @@ fn write_result(
     fn write_timeout(&mut self, desc: &TestDesc) -> io::Result<()> {
         self.writeln_message(&*format!(
             r#"{{ "type": "test", "event": "timeout", "name": "{}" }}"#,
-            EscapedString(desc.name.as_slice())
+            EscapedString(reported_name(desc, self.hash_names))
         ))
     }
 
@@ -180,6 +272,10 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
             state.filtered_out,
         ))?;
 
+        if state.not_run > 0 {
+            self.write_message(&*format!(", \"not_run\": {}", state.not_run))?;
+        }
+
         if let Some(ref exec_time) = state.exec_time {
             let time_str = format!(", \"exec_time\": {}", exec_time.0.as_secs_f64());
             self.write_message(&time_str)?;
@@ -191,9 +287,28 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
     }
 }
 
+/// Returns the fields giving the location of a test in its source file, for
+/// editors to show it, or nothing if it's unknown.
+pub(crate) fn location_fields(desc: &TestDesc) -> String {
+    match desc.source_location() {
+        Some(location) => format!(
+            concat!(
+                r#", "source_file": "{}", "start_line": {}, "start_col": {}, "#,
+                r#""end_line": {}, "end_col": {}"#
+            ),
+            EscapedString(location.source_file),
+            location.start_line,
+            location.start_col,
+            location.end_line,
+            location.end_col
+        ),
+        None => String::new(),
+    }
+}
+
 /// A formatting utility used to print strings with characters in need of escaping.
 /// Base code taken form `libserialize::json::escape_str`
-struct EscapedString<S: AsRef<str>>(S);
+pub(crate) struct EscapedString<S: AsRef<str>>(pub S);
 
 impl<S: AsRef<str>> std::fmt::Display for EscapedString<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> ::std::fmt::Result {
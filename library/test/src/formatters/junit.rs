@@ -1,9 +1,11 @@
+use std::fmt;
 use std::io::{self, prelude::Write};
 use std::time::Duration;
 
-use super::OutputFormatter;
+use super::{hashed_name, joined_output, OutputFormatter};
 use crate::{
     console::{ConsoleTestState, OutputLocation},
+    event::Attempt,
     test_result::TestResult,
     time,
     types::{TestDesc, TestType},
@@ -11,12 +13,14 @@
 
 pub struct JunitFormatter<T> {
     out: OutputLocation<T>,
-    results: Vec<(TestDesc, TestResult, Duration)>,
+    results: Vec<(TestDesc, TestResult, Duration, Vec<u8>)>,
+    hash_names: bool,
+    shuffle_seed: Option<u64>,
 }
 
 impl<T: Write> JunitFormatter<T> {
-    pub fn new(out: OutputLocation<T>) -> Self {
-        Self { out, results: Vec::new() }
+    pub fn new(out: OutputLocation<T>, hash_names: bool) -> Self {
+        Self { out, results: Vec::new(), hash_names, shuffle_seed: None }
     }
 
     fn write_message(&mut self, s: &str) -> io::Result<()> {
@@ -24,6 +28,30 @@ fn write_message(&mut self, s: &str) -> io::Result<()> {
 
         self.out.write_all(s.as_ref())
     }
+
+    fn write_failure(
+        &mut self,
+        kind: &str,
+        message: Option<&str>,
+        stdout: &[u8],
+    ) -> io::Result<()> {
+        let message = match message {
+            Some(m) => format!(" message=\"{}\"", EscapedXml(m)),
+            None => String::new(),
+        };
+        if stdout.is_empty() {
+            self.write_message(&*format!("<failure{} type=\"{}\"/>", message, kind))
+        } else {
+            // The captured output holds the panic message of the test.
+            let stdout = String::from_utf8_lossy(stdout);
+            self.write_message(&*format!(
+                "<failure{} type=\"{}\">{}</failure>",
+                message,
+                kind,
+                EscapedXml(&stdout)
+            ))
+        }
+    }
 }
 
 impl<T: Write> OutputFormatter for JunitFormatter<T> {
@@ -32,6 +60,12 @@ fn write_run_start(&mut self, _test_count: usize) -> io::Result<()> {
         self.write_message(&"<?xml version=\"1.0\" encoding=\"UTF-8\"?>")
     }
 
+    fn write_shuffle_seed(&mut self, seed: u64) -> io::Result<()> {
+        // The seed is written as a property of the testsuite, on run finish.
+        self.shuffle_seed = Some(seed);
+        Ok(())
+    }
+
     fn write_test_start(&mut self, _desc: &TestDesc) -> io::Result<()> {
         // We do not output anything on test start.
         Ok(())
@@ -47,14 +81,18 @@ fn write_result(
         desc: &TestDesc,
         result: &TestResult,
         exec_time: Option<&time::TestExecTime>,
-        _stdout: &[u8],
+        _attempt: Option<Attempt>,
+        _max_rss: Option<u64>,
+        stdout: &[u8],
+        stderr: &[u8],
         _state: &ConsoleTestState,
     ) -> io::Result<()> {
         // Because the testsuit node holds some of the information as attributes, we can't write it
         // until all of the tests has ran. Instead of writting every result as they come in, we add
         // them to a Vec and write them all at once when run is complete.
         let duration = exec_time.map(|t| t.0.clone()).unwrap_or_default();
-        self.results.push((desc.clone(), result.clone(), duration));
+        let output = joined_output(&desc.name, stdout, stderr);
+        self.results.push((desc.clone(), result.clone(), duration, output));
         Ok(())
     }
     fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
@@ -69,10 +107,30 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
              >",
             state.failed, state.total, state.ignored
         ))?;
-        for (desc, result, duration) in std::mem::replace(&mut self.results, Vec::new()) {
-            let (class_name, test_name) = parse_class_name(&desc);
+        if let Some(seed) = self.shuffle_seed {
+            self.write_message(&*format!(
+                "<properties><property name=\"shuffle_seed\" value=\"{}\" /></properties>",
+                seed
+            ))?;
+        }
+        for (desc, result, duration, stdout) in std::mem::replace(&mut self.results, Vec::new()) {
+            let (class_name, test_name) = if self.hash_names {
+                // The class name is derived from the test name, so it can't be kept either.
+                (String::from("hashed"), hashed_name(desc.name.as_slice()))
+            } else {
+                parse_class_name(&desc)
+            };
+            let (class_name, test_name) = (EscapedXml(class_name), EscapedXml(test_name));
             match result {
-                TestResult::TrIgnored => { /* no-op */ }
+                TestResult::TrIgnored | TestResult::TrIgnoredMsg(_) => {
+                    self.write_message(&*format!(
+                        "<testcase classname=\"{}\" \
+                         name=\"{}\" time=\"0\">",
+                        class_name, test_name
+                    ))?;
+                    self.write_message("<skipped/>")?;
+                    self.write_message("</testcase>")?;
+                }
                 TestResult::TrFailed => {
                     self.write_message(&*format!(
                         "<testcase classname=\"{}\" \
@@ -81,7 +139,7 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
                         test_name,
                         duration.as_secs_f64()
                     ))?;
-                    self.write_message("<failure type=\"assert\"/>")?;
+                    self.write_failure("assert", None, &stdout)?;
                     self.write_message("</testcase>")?;
                 }
 
@@ -93,7 +151,7 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
                         test_name,
                         duration.as_secs_f64()
                     ))?;
-                    self.write_message(&*format!("<failure message=\"{}\" type=\"assert\"/>", m))?;
+                    self.write_failure("assert", Some(m), &stdout)?;
                     self.write_message("</testcase>")?;
                 }
 
@@ -105,7 +163,7 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
                         test_name,
                         duration.as_secs_f64()
                     ))?;
-                    self.write_message("<failure type=\"timeout\"/>")?;
+                    self.write_failure("timeout", None, &stdout)?;
                     self.write_message("</testcase>")?;
                 }
 
@@ -137,6 +195,44 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
     }
 }
 
+/// Escapes a string for the text and attribute values of the XML document,
+/// keeping it on a single line.
+pub(crate) struct EscapedXml<S: AsRef<str>>(pub S);
+
+impl<S: AsRef<str>> fmt::Display for EscapedXml<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut start = 0;
+
+        for (i, byte) in self.0.as_ref().bytes().enumerate() {
+            let escaped = match byte {
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                b'&' => "&amp;",
+                b'"' => "&quot;",
+                b'\'' => "&apos;",
+                b'\t' => "&#9;",
+                b'\n' => "&#10;",
+                b'\r' => "&#13;",
+                _ => continue,
+            };
+
+            if start < i {
+                f.write_str(&self.0.as_ref()[start..i])?;
+            }
+
+            f.write_str(escaped)?;
+
+            start = i + 1;
+        }
+
+        if start != self.0.as_ref().len() {
+            f.write_str(&self.0.as_ref()[start..])?;
+        }
+
+        Ok(())
+    }
+}
+
 fn parse_class_name(desc: &TestDesc) -> (String, String) {
     match desc.test_type {
         TestType::UnitTest => parse_class_name_unit(desc),
@@ -1,7 +1,9 @@
-use std::{io, io::prelude::Write};
+use std::{borrow::Cow, env, io, io::prelude::Write};
 
 use crate::{
     console::ConsoleTestState,
+    event::Attempt,
+    options::{ColorTheme, RunIgnored},
     test_result::TestResult,
     time,
     types::{TestDesc, TestName},
@@ -12,13 +14,43 @@
 mod pretty;
 mod terse;
 
-pub(crate) use self::json::JsonFormatter;
+pub(crate) use self::json::{location_fields, EscapedString, JsonFormatter};
 pub(crate) use self::junit::JunitFormatter;
 pub(crate) use self::pretty::PrettyFormatter;
 pub(crate) use self::terse::TerseFormatter;
 
+/// What a colorized part of the pretty or terse output reports, which gives its
+/// color in the `ColorTheme`, and whether it's colorized with
+/// `--color=errors-only`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Highlight {
+    Ok,
+    Failed,
+    Ignored,
+    Warning,
+    Bench,
+}
+
+impl Highlight {
+    pub(crate) fn color(self, theme: &ColorTheme) -> term::color::Color {
+        match self {
+            Highlight::Ok => theme.ok,
+            Highlight::Failed => theme.failed,
+            Highlight::Ignored => theme.ignored,
+            Highlight::Warning => theme.warning,
+            Highlight::Bench => theme.bench,
+        }
+    }
+
+    /// Whether this is colorized, when colors are used at all.
+    pub(crate) fn is_colorized(self, errors_only_color: bool) -> bool {
+        !errors_only_color || self == Highlight::Failed
+    }
+}
+
 pub(crate) trait OutputFormatter {
     fn write_run_start(&mut self, test_count: usize) -> io::Result<()>;
+    fn write_shuffle_seed(&mut self, seed: u64) -> io::Result<()>;
     fn write_test_start(&mut self, desc: &TestDesc) -> io::Result<()>;
     fn write_timeout(&mut self, desc: &TestDesc) -> io::Result<()>;
     fn write_result(
@@ -26,12 +58,56 @@ fn write_result(
         desc: &TestDesc,
         result: &TestResult,
         exec_time: Option<&time::TestExecTime>,
+        attempt: Option<Attempt>,
+        max_rss: Option<u64>,
         stdout: &[u8],
+        stderr: &[u8],
         state: &ConsoleTestState,
     ) -> io::Result<()>;
     fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool>;
 }
 
+/// The name of the test as written by the machine-readable formatters: with
+/// `--hash-names`, this is a hash of the name instead of the name itself.
+pub(crate) fn reported_name(desc: &TestDesc, hash_names: bool) -> Cow<'_, str> {
+    if hash_names {
+        Cow::Owned(hashed_name(desc.name.as_slice()))
+    } else {
+        Cow::Borrowed(desc.name.as_slice())
+    }
+}
+
+/// Hashes a test name for `--hash-names`.
+///
+/// This is the 64-bit FNV-1a hash of the name, which doesn't depend on the
+/// platform or on the Rust release, so that a test keeps the same hash across
+/// runs and toolchains.
+pub(crate) fn hashed_name(name: &str) -> String {
+    let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Describes the attempts of a test which had to be retried, if any.
+pub(crate) fn attempt_note(result: &TestResult, attempt: Option<Attempt>) -> Option<String> {
+    let attempt = attempt.filter(|attempt| attempt.number > 1)?;
+    match *result {
+        TestResult::TrOk => {
+            Some(format!("flaky, passed on attempt {}/{}", attempt.number, attempt.max))
+        }
+        TestResult::TrFailed | TestResult::TrFailedMsg(_) => {
+            Some(format!("failed all {} attempts", attempt.max))
+        }
+        _ => None,
+    }
+}
+
+/// Formats the peak memory usage of a test, given in bytes, in mebibytes.
+pub(crate) fn fmt_max_rss(max_rss: u64) -> String {
+    format!("{:.1} MiB", max_rss as f64 / (1024.0 * 1024.0))
+}
+
 pub(crate) fn write_stderr_delimiter(test_output: &mut Vec<u8>, test_name: &TestName) {
     match test_output.last() {
         Some(b'\n') => (),
@@ -40,3 +116,85 @@ pub(crate) fn write_stderr_delimiter(test_output: &mut Vec<u8>, test_name: &Test
     }
     writeln!(test_output, "---- {} stderr ----", test_name).unwrap();
 }
+
+/// Joins the output of a test to stdout and to stderr, the latter after a
+/// delimiter, for the formatters presenting them in a single section.
+pub(crate) fn joined_output(test_name: &TestName, stdout: &[u8], stderr: &[u8]) -> Vec<u8> {
+    let mut output = stdout.to_vec();
+    if !stderr.is_empty() {
+        write_stderr_delimiter(&mut output, test_name);
+        output.extend_from_slice(stderr);
+    }
+    output
+}
+
+/// Lists the tests whose captured output exceeds `--max-output-warn`, from the
+/// largest output to the smallest, for `--report-output-size`.
+pub(crate) fn large_outputs(state: &ConsoleTestState) -> String {
+    let mut large_outputs = state.large_outputs.iter().collect::<Vec<_>>();
+    large_outputs.sort_by(|(desc1, size1), (desc2, size2)| {
+        size2.cmp(size1).then_with(|| desc1.name.as_slice().cmp(desc2.name.as_slice()))
+    });
+
+    let mut report =
+        format!("\ntests with large output (over {} bytes):\n", state.options.max_output_warn);
+    for (desc, size) in large_outputs {
+        report.push_str(&format!("    {}: {} bytes\n", desc.name, size));
+    }
+    report
+}
+
+/// Lists the `--report-slowest` slowest tests, from the slowest one.
+pub(crate) fn slowest_tests(state: &ConsoleTestState) -> String {
+    let mut exec_times = state.exec_times.iter().collect::<Vec<_>>();
+    exec_times.sort_by(|(desc1, time1), (desc2, time2)| {
+        time2.0.cmp(&time1.0).then_with(|| desc1.name.as_slice().cmp(desc2.name.as_slice()))
+    });
+
+    let count = state.options.report_slowest.unwrap_or(0);
+    let mut report = String::from("\nslowest tests:\n");
+    for (desc, exec_time) in exec_times.into_iter().take(count) {
+        let time_str = time::fmt_time(exec_time, state.options.time_precision);
+        report.push_str(&format!("    {}: {}\n", desc.name, time_str));
+    }
+    report
+}
+
+/// Lists, for every failed test, the command line that runs only that test
+/// again, for `--print-rerun`.
+pub(crate) fn rerun_commands(state: &ConsoleTestState) -> String {
+    let binary = env::args().next().unwrap_or_default();
+    let mut names = state
+        .failures
+        .iter()
+        .chain(&state.time_failures)
+        .map(|(desc, _)| desc.name.as_slice())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let mut commands = String::from("\nto re-run a failed test:\n");
+    for name in names {
+        commands.push_str(&format!("    {}\n", rerun_command(&binary, name, state.run_ignored)));
+    }
+    commands
+}
+
+pub(crate) fn rerun_command(binary: &str, test_name: &str, run_ignored: RunIgnored) -> String {
+    let binary = if binary.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=+".contains(c)) {
+        binary.to_string()
+    } else {
+        shell_quote(binary)
+    };
+    let mut command = format!("{} --exact {} --nocapture", binary, shell_quote(test_name));
+    match run_ignored {
+        RunIgnored::Only => command.push_str(" --ignored"),
+        RunIgnored::Yes => command.push_str(" --include-ignored"),
+        RunIgnored::No => {}
+    }
+    command
+}
+
+// Quotes `s` for a POSIX shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
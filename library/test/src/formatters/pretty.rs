@@ -1,9 +1,14 @@
 use std::{io, io::prelude::Write};
 
-use super::OutputFormatter;
+use super::{
+    attempt_note, fmt_max_rss, large_outputs, rerun_commands, slowest_tests, Highlight,
+    OutputFormatter,
+};
 use crate::{
-    bench::fmt_bench_samples,
+    bench::{fmt_bench_percentiles, fmt_bench_samples_with_precision, is_noisy},
     console::{ConsoleTestState, OutputLocation},
+    event::Attempt,
+    options::ColorTheme,
     test_result::TestResult,
     time,
     types::TestDesc,
@@ -12,6 +17,8 @@
 pub(crate) struct PrettyFormatter<T> {
     out: OutputLocation<T>,
     use_color: bool,
+    errors_only_color: bool,
+    theme: ColorTheme,
     time_options: Option<time::TestTimeOptions>,
 
     /// Number of columns to fill when aligning names
@@ -24,11 +31,21 @@ impl<T: Write> PrettyFormatter<T> {
     pub fn new(
         out: OutputLocation<T>,
         use_color: bool,
+        errors_only_color: bool,
+        theme: ColorTheme,
         max_name_len: usize,
         is_multithreaded: bool,
         time_options: Option<time::TestTimeOptions>,
     ) -> Self {
-        PrettyFormatter { out, use_color, max_name_len, is_multithreaded, time_options }
+        PrettyFormatter {
+            out,
+            use_color,
+            errors_only_color,
+            theme,
+            max_name_len,
+            is_multithreaded,
+            time_options,
+        }
     }
 
     #[cfg(test)]
@@ -37,45 +54,43 @@ pub fn output_location(&self) -> &OutputLocation<T> {
     }
 
     pub fn write_ok(&mut self) -> io::Result<()> {
-        self.write_short_result("ok", term::color::GREEN)
+        self.write_short_result("ok", Highlight::Ok)
     }
 
     pub fn write_failed(&mut self) -> io::Result<()> {
-        self.write_short_result("FAILED", term::color::RED)
+        self.write_short_result("FAILED", Highlight::Failed)
     }
 
     pub fn write_ignored(&mut self) -> io::Result<()> {
-        self.write_short_result("ignored", term::color::YELLOW)
+        self.write_short_result("ignored", Highlight::Ignored)
     }
 
     pub fn write_allowed_fail(&mut self) -> io::Result<()> {
-        self.write_short_result("FAILED (allowed)", term::color::YELLOW)
+        self.write_short_result("FAILED (allowed)", Highlight::Warning)
     }
 
     pub fn write_time_failed(&mut self) -> io::Result<()> {
-        self.write_short_result("FAILED (time limit exceeded)", term::color::RED)
+        self.write_short_result("FAILED (time limit exceeded)", Highlight::Failed)
     }
 
     pub fn write_bench(&mut self) -> io::Result<()> {
-        self.write_pretty("bench", term::color::CYAN)
+        self.write_pretty("bench", Highlight::Bench)
     }
 
-    pub fn write_short_result(
-        &mut self,
-        result: &str,
-        color: term::color::Color,
-    ) -> io::Result<()> {
-        self.write_pretty(result, color)
+    pub fn write_short_result(&mut self, result: &str, highlight: Highlight) -> io::Result<()> {
+        self.write_pretty(result, highlight)
     }
 
-    pub fn write_pretty(&mut self, word: &str, color: term::color::Color) -> io::Result<()> {
+    pub fn write_pretty(&mut self, word: &str, highlight: Highlight) -> io::Result<()> {
         match self.out {
             OutputLocation::Pretty(ref mut term) => {
-                if self.use_color {
-                    term.fg(color)?;
+                // With `--color=errors-only`, only the failures are colorized.
+                let colorize = self.use_color && highlight.is_colorized(self.errors_only_color);
+                if colorize {
+                    term.fg(highlight.color(&self.theme))?;
                 }
                 term.write_all(word.as_bytes())?;
-                if self.use_color {
+                if colorize {
                     term.reset()?;
                 }
                 term.flush()
@@ -97,15 +112,16 @@ fn write_time(
         &mut self,
         desc: &TestDesc,
         exec_time: Option<&time::TestExecTime>,
+        precision: Option<usize>,
     ) -> io::Result<()> {
         if let (Some(opts), Some(time)) = (self.time_options, exec_time) {
-            let time_str = format!(" <{}>", time);
+            let time_str = format!(" <{}>", time::fmt_time(time, precision));
 
-            let color = if opts.colored {
+            let highlight = if opts.colored {
                 if opts.is_critical(desc, time) {
-                    Some(term::color::RED)
+                    Some(Highlight::Failed)
                 } else if opts.is_warn(desc, time) {
-                    Some(term::color::YELLOW)
+                    Some(Highlight::Warning)
                 } else {
                     None
                 }
@@ -113,8 +129,8 @@ fn write_time(
                 None
             };
 
-            match color {
-                Some(color) => self.write_pretty(&time_str, color)?,
+            match highlight {
+                Some(highlight) => self.write_pretty(&time_str, highlight)?,
                 None => self.write_plain(&time_str)?,
             }
         }
@@ -132,20 +148,19 @@ fn write_results(
         self.write_plain(&results_out_str)?;
 
         let mut results = Vec::new();
-        let mut stdouts = String::new();
+        let mut wrote_stdouts = false;
         for &(ref f, ref stdout) in inputs {
             results.push(f.name.to_string());
             if !stdout.is_empty() {
-                stdouts.push_str(&format!("---- {} stdout ----\n", f.name));
-                let output = String::from_utf8_lossy(stdout);
-                stdouts.push_str(&output);
-                stdouts.push('\n');
+                if !wrote_stdouts {
+                    self.write_plain("\n")?;
+                    wrote_stdouts = true;
+                }
+                self.write_plain(&format!("---- {} stdout ----\n", f.name))?;
+                self.write_output(&String::from_utf8_lossy(stdout))?;
+                self.write_plain("\n")?;
             }
         }
-        if !stdouts.is_empty() {
-            self.write_plain("\n")?;
-            self.write_plain(&stdouts)?;
-        }
 
         self.write_plain(&results_out_str)?;
         results.sort();
@@ -155,6 +170,31 @@ fn write_results(
         Ok(())
     }
 
+    // Writes the captured output of a test, with the values of its failed
+    // `assert_eq!` or `assert_ne!` highlighted when colorizing.
+    fn write_output(&mut self, output: &str) -> io::Result<()> {
+        let lines = match assertion_lines(output) {
+            Some(lines) if self.use_color => lines,
+            _ => return self.write_plain(output),
+        };
+        // Both values are highlighted as failures, so that they are also
+        // colorized with `--color=errors-only`.
+        for (line, is_value) in lines {
+            if !is_value {
+                self.write_plain(line)?;
+                continue;
+            }
+            match line.strip_suffix('\n') {
+                Some(line) => {
+                    self.write_pretty(line, Highlight::Failed)?;
+                    self.write_plain("\n")?;
+                }
+                None => self.write_pretty(line, Highlight::Failed)?,
+            }
+        }
+        Ok(())
+    }
+
     pub fn write_successes(&mut self, state: &ConsoleTestState) -> io::Result<()> {
         self.write_results(&state.not_failures, "successes")
     }
@@ -185,6 +225,10 @@ fn write_run_start(&mut self, test_count: usize) -> io::Result<()> {
         self.write_plain(&format!("\nrunning {} {}\n", test_count, noun))
     }
 
+    fn write_shuffle_seed(&mut self, seed: u64) -> io::Result<()> {
+        self.write_plain(&format!("shuffle seed: {}\n", seed))
+    }
+
     fn write_test_start(&mut self, desc: &TestDesc) -> io::Result<()> {
         // When running tests concurrently, we should not print
         // the test's name as the result will be mis-aligned.
@@ -202,8 +246,11 @@ fn write_result(
         desc: &TestDesc,
         result: &TestResult,
         exec_time: Option<&time::TestExecTime>,
+        attempt: Option<Attempt>,
+        max_rss: Option<u64>,
+        _: &[u8],
         _: &[u8],
-        _: &ConsoleTestState,
+        state: &ConsoleTestState,
     ) -> io::Result<()> {
         if self.is_multithreaded {
             self.write_test_name(desc)?;
@@ -213,15 +260,36 @@ fn write_result(
             TestResult::TrOk => self.write_ok()?,
             TestResult::TrFailed | TestResult::TrFailedMsg(_) => self.write_failed()?,
             TestResult::TrIgnored => self.write_ignored()?,
+            TestResult::TrIgnoredMsg(ref msg) => {
+                self.write_ignored()?;
+                self.write_plain(&format!(" ({})", msg))?;
+            }
             TestResult::TrAllowedFail => self.write_allowed_fail()?,
             TestResult::TrBench(ref bs) => {
                 self.write_bench()?;
-                self.write_plain(&format!(": {}", fmt_bench_samples(bs)))?;
+                let precision = state.options.time_precision.unwrap_or(0);
+                self.write_plain(&format!(
+                    ": {}",
+                    fmt_bench_samples_with_precision(bs, precision)
+                ))?;
+                if state.options.show_percentiles && !bs.percentiles.is_empty() {
+                    self.write_plain(&format!(" ({})", fmt_bench_percentiles(bs)))?;
+                }
+                if is_noisy(&bs.ns_iter_summ, state.options.bench_noise_threshold) {
+                    self.write_pretty(" \u{26a0} high variance", Highlight::Warning)?;
+                }
             }
             TestResult::TrTimedFail => self.write_time_failed()?,
         }
 
-        self.write_time(desc, exec_time)?;
+        if let Some(note) = attempt_note(result, attempt) {
+            self.write_plain(&format!(" ({})", note))?;
+        }
+
+        self.write_time(desc, exec_time, state.options.time_precision)?;
+        if let Some(max_rss) = max_rss {
+            self.write_plain(&format!(" (max rss: {})", fmt_max_rss(max_rss)))?;
+        }
         self.write_plain("\n")
     }
 
@@ -246,15 +314,27 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
             if !state.time_failures.is_empty() {
                 self.write_time_failures(state)?;
             }
+
+            if state.options.print_rerun {
+                self.write_plain(&rerun_commands(state))?;
+            }
+        }
+
+        if !state.large_outputs.is_empty() {
+            self.write_plain(&large_outputs(state))?;
+        }
+
+        if !state.exec_times.is_empty() {
+            self.write_plain(&slowest_tests(state))?;
         }
 
         self.write_plain("\ntest result: ")?;
 
         if success {
             // There's no parallelism at this point so it's safe to use color
-            self.write_pretty("ok", term::color::GREEN)?;
+            self.write_pretty("ok", Highlight::Ok)?;
         } else {
-            self.write_pretty("FAILED", term::color::RED)?;
+            self.write_pretty("FAILED", Highlight::Failed)?;
         }
 
         let s = if state.allowed_fail > 0 {
@@ -276,8 +356,17 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
 
         self.write_plain(&s)?;
 
+        if state.not_run > 0 {
+            let aborted_str =
+                format!("; aborted after first failure; {} tests not run", state.not_run);
+            self.write_plain(&aborted_str)?;
+        }
+
         if let Some(ref exec_time) = state.exec_time {
-            let time_str = format!("; finished in {}", exec_time);
+            let time_str = format!(
+                "; finished in {}",
+                time::fmt_time(exec_time, state.options.time_precision)
+            );
             self.write_plain(&time_str)?;
         }
 
@@ -286,3 +375,53 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
         Ok(success)
     }
 }
+
+/// Splits `output` in lines, keeping their `\n`, and marks the lines showing
+/// the `left` and `right` values of the failed `assert_eq!` and `assert_ne!` in
+/// it, as printed by their panic message:
+///
+/// ```text
+/// thread 'tests::it_works' panicked at 'assertion failed: `(left == right)`
+///   left: `1`,
+///  right: `2`', src/lib.rs:4:9
+/// ```
+///
+/// Returns `None` if no such panic message is found.
+fn assertion_lines(output: &str) -> Option<Vec<(&str, bool)>> {
+    let mut lines: Vec<_> = output.split_inclusive('\n').map(|line| (line, false)).collect();
+    let mut found = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let header = lines[i].0;
+        i += 1;
+        if !header.contains("assertion failed: `(left == right)`")
+            && !header.contains("assertion failed: `(left != right)`")
+        {
+            continue;
+        }
+        match lines.get(i) {
+            Some((line, _)) if line.starts_with("  left: `") => {}
+            _ => continue,
+        }
+        // The left value may span several lines, up to the right one, which
+        // ends with the closing backtick, followed by the location of the
+        // panic or by its custom message.
+        let right = match lines[i..].iter().position(|(line, _)| line.starts_with(" right: `")) {
+            Some(right) => i + right,
+            None => continue,
+        };
+        let end = match lines[right..]
+            .iter()
+            .position(|(line, _)| line.contains("`', ") || line.contains("`: "))
+        {
+            Some(end) => right + end,
+            None => continue,
+        };
+        for (_, is_value) in &mut lines[i..=end] {
+            *is_value = true;
+        }
+        found = true;
+        i = end + 1;
+    }
+    found.then(|| lines)
+}
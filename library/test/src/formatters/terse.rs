@@ -1,9 +1,11 @@
 use std::{io, io::prelude::Write};
 
-use super::OutputFormatter;
+use super::{large_outputs, rerun_commands, slowest_tests, Highlight, OutputFormatter};
 use crate::{
-    bench::fmt_bench_samples,
+    bench::{fmt_bench_percentiles, fmt_bench_samples_with_precision, is_noisy},
     console::{ConsoleTestState, OutputLocation},
+    event::Attempt,
+    options::ColorTheme,
     test_result::TestResult,
     time,
     types::NamePadding,
@@ -16,9 +18,13 @@
 pub(crate) struct TerseFormatter<T> {
     out: OutputLocation<T>,
     use_color: bool,
+    errors_only_color: bool,
+    theme: ColorTheme,
     is_multithreaded: bool,
     /// Number of columns to fill when aligning names
     max_name_len: usize,
+    /// Number of results written between two flushes of `out`
+    flush_interval: usize,
 
     test_count: usize,
     total_test_count: usize,
@@ -28,73 +34,90 @@ impl<T: Write> TerseFormatter<T> {
     pub fn new(
         out: OutputLocation<T>,
         use_color: bool,
+        errors_only_color: bool,
+        theme: ColorTheme,
         max_name_len: usize,
         is_multithreaded: bool,
+        flush_interval: usize,
     ) -> Self {
         TerseFormatter {
             out,
             use_color,
+            errors_only_color,
+            theme,
             max_name_len,
             is_multithreaded,
+            flush_interval,
             test_count: 0,
             total_test_count: 0, // initialized later, when write_run_start is called
         }
     }
 
+    #[cfg(test)]
+    pub fn output_location(&self) -> &OutputLocation<T> {
+        &self.out
+    }
+
     pub fn write_ok(&mut self) -> io::Result<()> {
-        self.write_short_result(".", term::color::GREEN)
+        self.write_short_result(".", Highlight::Ok)
     }
 
     pub fn write_failed(&mut self) -> io::Result<()> {
-        self.write_short_result("F", term::color::RED)
+        self.write_short_result("F", Highlight::Failed)
     }
 
     pub fn write_ignored(&mut self) -> io::Result<()> {
-        self.write_short_result("i", term::color::YELLOW)
+        self.write_short_result("i", Highlight::Ignored)
     }
 
     pub fn write_allowed_fail(&mut self) -> io::Result<()> {
-        self.write_short_result("a", term::color::YELLOW)
+        self.write_short_result("a", Highlight::Warning)
     }
 
     pub fn write_bench(&mut self) -> io::Result<()> {
-        self.write_pretty("bench", term::color::CYAN)
+        self.write_pretty("bench", Highlight::Bench)
     }
 
-    pub fn write_short_result(
-        &mut self,
-        result: &str,
-        color: term::color::Color,
-    ) -> io::Result<()> {
-        self.write_pretty(result, color)?;
+    pub fn write_short_result(&mut self, result: &str, highlight: Highlight) -> io::Result<()> {
+        self.write_colored(result, highlight)?;
         if self.test_count % QUIET_MODE_MAX_COLUMN == QUIET_MODE_MAX_COLUMN - 1 {
             // we insert a new line every 100 dots in order to flush the
             // screen when dealing with line-buffered output (e.g., piping to
             // `stamp` in the rust CI).
             let out = format!(" {}/{}\n", self.test_count + 1, self.total_test_count);
             self.write_plain(&out)?;
+        } else if (self.test_count + 1) % self.flush_interval == 0 {
+            // By default, each result is flushed so that the progress shows up
+            // in the logs, but `--terse-flush-interval` can batch them.
+            self.out.flush()?;
         }
 
         self.test_count += 1;
         Ok(())
     }
 
-    pub fn write_pretty(&mut self, word: &str, color: term::color::Color) -> io::Result<()> {
+    pub fn write_pretty(&mut self, word: &str, highlight: Highlight) -> io::Result<()> {
+        self.write_colored(word, highlight)?;
+        self.out.flush()
+    }
+
+    // Writes `word` in the color of `highlight` (if colors are used), without
+    // flushing.
+    fn write_colored(&mut self, word: &str, highlight: Highlight) -> io::Result<()> {
         match self.out {
             OutputLocation::Pretty(ref mut term) => {
-                if self.use_color {
-                    term.fg(color)?;
+                // With `--color=errors-only`, only the failures are colorized.
+                let colorize = self.use_color && highlight.is_colorized(self.errors_only_color);
+                if colorize {
+                    term.fg(highlight.color(&self.theme))?;
                 }
                 term.write_all(word.as_bytes())?;
-                if self.use_color {
+                if colorize {
                     term.reset()?;
                 }
-                term.flush()
-            }
-            OutputLocation::Raw(ref mut stdout) => {
-                stdout.write_all(word.as_bytes())?;
-                stdout.flush()
+                Ok(())
             }
+            OutputLocation::Raw(ref mut stdout) => stdout.write_all(word.as_bytes()),
         }
     }
 
@@ -175,6 +198,10 @@ fn write_run_start(&mut self, test_count: usize) -> io::Result<()> {
         self.write_plain(&format!("\nrunning {} {}\n", test_count, noun))
     }
 
+    fn write_shuffle_seed(&mut self, seed: u64) -> io::Result<()> {
+        self.write_plain(&format!("shuffle seed: {}\n", seed))
+    }
+
     fn write_test_start(&mut self, desc: &TestDesc) -> io::Result<()> {
         // Remnants from old libtest code that used the padding value
         // in order to indicate benchmarks.
@@ -192,22 +219,36 @@ fn write_result(
         desc: &TestDesc,
         result: &TestResult,
         _: Option<&time::TestExecTime>,
+        _: Option<Attempt>,
+        _: Option<u64>,
+        _: &[u8],
         _: &[u8],
-        _: &ConsoleTestState,
+        state: &ConsoleTestState,
     ) -> io::Result<()> {
         match *result {
             TestResult::TrOk => self.write_ok(),
             TestResult::TrFailed | TestResult::TrFailedMsg(_) | TestResult::TrTimedFail => {
                 self.write_failed()
             }
-            TestResult::TrIgnored => self.write_ignored(),
+            TestResult::TrIgnored | TestResult::TrIgnoredMsg(_) => self.write_ignored(),
             TestResult::TrAllowedFail => self.write_allowed_fail(),
             TestResult::TrBench(ref bs) => {
                 if self.is_multithreaded {
                     self.write_test_name(desc)?;
                 }
                 self.write_bench()?;
-                self.write_plain(&format!(": {}\n", fmt_bench_samples(bs)))
+                let precision = state.options.time_precision.unwrap_or(0);
+                self.write_plain(&format!(
+                    ": {}",
+                    fmt_bench_samples_with_precision(bs, precision)
+                ))?;
+                if state.options.show_percentiles && !bs.percentiles.is_empty() {
+                    self.write_plain(&format!(" ({})", fmt_bench_percentiles(bs)))?;
+                }
+                if is_noisy(&bs.ns_iter_summ, state.options.bench_noise_threshold) {
+                    self.write_pretty(" \u{26a0} high variance", Highlight::Warning)?;
+                }
+                self.write_plain("\n")
             }
         }
     }
@@ -227,15 +268,27 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
         let success = state.failed == 0;
         if !success {
             self.write_failures(state)?;
+
+            if state.options.print_rerun {
+                self.write_plain(&rerun_commands(state))?;
+            }
+        }
+
+        if !state.large_outputs.is_empty() {
+            self.write_plain(&large_outputs(state))?;
+        }
+
+        if !state.exec_times.is_empty() {
+            self.write_plain(&slowest_tests(state))?;
         }
 
         self.write_plain("\ntest result: ")?;
 
         if success {
             // There's no parallelism at this point so it's safe to use color
-            self.write_pretty("ok", term::color::GREEN)?;
+            self.write_pretty("ok", Highlight::Ok)?;
         } else {
-            self.write_pretty("FAILED", term::color::RED)?;
+            self.write_pretty("FAILED", Highlight::Failed)?;
         }
 
         let s = if state.allowed_fail > 0 {
@@ -257,8 +310,17 @@ fn write_run_finish(&mut self, state: &ConsoleTestState) -> io::Result<bool> {
 
         self.write_plain(&s)?;
 
+        if state.not_run > 0 {
+            let aborted_str =
+                format!("; aborted after first failure; {} tests not run", state.not_run);
+            self.write_plain(&aborted_str)?;
+        }
+
         if let Some(ref exec_time) = state.exec_time {
-            let time_str = format!("; finished in {}", exec_time);
+            let time_str = format!(
+                "; finished in {}",
+                time::fmt_time(exec_time, state.options.time_precision)
+            );
             self.write_plain(&time_str)?;
         }
 
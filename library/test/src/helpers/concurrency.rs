@@ -1,14 +1,42 @@
 //! Helper module which helps to determine amount of threads to be used
 //! during tests execution.
-use std::{env, num::NonZeroUsize, thread};
+use std::{cmp, env, num::NonZeroUsize, sync::Once, thread};
 
 pub fn get_concurrency() -> usize {
+    let available = thread::available_concurrency().map(|n| n.get()).unwrap_or(1);
     if let Ok(value) = env::var("RUST_TEST_THREADS") {
-        match value.parse::<NonZeroUsize>().ok() {
-            Some(n) => n.get(),
-            _ => panic!("RUST_TEST_THREADS is `{}`, should be a positive integer.", value),
+        match parse_concurrency(&value, available) {
+            Some(n) => n,
+            None => {
+                // The concurrency is looked up more than once per run.
+                static WARN: Once = Once::new();
+                WARN.call_once(|| {
+                    eprintln!(
+                        "warning: RUST_TEST_THREADS is `{}`, should be a positive integer, a \
+                         negative number of threads to leave unused (e.g. `-1`), or a \
+                         fraction of the available threads (e.g. `auto/2`); using {} threads",
+                        value, available
+                    )
+                });
+                available
+            }
         }
     } else {
-        thread::available_concurrency().map(|n| n.get()).unwrap_or(1)
+        available
     }
 }
+
+/// Parses the number of threads given by `RUST_TEST_THREADS`, out of the
+/// `available` ones: either a number of threads, `-N` to leave `N` of them
+/// unused, or `auto/N` to use one `N`th of them. At least one thread is used.
+pub fn parse_concurrency(value: &str, available: usize) -> Option<usize> {
+    if let Some(divisor) = value.strip_prefix("auto/") {
+        let divisor = divisor.parse::<NonZeroUsize>().ok()?;
+        return Some(cmp::max(1, available / divisor.get()));
+    }
+    if let Some(unused) = value.strip_prefix('-') {
+        let unused = unused.parse::<NonZeroUsize>().ok()?;
+        return Some(cmp::max(1, available.saturating_sub(unused.get())));
+    }
+    value.parse::<NonZeroUsize>().ok().map(NonZeroUsize::get)
+}
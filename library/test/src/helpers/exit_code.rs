@@ -13,7 +13,12 @@ pub fn get_exit_code(status: ExitStatus) -> Result<i32, String> {
     match status.code() {
         Some(code) => Ok(code),
         None => match status.signal() {
-            Some(signal) => Err(format!("child process exited with signal {}", signal)),
+            // Stack overflows end up here, as the runtime aborts the process once it
+            // detects one (with SIGSEGV or SIGBUS when the guard page is hit outside
+            // of the runtime's handler).
+            Some(signal) => {
+                Err(format!("stack overflow or abnormal termination (signal {})", signal))
+            }
             None => Err("child process exited with unknown signal".into()),
         },
     }
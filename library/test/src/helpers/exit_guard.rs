@@ -0,0 +1,70 @@
+//! Helper module which reports the tests running in-process when the process
+//! exits, since a test calling `process::exit` takes the whole harness down
+//! with it.
+
+use std::io::{self, Write};
+use std::lazy::SyncLazy;
+use std::sync::Mutex;
+
+static RUNNING_TESTS: SyncLazy<Mutex<Vec<String>>> = SyncLazy::new(|| Mutex::new(Vec::new()));
+
+/// Marks a test as running in-process, until dropped.
+pub struct RunningTest {
+    name: String,
+}
+
+impl RunningTest {
+    pub fn new(name: &str) -> RunningTest {
+        register_exit_handler();
+        lock_running_tests().push(name.to_owned());
+        RunningTest { name: name.to_owned() }
+    }
+}
+
+impl Drop for RunningTest {
+    fn drop(&mut self) {
+        let mut running_tests = lock_running_tests();
+        if let Some(i) = running_tests.iter().position(|name| *name == self.name) {
+            running_tests.swap_remove(i);
+        }
+    }
+}
+
+/// Names of the tests currently running in-process.
+pub fn running_tests() -> Vec<String> {
+    lock_running_tests().clone()
+}
+
+fn lock_running_tests() -> std::sync::MutexGuard<'static, Vec<String>> {
+    // A test panicking can't poison the lock, which is never held while running it.
+    RUNNING_TESTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+extern "C" fn report_running_tests() {
+    let running_tests = running_tests();
+    if running_tests.is_empty() {
+        return;
+    }
+    let names = running_tests.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>();
+    // Written to the stderr of the process directly, as the output of the test
+    // exiting may be captured.
+    let _ = writeln!(
+        io::stderr(),
+        "error: the process exited while running {}; run the tests with \
+         `-Z unstable-options --force-subprocess` to report this as a failure of the test",
+        names.join(", ")
+    );
+}
+
+#[cfg(unix)]
+fn register_exit_handler() {
+    use std::sync::Once;
+
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        libc::atexit(report_running_tests);
+    });
+}
+
+#[cfg(not(unix))]
+fn register_exit_handler() {}
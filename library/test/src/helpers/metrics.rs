@@ -11,9 +11,17 @@ impl Metric {
     pub fn new(value: f64, noise: f64) -> Metric {
         Metric { value, noise }
     }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn noise(&self) -> f64 {
+        self.noise
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct MetricMap(BTreeMap<String, Metric>);
 
 impl MetricMap {
@@ -39,6 +47,15 @@ pub fn insert_metric(&mut self, name: &str, value: f64, noise: f64) {
         self.0.insert(name.to_owned(), m);
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the metrics, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Metric)> {
+        self.0.iter().map(|(name, metric)| (&**name, metric))
+    }
+
     pub fn fmt_metrics(&self) -> String {
         let v = self
             .0
@@ -3,5 +3,8 @@
 
 pub mod concurrency;
 pub mod exit_code;
+pub mod exit_guard;
 pub mod isatty;
 pub mod metrics;
+pub mod regex;
+pub mod shuffle;
@@ -0,0 +1,200 @@
+//! Minimal regular expressions, to match the panic messages of the tests with
+//! `#[should_panic(expected_regex = "...")]`.
+//!
+//! Only a small subset of the usual syntax is supported: `.`, character
+//! classes such as `[a-z_]` or `[^0-9]`, the `\d`, `\w` and `\s` classes, the
+//! `*`, `+` and `?` repetitions, and the `^` and `$` anchors at the start and
+//! end of the pattern. Other characters match themselves, and can be escaped
+//! with `\`.
+//!
+//! Without groups, repetitions can't be nested, and the matcher remembers the
+//! positions it failed to match each item at, so matching takes at most time
+//! proportional to the number of items times the square of the length of the
+//! text, whatever the pattern.
+
+/// A compiled regular expression.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    items: Vec<Item>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    atom: Atom,
+    min: usize,
+    max: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum Atom {
+    Any,
+    Char(char),
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            Atom::Any => true,
+            Atom::Char(expected) => c == expected,
+            Atom::Class { negated, ref ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated
+            }
+        }
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+impl Regex {
+    /// Compiles `pattern`, or returns why it's invalid.
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut chars = pattern.chars().peekable();
+        let anchored_start = chars.next_if_eq(&'^').is_some();
+        let mut anchored_end = false;
+        let mut items: Vec<Item> = Vec::new();
+
+        while let Some(c) = chars.next() {
+            let atom = match c {
+                '$' if chars.peek().is_none() => {
+                    anchored_end = true;
+                    break;
+                }
+                '.' => Atom::Any,
+                '[' => parse_class(&mut chars)?,
+                '\\' => match chars.next() {
+                    Some(c) => escaped(c)?,
+                    None => return Err("trailing `\\`".to_string()),
+                },
+                '*' | '+' | '?' => {
+                    let item = match items.last_mut() {
+                        Some(item) if item.min == 1 && item.max == Some(1) => item,
+                        _ => return Err(format!("`{}` doesn't follow anything to repeat", c)),
+                    };
+                    if c != '+' {
+                        item.min = 0;
+                    }
+                    if c != '?' {
+                        item.max = None;
+                    }
+                    continue;
+                }
+                '(' | ')' | '|' => {
+                    return Err(format!("`{}` isn't supported, groups and alternations aren't", c));
+                }
+                c => Atom::Char(c),
+            };
+            items.push(Item { atom, min: 1, max: Some(1) });
+        }
+
+        Ok(Regex { items, anchored_start, anchored_end })
+    }
+
+    /// Returns whether the regex matches a part of `text`, or all of it when
+    /// it's anchored at both ends.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        let mut failed = vec![false; self.items.len() * (text.len() + 1)];
+        if self.anchored_start {
+            return self.match_here(0, &text, 0, &mut failed);
+        }
+        (0..=text.len()).any(|start| self.match_here(0, &text, start, &mut failed))
+    }
+
+    // Returns whether the items from `item` on match `text` from `pos` on,
+    // trying the longest repetitions first. `failed` records the item and
+    // position pairs known not to match, which don't depend on where the
+    // match started.
+    fn match_here(&self, item: usize, text: &[char], pos: usize, failed: &mut [bool]) -> bool {
+        let current = match self.items.get(item) {
+            Some(current) => current,
+            None => return !self.anchored_end || pos == text.len(),
+        };
+        let state = item * (text.len() + 1) + pos;
+        if failed[state] {
+            return false;
+        }
+        let max = current.max.unwrap_or(usize::MAX);
+        let repeated =
+            text[pos..].iter().take(max).take_while(|&&c| current.atom.matches(c)).count();
+        let matched = repeated >= current.min
+            && (current.min..=repeated)
+                .rev()
+                .any(|n| self.match_here(item + 1, text, pos + n, failed));
+        if !matched {
+            failed[state] = true;
+        }
+        matched
+    }
+}
+
+// Parses a character class, after its `[`.
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<Atom, String> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let lo = match chars.next() {
+            // A `]` right after the `[` is part of the class.
+            Some(']') if !first => break,
+            Some('\\') => match chars.next().map(escaped) {
+                Some(Ok(Atom::Class { ranges: class, .. })) => {
+                    ranges.extend_from_slice(&class);
+                    first = false;
+                    continue;
+                }
+                Some(Ok(Atom::Char(c))) => c,
+                Some(Ok(Atom::Any)) => unreachable!(),
+                Some(Err(e)) => return Err(e),
+                None => return Err("unclosed character class".to_string()),
+            },
+            Some(c) => c,
+            None => return Err("unclosed character class".to_string()),
+        };
+        first = false;
+
+        let hi = if chars.next_if_eq(&'-').is_some() {
+            match chars.next() {
+                // A `-` at the end of the class is part of it.
+                Some(']') => {
+                    ranges.push((lo, lo));
+                    ranges.push(('-', '-'));
+                    break;
+                }
+                Some('\\') => match chars.next().map(escaped) {
+                    Some(Ok(Atom::Char(c))) => c,
+                    Some(Ok(_)) => return Err("invalid range in character class".to_string()),
+                    Some(Err(e)) => return Err(e),
+                    None => return Err("unclosed character class".to_string()),
+                },
+                Some(c) => c,
+                None => return Err("unclosed character class".to_string()),
+            }
+        } else {
+            lo
+        };
+        if hi < lo {
+            return Err(format!("invalid range `{}-{}` in character class", lo, hi));
+        }
+        ranges.push((lo, hi));
+    }
+    Ok(Atom::Class { negated, ranges })
+}
+
+// Returns the atom escaped by `\c`.
+fn escaped(c: char) -> Result<Atom, String> {
+    let class = |ranges: &[(char, char)]| Atom::Class { negated: false, ranges: ranges.to_vec() };
+    match c {
+        'd' => Ok(class(DIGIT)),
+        'w' => Ok(class(WORD)),
+        's' => Ok(class(SPACE)),
+        'n' => Ok(Atom::Char('\n')),
+        't' => Ok(Atom::Char('\t')),
+        c if c.is_ascii_alphanumeric() => Err(format!("unknown escape `\\{}`", c)),
+        c => Ok(Atom::Char(c)),
+    }
+}
@@ -0,0 +1,30 @@
+//! Shuffling of the tests for `--shuffle`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::types::TestDescAndFn;
+
+/// Picks a seed from the system clock, for `--shuffle` without `--shuffle-seed`.
+pub fn get_shuffle_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// Shuffles the tests in an order which only depends on `seed` and on their
+/// order before shuffling.
+pub fn shuffle_tests(seed: u64, tests: &mut [TestDescAndFn]) {
+    // SplitMix64, which is small and good enough to pick an order.
+    let mut state = seed;
+    let mut next_random = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    // Fisher-Yates shuffle.
+    for i in (1..tests.len()).rev() {
+        let j = (next_random() % (i as u64 + 1)) as usize;
+        tests.swap(i, j);
+    }
+}
@@ -24,46 +24,61 @@
 #![feature(rustc_private)]
 #![feature(nll)]
 #![feature(available_concurrency)]
+#![feature(backtrace)]
 #![feature(bench_black_box)]
 #![feature(internal_output_capture)]
+#![feature(once_cell)]
 #![feature(panic_unwind)]
 #![feature(staged_api)]
 #![feature(termination_trait_lib)]
 #![feature(test)]
+#![feature(thread_local_const_init)]
 #![feature(total_cmp)]
+#![cfg_attr(test, feature(command_access))]
 
 // Public reexports
 pub use self::bench::{black_box, Bencher};
-pub use self::console::run_tests_console;
-pub use self::options::{ColorConfig, Options, OutputFormat, RunIgnored, ShouldPanic};
+pub use self::console::{run_tests_console, run_tests_console_with_reporters};
+pub use self::event::CompletedTest;
+pub use self::options::{
+    ColorConfig, ColorTheme, LogfileFormat, Options, OutputFormat, RunIgnored, ShouldPanic,
+    TimeoutAction,
+};
+pub use self::reporter::Reporter;
+pub use self::test_result::TestResult;
+pub use self::time::TestExecTime;
 pub use self::types::TestName::*;
 pub use self::types::*;
 pub use self::ColorConfig::*;
-pub use cli::TestOpts;
+pub use cli::{TestOpts, DEFAULT_BENCH_SAMPLES, DEFAULT_TERSE_FLUSH_INTERVAL};
 
 // Module to be used by rustc to compile tests in libtest
 pub mod test {
     pub use crate::{
         assert_test_result,
         bench::Bencher,
-        cli::{parse_opts, TestOpts},
+        cli::{parse_opts, TestOpts, DEFAULT_BENCH_SAMPLES, DEFAULT_TERSE_FLUSH_INTERVAL},
+        event::{Attempt, CompletedTest, TestEvent},
         filter_tests,
         helpers::metrics::{Metric, MetricMap},
-        options::{Concurrent, Options, RunIgnored, RunStrategy, ShouldPanic},
-        run_test, test_main, test_main_static,
-        test_result::{TestResult, TrFailed, TrFailedMsg, TrIgnored, TrOk},
+        options::{
+            ColorTheme, Concurrent, Options, RunIgnored, RunStrategy, ShouldPanic, TimeoutAction,
+        },
+        reporter::Reporter,
+        run_test, run_tests_with_observer, summarize_filter, test_main, test_main_static,
+        test_result::{TestResult, TrFailed, TrFailedMsg, TrIgnored, TrIgnoredMsg, TrOk},
         time::{TestExecTime, TestTimeOptions},
         types::{
-            DynTestFn, DynTestName, StaticBenchFn, StaticTestFn, StaticTestName, TestDesc,
-            TestDescAndFn, TestId, TestName, TestType,
+            DynTestFn, DynTestName, SourceLocation, StaticBenchFn, StaticTestFn, StaticTestName,
+            TestDesc, TestDescAndFn, TestHook, TestId, TestName, TestType,
         },
+        FilterSummary,
     };
 }
 
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     env, io,
-    io::prelude::Write,
     panic::{self, catch_unwind, AssertUnwindSafe, PanicInfo},
     process::{self, Command, Termination},
     sync::mpsc::{channel, Sender},
@@ -79,6 +94,8 @@ pub mod test {
 mod formatters;
 mod helpers;
 mod options;
+mod reporter;
+mod server;
 pub mod stats;
 mod test_result;
 mod time;
@@ -87,18 +104,22 @@ pub mod test {
 #[cfg(test)]
 mod tests;
 
-use event::{CompletedTest, TestEvent};
+use event::{Attempt, TestEvent};
 use helpers::concurrency::get_concurrency;
-use helpers::exit_code::get_exit_code;
+use helpers::exit_guard::RunningTest;
+use helpers::shuffle::shuffle_tests;
 use options::{Concurrent, RunStrategy};
 use test_result::*;
-use time::TestExecTime;
 
 // Process exit code to be used to indicate test failures.
 const ERROR_EXIT_CODE: i32 = 101;
 
 const SECONDARY_TEST_INVOKER_VAR: &str = "__RUST_TEST_INVOKE";
 
+// Asks a test subprocess to print its backtrace when receiving `SIGQUIT`, for
+// `--timeout-action backtrace`.
+const TIMEOUT_BACKTRACE_VAR: &str = "__RUST_TEST_TIMEOUT_BACKTRACE";
+
 // The default console test runner. It accepts the command line
 // arguments and a vector of test_descs.
 pub fn test_main(args: &[String], tests: Vec<TestDescAndFn>, options: Option<Options>) {
@@ -113,21 +134,58 @@ pub fn test_main(args: &[String], tests: Vec<TestDescAndFn>, options: Option<Opt
     if let Some(options) = options {
         opts.options = options;
     }
+    if opts.dump_opts {
+        print!("{}", opts.dump());
+        return;
+    }
+    if opts.server {
+        let stdin = io::stdin();
+        if let Err(e) = server::serve(&opts, tests, stdin.lock(), io::stdout()) {
+            eprintln!("error: io error when serving tests: {:?}", e);
+            process::exit(ERROR_EXIT_CODE);
+        }
+        return;
+    }
     if opts.list {
         if let Err(e) = console::list_tests_console(&opts, tests) {
             eprintln!("error: io error when listing tests: {:?}", e);
             process::exit(ERROR_EXIT_CODE);
         }
-    } else {
-        match console::run_tests_console(&opts, tests) {
-            Ok(true) => {}
-            Ok(false) => process::exit(ERROR_EXIT_CODE),
+        return;
+    }
+    // Only read stdin once we know the tests are going to run, so that
+    // listing them or dumping the options never blocks on it.
+    if opts.tests_from_stdin {
+        match cli::read_test_names(io::stdin().lock()) {
+            Ok(names) => opts.selected_tests = Some(names),
             Err(e) => {
-                eprintln!("error: io error when listing tests: {:?}", e);
+                eprintln!("error: failed to read the test names from stdin: {}", e);
                 process::exit(ERROR_EXIT_CODE);
             }
         }
     }
+    if let Some(ref selected_tests) = opts.selected_tests {
+        let unknown_tests = unknown_test_names(selected_tests, &tests);
+        if !unknown_tests.is_empty() {
+            let level = if opts.allow_unknown_tests { "warning" } else { "error" };
+            eprintln!(
+                "{}: no test named {} (read by --tests-from-stdin)",
+                level,
+                unknown_tests.join(", ")
+            );
+            if !opts.allow_unknown_tests {
+                process::exit(ERROR_EXIT_CODE);
+            }
+        }
+    }
+    match console::run_tests_console(&opts, tests) {
+        Ok(true) => {}
+        Ok(false) => process::exit(ERROR_EXIT_CODE),
+        Err(e) => {
+            eprintln!("error: io error when listing tests: {:?}", e);
+            process::exit(ERROR_EXIT_CODE);
+        }
+    }
 }
 
 /// A variant optimized for invocation with a static test vector.
@@ -136,6 +194,8 @@ pub fn test_main(args: &[String], tests: Vec<TestDescAndFn>, options: Option<Opt
 /// This is the entry point for the main function generated by `rustc --test`
 /// when panic=unwind.
 pub fn test_main_static(tests: &[&TestDescAndFn]) {
+    run_spawned_test_if_requested(tests);
+
     let args = env::args().collect::<Vec<_>>();
     let owned_tests: Vec<_> = tests.iter().map(make_owned_test).collect();
     test_main(&args, owned_tests, None)
@@ -150,8 +210,16 @@ pub fn test_main_static(tests: &[&TestDescAndFn]) {
 /// This is the entry point for the main function generated by `rustc --test`
 /// when panic=abort.
 pub fn test_main_static_abort(tests: &[&TestDescAndFn]) {
-    // If we're being run in SpawnedSecondary mode, run the test here. run_test
-    // will then exit the process.
+    run_spawned_test_if_requested(tests);
+
+    let args = env::args().collect::<Vec<_>>();
+    let owned_tests: Vec<_> = tests.iter().map(make_owned_test).collect();
+    test_main(&args, owned_tests, Some(Options::new().panic_abort(true)))
+}
+
+// If we're being run in SpawnedSecondary mode (with panic=abort, or with
+// `--force-subprocess`), run the test here. run_test will then exit the process.
+fn run_spawned_test_if_requested(tests: &[&TestDescAndFn]) {
     if let Ok(name) = env::var(SECONDARY_TEST_INVOKER_VAR) {
         env::remove_var(SECONDARY_TEST_INVOKER_VAR);
         let test = tests
@@ -160,6 +228,10 @@ pub fn test_main_static_abort(tests: &[&TestDescAndFn]) {
             .map(make_owned_test)
             .next()
             .unwrap_or_else(|| panic!("couldn't find a test with the provided name '{}'", name));
+        if env::var_os(TIMEOUT_BACKTRACE_VAR).is_some() {
+            env::remove_var(TIMEOUT_BACKTRACE_VAR);
+            print_backtrace_on_sigquit();
+        }
         let TestDescAndFn { desc, testfn } = test;
         let testfn = match testfn {
             StaticTestFn(f) => f,
@@ -167,10 +239,6 @@ pub fn test_main_static_abort(tests: &[&TestDescAndFn]) {
         };
         run_test_in_spawned_subprocess(desc, Box::new(testfn));
     }
-
-    let args = env::args().collect::<Vec<_>>();
-    let owned_tests: Vec<_> = tests.iter().map(make_owned_test).collect();
-    test_main(&args, owned_tests, Some(Options::new().panic_abort(true)))
 }
 
 /// Clones static values for putting into a dynamic vector, which test_main()
@@ -185,6 +253,22 @@ fn make_owned_test(test: &&TestDescAndFn) -> TestDescAndFn {
     }
 }
 
+/// Stops the running test and reports it as ignored with the given `reason`,
+/// for tests that can only tell at runtime that they can't be run (e.g. when
+/// an external service they need is unavailable).
+///
+/// This unwinds out of the test like a panic does, but without invoking the
+/// panic hook, and is never treated as a failure, not even for
+/// `#[should_panic]` tests.
+///
+/// Skipping is only supported for tests that run in-process: under
+/// `-Z panic-abort-tests` the test's process aborts and the test is reported
+/// as failed. Exiting the test's process (e.g. via `std::process::exit`) is
+/// not a way to skip a test either.
+pub fn skip(reason: &str) -> ! {
+    panic::resume_unwind(Box::new(SkipTest(reason.to_owned())))
+}
+
 /// Invoked when unit tests terminate. Should panic if the unit
 /// Tests is considered a failure. By default, invokes `report()`
 /// and checks for a `0` result.
@@ -198,6 +282,22 @@ pub fn assert_test_result<T: Termination>(result: T) {
     );
 }
 
+/// Runs the tests like `run_tests_console`, but hands each completed test to
+/// `observer` instead of reporting it on the console, for harnesses embedding
+/// libtest.
+pub fn run_tests_with_observer(
+    opts: &TestOpts,
+    tests: Vec<TestDescAndFn>,
+    observer: &mut dyn FnMut(&CompletedTest),
+) -> io::Result<()> {
+    run_tests(opts, tests, |event| {
+        if let TestEvent::TeResult(ref completed_test) = event {
+            observer(completed_test);
+        }
+        Ok(())
+    })
+}
+
 pub fn run_tests<F>(
     opts: &TestOpts,
     tests: Vec<TestDescAndFn>,
@@ -224,9 +324,17 @@ struct TimeoutEntry {
         timeout: Instant,
     }
 
+    // Prevent the usage of `Instant` in some cases:
+    // - It's currently not supported for wasm targets.
+    // - We disable it for miri because it's not available when isolation is enabled.
+    let is_instant_supported = !cfg!(target_arch = "wasm32") && !cfg!(miri);
+    let start_time = is_instant_supported.then(Instant::now);
+
     let tests_len = tests.len();
 
     let mut filtered_tests = filter_tests(opts, tests);
+    let filtered_out = tests_len - filtered_tests.len();
+    filtered_tests = repeat_tests(filtered_tests, opts.repeat);
     if !opts.bench_benchmarks {
         filtered_tests = convert_benchmarks_to_tests(filtered_tests);
     }
@@ -240,10 +348,14 @@ struct TimeoutEntry {
         filtered_tests
     };
 
-    let filtered_out = tests_len - filtered_tests.len();
     let event = TestEvent::TeFilteredOut(filtered_out);
     notify_about_test_event(event)?;
 
+    if let Some(seed) = opts.shuffle_seed {
+        let event = TestEvent::TeShuffleSeed(seed);
+        notify_about_test_event(event)?;
+    }
+
     let filtered_descs = filtered_tests.iter().map(|t| t.desc.clone()).collect();
 
     let event = TestEvent::TeFiltered(filtered_descs);
@@ -262,11 +374,12 @@ struct TimeoutEntry {
     let mut pending = 0;
 
     let (tx, rx) = channel::<CompletedTest>();
-    let run_strategy = if opts.options.panic_abort && !opts.force_run_in_process {
-        RunStrategy::SpawnPrimary
-    } else {
-        RunStrategy::InProcess
-    };
+    let run_strategy =
+        if (opts.options.panic_abort || opts.force_subprocess) && !opts.force_run_in_process {
+            RunStrategy::SpawnPrimary
+        } else {
+            RunStrategy::InProcess
+        };
 
     let mut running_tests: TestMap = HashMap::default();
     let mut timeout_queue: VecDeque<TimeoutEntry> = VecDeque::new();
@@ -296,6 +409,12 @@ fn calc_timeout(timeout_queue: &VecDeque<TimeoutEntry>) -> Option<Duration> {
         })
     }
 
+    // With `--fail-fast`, no test is started after a failure.
+    fn aborts_run(opts: &TestOpts, result: &TestResult) -> bool {
+        opts.fail_fast && matches!(result, TrFailed | TrFailedMsg(_) | TrTimedFail)
+    }
+    let mut aborted = false;
+
     if concurrency == 1 {
         while !remaining.is_empty() {
             let (id, test) = remaining.pop().unwrap();
@@ -305,15 +424,24 @@ fn calc_timeout(timeout_queue: &VecDeque<TimeoutEntry>) -> Option<Duration> {
                 run_test(opts, !opts.run_tests, id, test, run_strategy, tx.clone(), Concurrent::No);
             assert!(join_handle.is_none());
             let completed_test = rx.recv().unwrap();
+            aborted = aborts_run(opts, &completed_test.result);
 
             let event = TestEvent::TeResult(completed_test);
             notify_about_test_event(event)?;
+            if aborted {
+                break;
+            }
         }
     } else {
-        while pending > 0 || !remaining.is_empty() {
-            while pending < concurrency && !remaining.is_empty() {
+        // Once aborted, the tests still running are waited for, so that their
+        // results are reported rather than lost.
+        while pending > 0 || (!aborted && !remaining.is_empty()) {
+            while pending < concurrency && !aborted && !remaining.is_empty() {
                 let (id, test) = remaining.pop().unwrap();
-                let timeout = time::get_default_test_timeout();
+                let timeout = match opts.kill_on_timeout {
+                    Some(timeout) => Instant::now() + timeout,
+                    None => time::get_default_test_timeout(),
+                };
                 let desc = test.desc.clone();
 
                 let event = TestEvent::TeWait(desc.clone());
@@ -366,6 +494,7 @@ fn calc_timeout(timeout_queue: &VecDeque<TimeoutEntry>) -> Option<Duration> {
                     }
                 }
             }
+            aborted |= aborts_run(opts, &completed_test.result);
 
             let event = TestEvent::TeResult(completed_test);
             notify_about_test_event(event)?;
@@ -373,52 +502,136 @@ fn calc_timeout(timeout_queue: &VecDeque<TimeoutEntry>) -> Option<Duration> {
         }
     }
 
-    if opts.bench_benchmarks {
+    if aborted {
+        let event = TestEvent::TeAborted(remaining.len() + filtered_benchs.len());
+        notify_about_test_event(event)?;
+    } else if opts.bench_benchmarks {
         // All benchmarks run at the end, in serial.
-        for (id, b) in filtered_benchs {
+        let mut filtered_benchs = filtered_benchs.into_iter();
+        while let Some((id, b)) = filtered_benchs.next() {
             let event = TestEvent::TeWait(b.desc.clone());
             notify_about_test_event(event)?;
             run_test(opts, false, id, b, run_strategy, tx.clone(), Concurrent::No);
             let completed_test = rx.recv().unwrap();
+            let aborted = aborts_run(opts, &completed_test.result);
 
             let event = TestEvent::TeResult(completed_test);
             notify_about_test_event(event)?;
+            if aborted {
+                let event = TestEvent::TeAborted(filtered_benchs.len());
+                notify_about_test_event(event)?;
+                break;
+            }
         }
     }
-    Ok(())
+
+    let exec_time = start_time.map(|start| time::TestSuiteExecTime(start.elapsed()));
+    let event = TestEvent::TeFinished(exec_time);
+    notify_about_test_event(event)
 }
 
-pub fn filter_tests(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> Vec<TestDescAndFn> {
-    let mut filtered = tests;
-    let matches_filter = |test: &TestDescAndFn, filter: &str| {
-        let test_name = test.desc.name.as_slice();
+/// Returns the names given to `--tests-from-stdin` which match none of `tests`.
+fn unknown_test_names<'a>(names: &'a [String], tests: &[TestDescAndFn]) -> Vec<&'a str> {
+    let known_names: HashSet<&str> = tests.iter().map(|test| test.desc.name.as_slice()).collect();
+    names.iter().map(|name| &**name).filter(|name| !known_names.contains(name)).collect()
+}
 
-        match opts.filter_exact {
-            true => test_name == filter,
-            false => test_name.contains(filter),
+/// Numbers of tests kept and removed by `filter_tests`, by kind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterSummary {
+    /// Number of tests and benchmarks before filtering.
+    pub total: usize,
+    /// Number of tests and benchmarks kept by the filters.
+    pub kept: usize,
+    /// Number of kept tests which are ignored, and won't run.
+    pub ignored: usize,
+    /// Number of kept benchmarks.
+    pub benches: usize,
+    /// Number of tests and benchmarks removed by the filters.
+    pub filtered_out: usize,
+}
+
+/// Counts the tests that `filter_tests` keeps, without consuming them, e.g. to
+/// size a progress bar before running the tests. The repetitions of
+/// `--repeat` aren't counted.
+pub fn summarize_filter(opts: &TestOpts, tests: &[TestDescAndFn]) -> FilterSummary {
+    let selected_tests = selected_tests(opts);
+    let mut summary = FilterSummary { total: tests.len(), ..FilterSummary::default() };
+    for test in tests.iter().filter(|test| keeps_test(opts, selected_tests.as_ref(), test)) {
+        summary.kept += 1;
+        if test.desc.ignore && opts.run_ignored == RunIgnored::No {
+            summary.ignored += 1;
+        }
+        if matches!(test.testfn, StaticBenchFn(_) | DynBenchFn(_)) {
+            summary.benches += 1;
         }
+    }
+    summary.filtered_out = summary.total - summary.kept;
+    summary
+}
+
+// The names of the tests read from stdin, if any.
+fn selected_tests(opts: &TestOpts) -> Option<HashSet<&str>> {
+    opts.selected_tests.as_ref().map(|names| names.iter().map(|name| &**name).collect())
+}
+
+// Whether the filters of `opts` keep the test `test`.
+fn keeps_test(
+    opts: &TestOpts,
+    selected_tests: Option<&HashSet<&str>>,
+    test: &TestDescAndFn,
+) -> bool {
+    let desc = &test.desc;
+    let test_name = desc.name.as_slice();
+    let matches_filter = |filter: &str| match opts.filter_exact {
+        true => test_name == filter,
+        false => test_name.contains(filter),
     };
 
     // Remove tests that don't match the test filter
-    if !opts.filters.is_empty() {
-        filtered.retain(|test| opts.filters.iter().any(|filter| matches_filter(test, filter)));
+    if !opts.filters.is_empty() && !opts.filters.iter().any(|filter| matches_filter(filter)) {
+        return false;
+    }
+
+    // Remove tests that weren't read from stdin
+    if let Some(selected_tests) = selected_tests {
+        if !selected_tests.contains(test_name) {
+            return false;
+        }
     }
 
     // Skip tests that match any of the skip filters
-    filtered.retain(|test| !opts.skip.iter().any(|sf| matches_filter(test, sf)));
+    if opts.skip.iter().any(|sf| matches_filter(sf)) {
+        return false;
+    }
 
     // Excludes #[should_panic] tests
-    if opts.exclude_should_panic {
-        filtered.retain(|test| test.desc.should_panic == ShouldPanic::No);
+    if opts.exclude_should_panic && desc.should_panic != ShouldPanic::No {
+        return false;
+    }
+
+    // Only the #[should_panic] tests are run with `--only-should-panic`
+    if opts.only_should_panic && desc.should_panic == ShouldPanic::No {
+        return false;
+    }
+
+    // Only the benchmarks are run with `--bench-only`
+    if opts.bench_only && !matches!(test.testfn, StaticBenchFn(_) | DynBenchFn(_)) {
+        return false;
     }
 
+    // Only the ignored tests are run with `--ignored`
+    opts.run_ignored != RunIgnored::Only || desc.ignore
+}
+
+pub fn filter_tests(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> Vec<TestDescAndFn> {
+    let mut filtered = tests;
+    let selected_tests = selected_tests(opts);
+    filtered.retain(|test| keeps_test(opts, selected_tests.as_ref(), test));
+
     // maybe unignore tests
     match opts.run_ignored {
-        RunIgnored::Yes => {
-            filtered.iter_mut().for_each(|test| test.desc.ignore = false);
-        }
-        RunIgnored::Only => {
-            filtered.retain(|test| test.desc.ignore);
+        RunIgnored::Yes | RunIgnored::Only => {
             filtered.iter_mut().for_each(|test| test.desc.ignore = false);
         }
         RunIgnored::No => {}
@@ -427,9 +640,50 @@ pub fn filter_tests(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> Vec<TestDescA
     // Sort the tests alphabetically
     filtered.sort_by(|t1, t2| t1.desc.name.as_slice().cmp(t2.desc.name.as_slice()));
 
+    // Shuffle the tests, starting from the sorted order so that the seed is
+    // enough to reproduce the order
+    if let Some(seed) = opts.shuffle_seed {
+        shuffle_tests(seed, &mut filtered);
+    }
+
     filtered
 }
 
+/// Repeats each test `repeat` times for `--repeat`, suffixing its name with
+/// `#N`. Dynamic tests can't be copied, so they are run once, under their name.
+fn repeat_tests(tests: Vec<TestDescAndFn>, repeat: usize) -> Vec<TestDescAndFn> {
+    if repeat <= 1 {
+        return tests;
+    }
+
+    let mut repeated = Vec::with_capacity(tests.len() * repeat);
+    for test in tests {
+        if let StaticTestFn(_) | StaticBenchFn(_) = test.testfn {
+            for i in 1..=repeat {
+                let testfn = match test.testfn {
+                    StaticTestFn(f) => StaticTestFn(f),
+                    StaticBenchFn(f) => StaticBenchFn(f),
+                    _ => unreachable!(),
+                };
+                let name = DynTestName(format!("{}#{}", test.desc.name, i));
+                let desc = TestDesc { name, ..test.desc.clone() };
+                repeated.push(TestDescAndFn { desc, testfn });
+            }
+        } else {
+            repeated.push(test);
+        }
+    }
+    repeated
+}
+
+/// Returns the name of the test repeated by `--repeat`, without its `#N` suffix.
+pub(crate) fn repeated_test_name(name: &str) -> &str {
+    match name.rsplit_once('#') {
+        Some((base, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => name,
+    }
+}
+
 pub fn convert_benchmarks_to_tests(tests: Vec<TestDescAndFn>) -> Vec<TestDescAndFn> {
     // convert benchmarks to tests, if we're not benchmarking them
     tests
@@ -466,7 +720,11 @@ pub fn run_test(
         && !cfg!(target_os = "emscripten");
 
     if force_ignore || desc.ignore || ignore_because_no_process_support {
-        let message = CompletedTest::new(id, desc, TrIgnored, None, Vec::new());
+        let result = match desc.ignore_message() {
+            Some(msg) if desc.ignore => TrIgnoredMsg(msg.to_string()),
+            _ => TrIgnored,
+        };
+        let message = CompletedTest::new(id, desc, result, None, Vec::new());
         monitor_ch.send(message).unwrap();
         return None;
     }
@@ -476,38 +734,75 @@ struct TestRunOpts {
         pub nocapture: bool,
         pub concurrency: Concurrent,
         pub time: Option<time::TestTimeOptions>,
+        pub report_time: bool,
+        pub allowed_panic_messages: Vec<String>,
+        pub timeout_action: TimeoutAction,
+        pub kill_on_timeout: Option<Duration>,
+        pub retries: usize,
+        pub before_each: Option<TestHook>,
+        pub after_each: Option<TestHook>,
+        pub report_memory: bool,
     }
 
     fn run_test_inner(
         id: TestId,
         desc: TestDesc,
         monitor_ch: Sender<CompletedTest>,
-        testfn: Box<dyn FnOnce() + Send>,
+        mut testfn: Box<dyn FnMut() + Send>,
         opts: TestRunOpts,
     ) -> Option<thread::JoinHandle<()>> {
         let concurrency = opts.concurrency;
         let name = desc.name.clone();
 
-        let runtest = move || match opts.strategy {
-            RunStrategy::InProcess => run_test_in_process(
-                id,
-                desc,
-                opts.nocapture,
-                opts.time.is_some(),
-                testfn,
-                monitor_ch,
-                opts.time,
-            ),
-            RunStrategy::SpawnPrimary => spawn_test_subprocess(
-                id,
-                desc,
-                opts.nocapture,
-                opts.time.is_some(),
-                monitor_ch,
-                opts.time,
-            ),
+        let runtest = move || {
+            let max_attempts = opts.retries + 1;
+            for number in 1..=max_attempts {
+                let mut completed_test = match opts.strategy {
+                    RunStrategy::InProcess => run_test_in_process(
+                        id,
+                        desc.clone(),
+                        opts.nocapture,
+                        opts.report_time,
+                        &mut *testfn,
+                        opts.time,
+                        &opts.allowed_panic_messages,
+                        opts.kill_on_timeout,
+                        opts.before_each.as_ref(),
+                        opts.after_each.as_ref(),
+                    ),
+                    RunStrategy::SpawnPrimary => spawn_test_subprocess(
+                        id,
+                        desc.clone(),
+                        opts.nocapture,
+                        opts.report_time,
+                        opts.time,
+                        opts.timeout_action,
+                        opts.kill_on_timeout,
+                        opts.report_memory,
+                    ),
+                };
+                if opts.retries > 0 {
+                    completed_test.attempt = Some(Attempt { number, max: max_attempts });
+                }
+
+                // Only the last attempt is reported, so that a retried test
+                // is counted once.
+                let failed = matches!(completed_test.result, TrFailed | TrFailedMsg(_));
+                if !failed || number == max_attempts {
+                    monitor_ch.send(completed_test).unwrap();
+                    return;
+                }
+            }
         };
 
+        // A test that overflows its stack (or otherwise terminates abnormally)
+        // while running in-process takes the whole harness down with it, so it
+        // can't be reported as a failure. The thread running it is named after
+        // the test though, so the runtime's "thread '...' has overflowed its
+        // stack" message names the culprit; when tests run serially, it's the
+        // last test announced. With `-Z panic-abort-tests`, every test runs in
+        // its own process and abnormal terminations are reported for the test.
+
         // If the platform is single-threaded we're just going to run
         // the test synchronously, regardless of the concurrency
         // level.
@@ -532,33 +827,69 @@ fn run_test_inner(
         }
     }
 
-    let test_run_opts =
-        TestRunOpts { strategy, nocapture: opts.nocapture, concurrency, time: opts.time_options };
+    let test_run_opts = TestRunOpts {
+        strategy,
+        nocapture: opts.nocapture,
+        concurrency,
+        time: opts.time_options,
+        // The slowest tests can only be found if every test is timed.
+        report_time: opts.time_options.is_some() || opts.options.report_slowest.is_some(),
+        allowed_panic_messages: opts.allowed_panic_messages.clone(),
+        timeout_action: opts.timeout_action,
+        kill_on_timeout: opts.kill_on_timeout,
+        retries: opts.retries,
+        before_each: opts.before_each.clone(),
+        after_each: opts.after_each.clone(),
+        report_memory: opts.report_memory,
+    };
 
     match testfn {
         DynBenchFn(bencher) => {
             // Benchmarks aren't expected to panic, so we run them all in-process.
-            crate::bench::benchmark(id, desc, monitor_ch, opts.nocapture, |harness| {
-                bencher.run(harness)
-            });
+            crate::bench::benchmark(
+                id,
+                desc,
+                monitor_ch,
+                opts.nocapture,
+                &opts.bench_percentiles,
+                opts.bench_count_allocs,
+                opts.bench_warmup,
+                opts.bench_samples,
+                |harness| bencher.run(harness),
+            );
             None
         }
         StaticBenchFn(benchfn) => {
             // Benchmarks aren't expected to panic, so we run them all in-process.
-            crate::bench::benchmark(id, desc, monitor_ch, opts.nocapture, benchfn);
+            crate::bench::benchmark(
+                id,
+                desc,
+                monitor_ch,
+                opts.nocapture,
+                &opts.bench_percentiles,
+                opts.bench_count_allocs,
+                opts.bench_warmup,
+                opts.bench_samples,
+                benchfn,
+            );
             None
         }
         DynTestFn(f) => {
             match strategy {
                 RunStrategy::InProcess => (),
+                // Only the static tests can be found by name in a subprocess, so
+                // `--force-subprocess` leaves the dynamic ones in-process.
+                RunStrategy::SpawnPrimary if !opts.options.panic_abort => (),
                 _ => panic!("Cannot run dynamic test fn out-of-process"),
             };
+            // Dynamic tests can only run once, so they aren't retried.
+            let mut f = Some(f);
             run_test_inner(
                 id,
                 desc,
                 monitor_ch,
-                Box::new(move || __rust_begin_short_backtrace(f)),
-                test_run_opts,
+                Box::new(move || __rust_begin_short_backtrace(f.take().unwrap())),
+                TestRunOpts { strategy: RunStrategy::InProcess, retries: 0, ..test_run_opts },
             )
         }
         StaticTestFn(f) => run_test_inner(
@@ -585,33 +916,89 @@ fn run_test_in_process(
     desc: TestDesc,
     nocapture: bool,
     report_time: bool,
-    testfn: Box<dyn FnOnce() + Send>,
-    monitor_ch: Sender<CompletedTest>,
+    testfn: &mut dyn FnMut(),
     time_opts: Option<time::TestTimeOptions>,
-) {
-    // Buffer for capturing standard I/O
+    allowed_panic_messages: &[String],
+    kill_on_timeout: Option<Duration>,
+    before_each: Option<&TestHook>,
+    after_each: Option<&TestHook>,
+) -> CompletedTest {
+    // Buffers for capturing standard I/O
     let data = Arc::new(Mutex::new(Vec::new()));
+    let error_data = Arc::new(Mutex::new(Vec::new()));
 
     if !nocapture {
         io::set_output_capture(Some(data.clone()));
+        io::set_error_capture(Some(error_data.clone()));
     }
 
+    // Names the test if it exits the process, which can't be reported as its failure.
+    let running_test = RunningTest::new(desc.name.as_slice());
+    // The hooks run with the output captured, but aren't timed with the test.
+    // The test isn't run if `before_each` panics.
+    let before_each_failure = run_hook("before_each", before_each, &desc);
     let start = report_time.then(Instant::now);
-    let result = catch_unwind(AssertUnwindSafe(testfn));
+    // The thread of the test can't be killed, but the test still fails once it
+    // finishes, if it ran for too long.
+    let deadline = kill_on_timeout.map(|timeout| Instant::now() + timeout);
+    let result = match before_each_failure {
+        None => catch_unwind(AssertUnwindSafe(testfn)),
+        Some(_) => Ok(()),
+    };
+    let exceeded_timeout = deadline.map_or(false, |deadline| Instant::now() > deadline);
     let exec_time = start.map(|start| {
         let duration = start.elapsed();
         TestExecTime(duration)
     });
+    let after_each_failure = run_hook("after_each", after_each, &desc);
 
     io::set_output_capture(None);
+    io::set_error_capture(None);
+    drop(running_test);
 
+    let mut allowed_panic_note = None;
     let test_result = match result {
         Ok(()) => calc_result(&desc, Ok(()), &time_opts, &exec_time),
-        Err(e) => calc_result(&desc, Err(e.as_ref()), &time_opts, &exec_time),
+        Err(e) => {
+            let test_result = calc_result(&desc, Err(e.as_ref()), &time_opts, &exec_time);
+            match allowed_panic_message(&test_result, e.as_ref(), allowed_panic_messages) {
+                Some(msg) => {
+                    allowed_panic_note =
+                        Some(format!("note: test passed, its panic message contains `{}`\n", msg));
+                    TrOk
+                }
+                None => test_result,
+            }
+        }
     };
-    let stdout = data.lock().unwrap_or_else(|e| e.into_inner()).to_vec();
-    let message = CompletedTest::new(id, desc, test_result, exec_time, stdout);
-    monitor_ch.send(message).unwrap();
+    let test_result = match (test_result, kill_on_timeout) {
+        (TrOk, Some(timeout)) if exceeded_timeout => TrFailedMsg(exceeded_timeout_msg(timeout)),
+        (test_result, _) => test_result,
+    };
+    // A panic in `after_each` only fails the tests that would have passed.
+    let test_result = match (before_each_failure, after_each_failure, test_result) {
+        (Some(msg), _, _) | (None, Some(msg), TrOk) => TrFailedMsg(msg),
+        (_, _, test_result) => test_result,
+    };
+    let mut stdout = data.lock().unwrap_or_else(|e| e.into_inner()).to_vec();
+    if let Some(note) = allowed_panic_note {
+        stdout.extend_from_slice(note.as_bytes());
+    }
+    let mut completed_test = CompletedTest::new(id, desc, test_result, exec_time, stdout);
+    completed_test.stderr = error_data.lock().unwrap_or_else(|e| e.into_inner()).to_vec();
+    completed_test
+}
+
+// Runs `hook`, if any, returning the message to fail the test with if it panics.
+fn run_hook(name: &str, hook: Option<&TestHook>, desc: &TestDesc) -> Option<String> {
+    let hook = hook?;
+    let err = catch_unwind(AssertUnwindSafe(|| (hook.0)(desc))).err()?;
+    let panic_str =
+        err.downcast_ref::<String>().map(|e| &**e).or_else(|| err.downcast_ref::<&str>().copied());
+    Some(match panic_str {
+        Some(panic_str) => format!("{} hook panicked: {}", name, panic_str),
+        None => format!("{} hook panicked", name),
+    })
 }
 
 fn spawn_test_subprocess(
@@ -619,56 +1006,238 @@ fn spawn_test_subprocess(
     desc: TestDesc,
     nocapture: bool,
     report_time: bool,
-    monitor_ch: Sender<CompletedTest>,
     time_opts: Option<time::TestTimeOptions>,
-) {
-    let (result, test_output, exec_time) = (|| {
+    timeout_action: TimeoutAction,
+    kill_on_timeout: Option<Duration>,
+    report_memory: bool,
+) -> CompletedTest {
+    let (result, test_output, test_error_output, exec_time, max_rss) = (|| {
         let args = env::args().collect::<Vec<_>>();
-        let current_exe = &args[0];
-
-        let mut command = Command::new(current_exe);
-        command.env(SECONDARY_TEST_INVOKER_VAR, desc.name.as_slice());
-        if nocapture {
-            command.stdout(process::Stdio::inherit());
-            command.stderr(process::Stdio::inherit());
+        let mut command = test_subprocess_command(&args[0], &desc, nocapture);
+        if timeout_action == TimeoutAction::Backtrace {
+            command.env(TIMEOUT_BACKTRACE_VAR, "1");
         }
 
         let start = report_time.then(Instant::now);
-        let output = match command.output() {
-            Ok(out) => out,
-            Err(e) => {
-                let err = format!("Failed to spawn {} as child for test: {:?}", args[0], e);
-                return (TrFailed, err.into_bytes(), None);
+        let (timeout_action, timeout) = match kill_on_timeout {
+            Some(timeout) if timeout_action == TimeoutAction::Backtrace => {
+                (TimeoutAction::Backtrace, timeout)
             }
+            Some(timeout) => (TimeoutAction::Kill, timeout),
+            None => (timeout_action, Duration::from_secs(time::TEST_WARN_TIMEOUT_S)),
         };
+        let (output, killed, max_rss) =
+            match run_test_subprocess(command, timeout_action, timeout, report_memory) {
+                Ok(out) => out,
+                Err(e) => {
+                    let err = format!("Failed to spawn {} as child for test: {:?}", args[0], e);
+                    return (TrFailed, Vec::new(), err.into_bytes(), None, None);
+                }
+            };
         let exec_time = start.map(|start| {
             let duration = start.elapsed();
             TestExecTime(duration)
         });
 
         let std::process::Output { stdout, stderr, status } = output;
-        let mut test_output = stdout;
-        formatters::write_stderr_delimiter(&mut test_output, &desc.name);
-        test_output.extend_from_slice(&stderr);
-
-        let result = match (|| -> Result<TestResult, String> {
-            let exit_code = get_exit_code(status)?;
-            Ok(get_result_from_exit_code(&desc, exit_code, &time_opts, &exec_time))
-        })() {
-            Ok(r) => r,
-            Err(e) => {
-                write!(&mut test_output, "Unexpected error: {}", e).unwrap();
-                TrFailed
+
+        let result = if killed {
+            match kill_on_timeout {
+                Some(timeout) => TrFailedMsg(exceeded_timeout_msg(timeout)),
+                None => TrFailedMsg(format!(
+                    "killed after running for over {} seconds",
+                    time::TEST_WARN_TIMEOUT_S
+                )),
             }
+        } else {
+            get_result_from_exit_status(&desc, status, &time_opts, &exec_time)
         };
 
-        (result, test_output, exec_time)
+        (result, stdout, stderr, exec_time, max_rss)
     })();
 
-    let message = CompletedTest::new(id, desc, result, exec_time, test_output);
-    monitor_ch.send(message).unwrap();
+    let mut completed_test = CompletedTest::new(id, desc, result, exec_time, test_output);
+    completed_test.stderr = test_error_output;
+    completed_test.max_rss = max_rss;
+    completed_test
 }
 
+fn exceeded_timeout_msg(timeout: Duration) -> String {
+    format!("test exceeded timeout of {} seconds", timeout.as_secs_f64())
+}
+
+/// Builds the command that re-runs `current_exe` to execute only the test `desc`.
+fn test_subprocess_command(current_exe: &str, desc: &TestDesc, nocapture: bool) -> Command {
+    let mut command = Command::new(current_exe);
+    command.env(SECONDARY_TEST_INVOKER_VAR, repeated_test_name(desc.name.as_slice()));
+    #[cfg(not(bootstrap))]
+    command.envs(desc.env.iter().copied());
+    if nocapture {
+        command.stdout(process::Stdio::inherit());
+        command.stderr(process::Stdio::inherit());
+    } else {
+        command.stdout(process::Stdio::piped());
+        command.stderr(process::Stdio::piped());
+    }
+    command
+}
+
+/// Runs a test subprocess to completion, unless `timeout_action` asks to kill
+/// it once it has been running for longer than `timeout`. Returns its output,
+/// whether it was killed, and its peak memory usage, if `report_memory` is set
+/// and the platform reports it.
+fn run_test_subprocess(
+    mut command: Command,
+    timeout_action: TimeoutAction,
+    timeout: Duration,
+    report_memory: bool,
+) -> io::Result<(process::Output, bool, Option<u64>)> {
+    if timeout_action == TimeoutAction::Report && !report_memory {
+        return command.output().map(|output| (output, false, None));
+    }
+
+    let mut child = command.spawn()?;
+    // The pipes must be drained while waiting, lest the child blocks on a full pipe.
+    let stdout = read_pipe_in_background(child.stdout.take());
+    let stderr = read_pipe_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let mut killed = false;
+    let (status, max_rss) = loop {
+        if let Some(exited) = wait_test_subprocess(&mut child, report_memory, false)? {
+            break exited;
+        }
+        if timeout_action != TimeoutAction::Report && Instant::now() >= deadline {
+            if timeout_action == TimeoutAction::Backtrace {
+                request_backtrace(&mut child)?;
+            }
+            // The child may have exited in the meantime, so this can fail.
+            let _ = child.kill();
+            killed = true;
+            break wait_test_subprocess(&mut child, report_memory, true)?.unwrap();
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok((process::Output { status, stdout, stderr }, killed, max_rss))
+}
+
+// Waits for the test subprocess to exit, or only checks whether it has exited
+// unless `block` is set, like `Child::wait` and `Child::try_wait`. With
+// `report_memory`, the peak resident set size of the subprocess, in bytes, is
+// also returned.
+#[cfg(target_os = "linux")]
+fn wait_test_subprocess(
+    child: &mut process::Child,
+    report_memory: bool,
+    block: bool,
+) -> io::Result<Option<(process::ExitStatus, Option<u64>)>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    if !report_memory {
+        return wait_test_subprocess_without_memory(child, block);
+    }
+
+    let mut status = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = child.id() as libc::pid_t;
+    let options = if block { 0 } else { libc::WNOHANG };
+    loop {
+        match unsafe { libc::wait4(pid, &mut status, options, &mut rusage) } {
+            0 => return Ok(None),
+            -1 => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    // Interrupted by a signal before the child changed state.
+                    Some(libc::EINTR) => continue,
+                    // `request_backtrace` may have reaped the child already,
+                    // leaving its status to `Child`, and its memory usage unknown.
+                    Some(libc::ECHILD) => return wait_test_subprocess_without_memory(child, block),
+                    _ => return Err(err),
+                }
+            }
+            // `ru_maxrss` is in kilobytes.
+            _ => {
+                let max_rss = rusage.ru_maxrss as u64 * 1024;
+                return Ok(Some((process::ExitStatus::from_raw(status), Some(max_rss))));
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn wait_test_subprocess(
+    child: &mut process::Child,
+    _report_memory: bool,
+    block: bool,
+) -> io::Result<Option<(process::ExitStatus, Option<u64>)>> {
+    wait_test_subprocess_without_memory(child, block)
+}
+
+fn wait_test_subprocess_without_memory(
+    child: &mut process::Child,
+    block: bool,
+) -> io::Result<Option<(process::ExitStatus, Option<u64>)>> {
+    let status = if block { Some(child.wait()?) } else { child.try_wait()? };
+    Ok(status.map(|status| (status, None)))
+}
+
+fn read_pipe_in_background<R: io::Read + Send + 'static>(
+    pipe: Option<R>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            // Keep what could be read before an error.
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+// Sends `SIGQUIT` to a test subprocess, to which it responds by printing its
+// backtrace and exiting (see `print_backtrace_on_sigquit`), and gives it a
+// second to do so. The subprocess is killed afterwards in any case.
+#[cfg(unix)]
+fn request_backtrace(child: &mut process::Child) -> io::Result<()> {
+    if unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGQUIT) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while Instant::now() < deadline && child.try_wait()?.is_none() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn request_backtrace(_child: &mut process::Child) -> io::Result<()> {
+    Ok(())
+}
+
+// The backtrace of the test can only be captured on its own thread, so it's
+// captured from the handler, which is usually run by the main thread, the one
+// running the test. This isn't async-signal-safe: if the test was interrupted
+// while holding a lock the printing needs (e.g. the allocator's, or the one of
+// stderr), the handler blocks, and the subprocess is killed without printing
+// anything, like with `--timeout-action kill`.
+#[cfg(unix)]
+fn print_backtrace_on_sigquit() {
+    extern "C" fn print_backtrace(_signal: libc::c_int) {
+        eprintln!("test timed out, backtrace:\n{}", std::backtrace::Backtrace::force_capture());
+        unsafe { libc::_exit(ERROR_EXIT_CODE) }
+    }
+
+    unsafe {
+        libc::signal(libc::SIGQUIT, print_backtrace as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn print_backtrace_on_sigquit() {}
+
 fn run_test_in_spawned_subprocess(desc: TestDesc, testfn: Box<dyn FnOnce() + Send>) -> ! {
     let builtin_panic_hook = panic::take_hook();
     let record_result = Arc::new(move |panic_info: Option<&'_ PanicInfo<'_>>| {
@@ -1,5 +1,7 @@
 //! Enums denoting options for test execution.
 
+use super::bench::DEFAULT_BENCH_NOISE_THRESHOLD;
+
 /// Whether to execute tests concurrently or not
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Concurrent {
@@ -20,6 +22,8 @@ pub enum ShouldPanic {
     No,
     Yes,
     YesWithMessage(&'static str),
+    /// The panic message must match this regex, see `helpers::regex`.
+    YesWithRegex(&'static str),
 }
 
 /// Whether should console output be colored or not
@@ -28,6 +32,43 @@ pub enum ColorConfig {
     AutoColor,
     AlwaysColor,
     NeverColor,
+    /// Colorize only the failures, leaving the rest of the output plain
+    ErrorsOnlyColor,
+}
+
+/// Colors of the console output, when it's colored, as numbers of the ANSI
+/// terminal colors: 0 to 7 for black, red, green, yellow, blue, magenta, cyan
+/// and white, and 8 to 15 for their bright variants.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ColorTheme {
+    /// Passed tests and test runs
+    pub ok: u32,
+    /// Failed tests and test runs
+    pub failed: u32,
+    /// Ignored tests
+    pub ignored: u32,
+    /// Allowed failures, slow tests and noisy benchmarks
+    pub warning: u32,
+    /// Benchmark results
+    pub bench: u32,
+}
+
+impl ColorTheme {
+    /// Green passes, red failures, yellow ignored tests and warnings, and cyan
+    /// benchmarks.
+    pub const DEFAULT: ColorTheme = ColorTheme {
+        ok: term::color::GREEN,
+        failed: term::color::RED,
+        ignored: term::color::YELLOW,
+        warning: term::color::YELLOW,
+        bench: term::color::CYAN,
+    };
+}
+
+impl Default for ColorTheme {
+    fn default() -> ColorTheme {
+        ColorTheme::DEFAULT
+    }
 }
 
 /// Format of the test results output
@@ -43,6 +84,15 @@ pub enum OutputFormat {
     Junit,
 }
 
+/// Format of the results written to the `--logfile`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogfileFormat {
+    /// One line per test
+    Plain,
+    /// JUnit XML document
+    Junit,
+}
+
 /// Whether ignored test should be run or not
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RunIgnored {
@@ -52,6 +102,19 @@ pub enum RunIgnored {
     Only,
 }
 
+/// What to do with a test running for longer than the timeout
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimeoutAction {
+    /// Report that the test is running for too long, and let it finish
+    Report,
+    /// Kill the process of the test, which fails (only for tests running in
+    /// a subprocess)
+    Kill,
+    /// Make the process of the test print its backtrace (only on Unix), then
+    /// kill it like `Kill`
+    Backtrace,
+}
+
 #[derive(Clone, Copy)]
 pub enum RunStrategy {
     /// Runs the test in the current process, and sends the result back over the
@@ -64,17 +127,45 @@ pub enum RunStrategy {
     SpawnPrimary,
 }
 
+/// Default value of `--max-output-warn`, in bytes.
+pub const DEFAULT_MAX_OUTPUT_WARN: usize = 1024 * 1024;
+
 /// Options for the test run defined by the caller (instead of CLI arguments).
 /// In case we want to add other options as well, just add them in this struct.
 #[derive(Copy, Clone, Debug)]
 pub struct Options {
     pub display_output: bool,
     pub panic_abort: bool,
+    pub show_percentiles: bool,
+    pub time_precision: Option<usize>,
+    pub print_rerun: bool,
+    /// Percentage of the median above which the deviation of a benchmark is
+    /// reported as high variance.
+    pub bench_noise_threshold: f64,
+    pub report_output_size: bool,
+    /// Size in bytes above which the captured output of a test is reported
+    /// with `report_output_size`.
+    pub max_output_warn: usize,
+    /// Number of the slowest tests listed at the end of the run.
+    pub report_slowest: Option<usize>,
+    /// Colors of the console output, when it's colored.
+    pub color_theme: ColorTheme,
 }
 
 impl Options {
     pub fn new() -> Options {
-        Options { display_output: false, panic_abort: false }
+        Options {
+            display_output: false,
+            panic_abort: false,
+            show_percentiles: false,
+            time_precision: None,
+            print_rerun: false,
+            bench_noise_threshold: DEFAULT_BENCH_NOISE_THRESHOLD,
+            report_output_size: false,
+            max_output_warn: DEFAULT_MAX_OUTPUT_WARN,
+            report_slowest: None,
+            color_theme: ColorTheme::DEFAULT,
+        }
     }
 
     pub fn display_output(mut self, display_output: bool) -> Options {
@@ -86,4 +177,44 @@ pub fn panic_abort(mut self, panic_abort: bool) -> Options {
         self.panic_abort = panic_abort;
         self
     }
+
+    pub fn show_percentiles(mut self, show_percentiles: bool) -> Options {
+        self.show_percentiles = show_percentiles;
+        self
+    }
+
+    pub fn time_precision(mut self, time_precision: Option<usize>) -> Options {
+        self.time_precision = time_precision;
+        self
+    }
+
+    pub fn print_rerun(mut self, print_rerun: bool) -> Options {
+        self.print_rerun = print_rerun;
+        self
+    }
+
+    pub fn bench_noise_threshold(mut self, bench_noise_threshold: f64) -> Options {
+        self.bench_noise_threshold = bench_noise_threshold;
+        self
+    }
+
+    pub fn report_output_size(mut self, report_output_size: bool) -> Options {
+        self.report_output_size = report_output_size;
+        self
+    }
+
+    pub fn max_output_warn(mut self, max_output_warn: usize) -> Options {
+        self.max_output_warn = max_output_warn;
+        self
+    }
+
+    pub fn report_slowest(mut self, report_slowest: Option<usize>) -> Options {
+        self.report_slowest = report_slowest;
+        self
+    }
+
+    pub fn color_theme(mut self, color_theme: ColorTheme) -> Options {
+        self.color_theme = color_theme;
+        self
+    }
 }
@@ -0,0 +1,27 @@
+//! Side-channel reporting of the test events.
+
+use std::fmt;
+use std::io;
+
+use super::event::TestEvent;
+
+/// Receives the events of a test run in addition to the output formatter.
+///
+/// Unlike the output formatter, which owns the standard output, reporters are
+/// meant for telemetry (pushing metrics, recording results in a database,
+/// etc.), and any number of them can be given to
+/// `run_tests_console_with_reporters`.
+///
+/// Each event is handed to the output formatter first, and then to the
+/// reporters in the order they were registered. An error returned by a
+/// reporter is logged to the standard error, but doesn't abort the test run
+/// nor prevent the other reporters from receiving the event.
+pub trait Reporter {
+    fn on_event(&mut self, event: &TestEvent) -> io::Result<()>;
+}
+
+impl fmt::Debug for dyn Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reporter")
+    }
+}
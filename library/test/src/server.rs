@@ -0,0 +1,204 @@
+//! Module serving test runs over stdin/stdout for `--server`, so that IDEs can
+//! list and run tests without spawning the test binary for each action.
+//!
+//! The protocol is line-delimited JSON: each line read is a command, among
+//!
+//! * `{ "command": "list" }`, answered by `{ "type": "list", "tests": [...] }`
+//!   with the names of the tests;
+//! * `{ "command": "run", "name": "<test name>" }`, running the test with
+//!   exactly that name;
+//! * `{ "command": "run-all" }`, running all the tests.
+//!
+//! Running tests streams back the same events as `--format json`, ending with
+//! the `suite` event. Errors are answered by `{ "type": "error", "message":
+//! "..." }`. A single client is served, until stdin is closed.
+
+use std::io::{self, prelude::*};
+use std::iter::Peekable;
+use std::mem;
+use std::str::Chars;
+
+use super::{
+    cli::TestOpts,
+    console::{on_test_event, ConsoleTestState, OutputLocation},
+    filter_tests,
+    formatters::{EscapedString, JsonFormatter, OutputFormatter},
+    run_tests,
+    types::{TestDescAndFn, TestFn},
+};
+
+enum Request {
+    List,
+    Run(String),
+    RunAll,
+}
+
+/// Serves the commands read from `input` until it is exhausted, writing the
+/// answers to `output`.
+pub(crate) fn serve<R: BufRead, W: Write>(
+    opts: &TestOpts,
+    tests: Vec<TestDescAndFn>,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    let mut tests = filter_tests(opts, tests);
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_request(&line) {
+            Ok(Request::List) => {
+                let names = tests
+                    .iter()
+                    .map(|test| format!(r#""{}""#, EscapedString(test.desc.name.as_slice())))
+                    .collect::<Vec<_>>();
+                writeln!(output, r#"{{ "type": "list", "tests": [{}] }}"#, names.join(", "))?;
+            }
+            Ok(Request::Run(name)) => {
+                match tests.iter_mut().find(|test| test.desc.name.as_slice() == name) {
+                    Some(test) => {
+                        let test = take_runnable(test);
+                        run(opts, vec![test], &mut output)?;
+                    }
+                    None => write_error(&mut output, &format!("no test named `{}`", name))?,
+                }
+            }
+            Ok(Request::RunAll) => {
+                let tests = tests.iter_mut().map(take_runnable).collect();
+                run(opts, tests, &mut output)?;
+            }
+            Err(msg) => write_error(&mut output, &msg)?,
+        }
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+fn run<W: Write>(opts: &TestOpts, tests: Vec<TestDescAndFn>, output: W) -> io::Result<()> {
+    let mut out = JsonFormatter::new(OutputLocation::Raw(output), opts.hash_names);
+    let mut st = ConsoleTestState::new(opts)?;
+
+    run_tests(opts, tests, |x| on_test_event(&x, &mut st, &mut out, &mut []))?;
+
+    out.write_run_finish(&st)?;
+    Ok(())
+}
+
+// Returns a test that can be run, leaving one that can be run again in place of
+// `test`. Dynamic tests can't be copied, so they can only run once.
+fn take_runnable(test: &mut TestDescAndFn) -> TestDescAndFn {
+    fn already_run() {
+        panic!("dynamic tests can only be run once by the test server");
+    }
+
+    let testfn = match test.testfn {
+        TestFn::StaticTestFn(f) => TestFn::StaticTestFn(f),
+        TestFn::StaticBenchFn(f) => TestFn::StaticBenchFn(f),
+        _ => mem::replace(&mut test.testfn, TestFn::StaticTestFn(already_run)),
+    };
+    TestDescAndFn { desc: test.desc.clone(), testfn }
+}
+
+fn write_error<W: Write>(output: &mut W, msg: &str) -> io::Result<()> {
+    writeln!(output, r#"{{ "type": "error", "message": "{}" }}"#, EscapedString(msg))
+}
+
+fn parse_request(line: &str) -> Result<Request, String> {
+    let fields = parse_object(line)?;
+    let field = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+    match field("command") {
+        Some("list") => Ok(Request::List),
+        Some("run") => match field("name") {
+            Some(name) => Ok(Request::Run(name.to_string())),
+            None => Err("missing test name for the `run` command".to_string()),
+        },
+        Some("run-all") => Ok(Request::RunAll),
+        Some(command) => Err(format!("unknown command `{}`", command)),
+        None => Err("missing command".to_string()),
+    }
+}
+
+// Parses a JSON object whose values are all strings, which is enough for the
+// commands.
+fn parse_object(s: &str) -> Result<Vec<(String, String)>, String> {
+    let invalid = || format!("invalid command `{}`", s);
+    let mut chars = s.chars().peekable();
+    let mut fields = Vec::new();
+
+    skip_whitespace(&mut chars);
+    if chars.next() != Some('{') {
+        return Err(invalid());
+    }
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            let key = parse_string(&mut chars).ok_or_else(invalid)?;
+            skip_whitespace(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(invalid());
+            }
+            skip_whitespace(&mut chars);
+            let value = parse_string(&mut chars).ok_or_else(invalid)?;
+            fields.push((key, value));
+
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => skip_whitespace(&mut chars),
+                Some('}') => break,
+                _ => return Err(invalid()),
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+    if chars.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok(fields)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars<'_>>) -> Option<String> {
+    if chars.next() != Some('"') {
+        return None;
+    }
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => {
+                let c = match chars.next()? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let hex = chars.by_ref().take(4).collect::<String>();
+                        let code = u32::from_str_radix(&hex, 16).ok()?;
+                        // Surrogate pairs aren't supported, as test names hardly need them.
+                        char::from_u32(code)?
+                    }
+                    _ => return None,
+                };
+                string.push(c);
+            }
+            c => string.push(c),
+        }
+    }
+}
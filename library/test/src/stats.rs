@@ -83,6 +83,11 @@ pub trait Stats {
     /// See: <https://en.wikipedia.org/wiki/Percentile>
     fn percentile(&self, pct: f64) -> f64;
 
+    /// Percentiles of the sample for each value in `pcts`, returned in the same order. This
+    /// function sorts the sample only once, but is otherwise equivalent to calling `percentile`
+    /// for every element of `pcts`.
+    fn percentiles(&self, pcts: &[f64]) -> Vec<f64>;
+
     /// Quartiles of the sample: three values that divide the sample into four equal groups, each
     /// with 1/4 of the data. The middle value is the median. See `median` and `percentile`. This
     /// function may calculate the 3 quartiles more efficiently than 3 calls to `percentile`, but
@@ -238,6 +243,12 @@ fn percentile(&self, pct: f64) -> f64 {
         percentile_of_sorted(&tmp, pct)
     }
 
+    fn percentiles(&self, pcts: &[f64]) -> Vec<f64> {
+        let mut tmp = self.to_vec();
+        local_sort(&mut tmp);
+        pcts.iter().map(|&pct| percentile_of_sorted(&tmp, pct)).collect()
+    }
+
     fn quartiles(&self) -> (f64, f64, f64) {
         let mut tmp = self.to_vec();
         local_sort(&mut tmp);
@@ -571,6 +571,26 @@ fn test_sum_f64_between_ints_that_sum_to_0() {
     assert_eq!([1e30f64, 1.2f64, -1e30f64].sum(), 1.2);
 }
 
+#[test]
+fn test_percentiles() {
+    // 0.0, 1.0, ..., 100.0 in reverse order, so that every integral percentile
+    // is exactly representable and the input has to be sorted first.
+    let samples = (0..=100).rev().map(|i| i as f64).collect::<Vec<_>>();
+    let pcts = [0.0, 50.0, 90.0, 99.0, 100.0];
+    assert_eq!(samples.percentiles(&pcts), vec![0.0, 50.0, 90.0, 99.0, 100.0]);
+    for &pct in &pcts {
+        assert_eq!(samples.percentile(pct), pct);
+    }
+
+    // Values between two ranks are linearly interpolated.
+    let samples = [4.0, 1.0, 3.0, 2.0];
+    assert_eq!(samples.percentiles(&[0.0, 50.0, 100.0]), vec![1.0, 2.5, 4.0]);
+
+    // A single sample is every percentile.
+    assert_eq!([7.0].percentiles(&[0.0, 99.0, 100.0]), vec![7.0, 7.0, 7.0]);
+    assert!(samples.percentiles(&[]).is_empty());
+}
+
 #[bench]
 pub fn sum_three_items(b: &mut Bencher) {
     b.iter(|| {
@@ -1,6 +1,10 @@
 use std::any::Any;
+use std::borrow::Cow;
+use std::process::ExitStatus;
 
 use super::bench::BenchSamples;
+use super::helpers::exit_code::get_exit_code;
+use super::helpers::regex::Regex;
 use super::options::ShouldPanic;
 use super::time;
 use super::types::TestDesc;
@@ -19,11 +23,15 @@ pub enum TestResult {
     TrFailed,
     TrFailedMsg(String),
     TrIgnored,
+    TrIgnoredMsg(String),
     TrAllowedFail,
     TrBench(BenchSamples),
     TrTimedFail,
 }
 
+/// Payload the test unwinds with when it calls `test::skip`.
+pub(crate) struct SkipTest(pub String);
+
 /// Creates a `TestResult` depending on the raw result of test execution
 /// and associated data.
 pub fn calc_result<'a>(
@@ -32,15 +40,19 @@ pub fn calc_result<'a>(
     time_opts: &Option<time::TestTimeOptions>,
     exec_time: &Option<time::TestExecTime>,
 ) -> TestResult {
+    // A test that skipped itself is ignored, whatever it was expected to do.
+    if let Err(err) = task_result {
+        if let Some(SkipTest(reason)) = err.downcast_ref::<SkipTest>() {
+            return TestResult::TrIgnoredMsg(reason.clone());
+        }
+    }
+
     let result = match (&desc.should_panic, task_result) {
         (&ShouldPanic::No, Ok(())) | (&ShouldPanic::Yes, Err(_)) => TestResult::TrOk,
-        (&ShouldPanic::YesWithMessage(msg), Err(ref err)) => {
-            let maybe_panic_str = err
-                .downcast_ref::<String>()
-                .map(|e| &**e)
-                .or_else(|| err.downcast_ref::<&'static str>().copied());
+        (&ShouldPanic::YesWithMessage(msg), Err(err)) => {
+            let maybe_panic_str = panic_message(err);
 
-            if maybe_panic_str.map(|e| e.contains(msg)).unwrap_or(false) {
+            if maybe_panic_str.as_ref().map(|e| e.contains(msg)).unwrap_or(false) {
                 TestResult::TrOk
             } else if desc.allow_fail {
                 TestResult::TrAllowedFail
@@ -53,14 +65,46 @@ pub fn calc_result<'a>(
                 ))
             } else {
                 TestResult::TrFailedMsg(format!(
-                    r#"expected panic with string value,
- found non-string value: `{:?}`
-     expected substring: `{:?}`"#,
-                    (**err).type_id(),
+                    r#"panic payload was not a string, cannot check expected message
+    payload type id: `{:?}`
+ expected substring: `{:?}`"#,
+                    (*err).type_id(),
                     msg
                 ))
             }
         }
+        (&ShouldPanic::YesWithRegex(pattern), Err(err)) => {
+            let maybe_panic_str = panic_message(err);
+
+            match Regex::new(pattern) {
+                Err(e) => invalid_regex(pattern, e),
+                Ok(regex)
+                    if maybe_panic_str.as_ref().map(|e| regex.is_match(e)).unwrap_or(false) =>
+                {
+                    TestResult::TrOk
+                }
+                Ok(_) if desc.allow_fail => TestResult::TrAllowedFail,
+                Ok(_) => match maybe_panic_str {
+                    Some(panic_str) => TestResult::TrFailedMsg(format!(
+                        r#"panic did not match expected regex
+ panic message: `{:?}`,
+expected regex: `{:?}`"#,
+                        panic_str, pattern
+                    )),
+                    None => TestResult::TrFailedMsg(format!(
+                        r#"panic payload was not a string, cannot check expected message
+payload type id: `{:?}`
+ expected regex: `{:?}`"#,
+                        (*err).type_id(),
+                        pattern
+                    )),
+                },
+            }
+        }
+        (&ShouldPanic::YesWithRegex(pattern), Ok(())) => match Regex::new(pattern) {
+            Err(e) => invalid_regex(pattern, e),
+            Ok(_) => TestResult::TrFailedMsg("test did not panic as expected".to_string()),
+        },
         (&ShouldPanic::Yes, Ok(())) | (&ShouldPanic::YesWithMessage(_), Ok(())) => {
             TestResult::TrFailedMsg("test did not panic as expected".to_string())
         }
@@ -83,6 +127,72 @@ pub fn calc_result<'a>(
     result
 }
 
+/// Returns the message of a panic `payload`, if it can be displayed as one:
+/// a string, or a number, `char` or `bool` given to `std::panic::panic_any`.
+///
+/// Other payloads can't be inspected, and only their `TypeId` is known.
+pub(crate) fn panic_message<'a>(payload: &'a (dyn Any + 'static + Send)) -> Option<Cow<'a, str>> {
+    if let Some(s) = payload.downcast_ref::<String>() {
+        return Some(Cow::Borrowed(s));
+    }
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        return Some(Cow::Borrowed(s));
+    }
+    if let Some(s) = payload.downcast_ref::<Box<str>>() {
+        return Some(Cow::Borrowed(s));
+    }
+    if let Some(s) = payload.downcast_ref::<Cow<'static, str>>() {
+        return Some(Cow::Borrowed(s));
+    }
+    macro_rules! display {
+        ($($ty:ty),*) => {
+            $(if let Some(v) = payload.downcast_ref::<$ty>() {
+                return Some(Cow::Owned(v.to_string()));
+            })*
+        };
+    }
+    display!(char, bool, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+    None
+}
+
+// Fails a test whose `#[should_panic(expected_regex = "...")]` can't be compiled.
+fn invalid_regex(pattern: &str, error: String) -> TestResult {
+    TestResult::TrFailedMsg(format!("invalid should_panic regex `{}`: {}", pattern, error))
+}
+
+/// Returns the first of the `allowed` messages contained in the message of the
+/// panic that made the test fail with `result`, if any (see
+/// `--allow-panic-message`).
+pub fn allowed_panic_message<'a>(
+    result: &TestResult,
+    payload: &(dyn Any + 'static + Send),
+    allowed: &'a [String],
+) -> Option<&'a str> {
+    match result {
+        TestResult::TrFailed | TestResult::TrFailedMsg(_) => {}
+        _ => return None,
+    }
+    let panic_str = panic_message(payload)?;
+
+    allowed.iter().map(|msg| &**msg).find(|msg| panic_str.contains(msg))
+}
+
+/// Creates a `TestResult` depending on how the test subprocess terminated.
+///
+/// A subprocess that didn't exit with a code, e.g. because it was killed by a
+/// signal after overflowing its stack, is reported as failed with the reason.
+pub fn get_result_from_exit_status(
+    desc: &TestDesc,
+    status: ExitStatus,
+    time_opts: &Option<time::TestTimeOptions>,
+    exec_time: &Option<time::TestExecTime>,
+) -> TestResult {
+    match get_exit_code(status) {
+        Ok(code) => get_result_from_exit_code(desc, code, time_opts, exec_time),
+        Err(e) => TestResult::TrFailedMsg(e),
+    }
+}
+
 /// Creates a `TestResult` depending on the exit code of test subprocess.
 pub fn get_result_from_exit_code(
     desc: &TestDesc,
@@ -1,9 +1,9 @@
 use super::*;
 
 use crate::{
-    bench::Bencher,
+    bench::{fmt_bench_samples_with_precision, BenchSamples, Bencher},
     console::OutputLocation,
-    formatters::PrettyFormatter,
+    formatters::{PrettyFormatter, TerseFormatter},
     options::OutputFormat,
     test::{
         filter_tests,
@@ -25,8 +25,10 @@
         // ShouldPanic, StaticTestName, TestDesc, TestDescAndFn, TestOpts, TestTimeOptions,
         // TestType, TrFailedMsg, TrIgnored, TrOk,
     },
-    time::{TestTimeOptions, TimeThreshold},
+    time::{self, TestSuiteExecTime, TestTimeOptions, TimeThreshold},
 };
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::time::Duration;
 
@@ -34,20 +36,47 @@ impl TestOpts {
     fn new() -> TestOpts {
         TestOpts {
             list: false,
+            dump_opts: false,
+            server: false,
             filters: vec![],
             filter_exact: false,
+            tests_from_stdin: false,
+            selected_tests: None,
+            allow_unknown_tests: false,
             force_run_in_process: false,
+            force_subprocess: false,
             exclude_should_panic: false,
+            only_should_panic: false,
             run_ignored: RunIgnored::No,
             run_tests: false,
             bench_benchmarks: false,
+            bench_only: false,
             logfile: None,
+            logfile_format: LogfileFormat::Plain,
             nocapture: false,
             color: AutoColor,
             format: OutputFormat::Pretty,
+            terse_flush_interval: DEFAULT_TERSE_FLUSH_INTERVAL,
             test_threads: None,
             skip: vec![],
+            allowed_panic_messages: vec![],
+            timeout_action: TimeoutAction::Report,
             time_options: None,
+            bench_percentiles: vec![],
+            bench_count_allocs: false,
+            report_memory: false,
+            bench_warmup: Duration::new(0, 0),
+            bench_samples: DEFAULT_BENCH_SAMPLES,
+            hash_names: false,
+            hash_map: None,
+            kill_on_timeout: None,
+            shuffle_seed: None,
+            retries: 0,
+            repeat: 1,
+            fail_fast: false,
+            flakiness_report: None,
+            before_each: None,
+            after_each: None,
             options: Options::new(),
         }
     }
@@ -56,29 +85,10 @@ fn new() -> TestOpts {
 fn one_ignored_one_unignored_test() -> Vec<TestDescAndFn> {
     vec![
         TestDescAndFn {
-            desc: TestDesc {
-                name: StaticTestName("1"),
-                ignore: true,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                compile_fail: false,
-                no_run: false,
-                test_type: TestType::Unknown,
-            },
-            testfn: DynTestFn(Box::new(move || {})),
-        },
-        TestDescAndFn {
-            desc: TestDesc {
-                name: StaticTestName("2"),
-                ignore: false,
-                should_panic: ShouldPanic::No,
-                allow_fail: false,
-                compile_fail: false,
-                no_run: false,
-                test_type: TestType::Unknown,
-            },
+            desc: TestDesc { ignore: true, ..test_desc("1") },
             testfn: DynTestFn(Box::new(move || {})),
         },
+        TestDescAndFn { desc: test_desc("2"), testfn: DynTestFn(Box::new(move || {})) },
     ]
 }
 
@@ -88,15 +98,7 @@ fn f() {
         panic!();
     }
     let desc = TestDescAndFn {
-        desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: true,
-            should_panic: ShouldPanic::No,
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
-        },
+        desc: TestDesc { ignore: true, ..test_desc("whatever") },
         testfn: DynTestFn(Box::new(f)),
     };
     let (tx, rx) = channel();
@@ -109,21 +111,88 @@ fn f() {
 pub fn ignored_tests_result_in_ignored() {
     fn f() {}
     let desc = TestDescAndFn {
+        desc: TestDesc { ignore: true, ..test_desc("whatever") },
+        testfn: DynTestFn(Box::new(f)),
+    };
+    let (tx, rx) = channel();
+    run_test(&TestOpts::new(), false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+    let result = rx.recv().unwrap().result;
+    assert_eq!(result, TrIgnored);
+}
+
+#[test]
+#[cfg(not(bootstrap))]
+pub fn ignored_tests_with_message_result_in_ignored_with_reason() {
+    fn f() {}
+    let test = || TestDescAndFn {
         desc: TestDesc {
-            name: StaticTestName("whatever"),
             ignore: true,
-            should_panic: ShouldPanic::No,
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
+            ignore_message: Some("flaky on CI"),
+            ..test_desc("whatever")
         },
         testfn: DynTestFn(Box::new(f)),
     };
+    let opts = TestOpts::new();
     let (tx, rx) = channel();
-    run_test(&TestOpts::new(), false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+    run_test(&opts, false, TestId(0), test(), RunStrategy::InProcess, tx, Concurrent::No);
     let result = rx.recv().unwrap().result;
-    assert_eq!(result, TrIgnored);
+    assert_eq!(result, TrIgnoredMsg("flaky on CI".to_string()));
+
+    // The reason is kept when the test is run anyway.
+    let opts = TestOpts { run_ignored: RunIgnored::Yes, ..opts };
+    let mut filtered = filter_tests(&opts, vec![test()]);
+    let test = filtered.pop().unwrap();
+    assert!(!test.desc.ignore);
+    assert_eq!(test.desc.ignore_message(), Some("flaky on CI"));
+    let (tx, rx) = channel();
+    run_test(&opts, false, TestId(0), test, RunStrategy::InProcess, tx, Concurrent::No);
+    assert_eq!(rx.recv().unwrap().result, TrOk);
+}
+
+// FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn skipped_tests_result_in_ignored_with_reason() {
+    fn f() {
+        crate::skip("service unavailable");
+    }
+    for &should_panic in &[ShouldPanic::No, ShouldPanic::Yes] {
+        let desc = TestDescAndFn {
+            desc: TestDesc { should_panic, ..test_desc("whatever") },
+            testfn: DynTestFn(Box::new(f)),
+        };
+        let (tx, rx) = channel();
+        run_test(
+            &TestOpts::new(),
+            false,
+            TestId(0),
+            desc,
+            RunStrategy::InProcess,
+            tx,
+            Concurrent::No,
+        );
+        let result = rx.recv().unwrap().result;
+        assert_eq!(result, TrIgnoredMsg("service unavailable".to_string()));
+    }
+}
+
+// FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_captures_stdout_and_stderr_apart() {
+    fn f() {
+        println!("to stdout");
+        eprintln!("to stderr");
+        panic!("oops");
+    }
+    let desc = TestDescAndFn { desc: test_desc("whatever"), testfn: DynTestFn(Box::new(f)) };
+    let (tx, rx) = channel();
+    run_test(&TestOpts::new(), false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+    let completed_test = rx.recv().unwrap();
+    assert_eq!(completed_test.result, TrFailed);
+    assert_eq!(String::from_utf8(completed_test.stdout).unwrap(), "to stdout\n");
+    let stderr = String::from_utf8(completed_test.stderr).unwrap();
+    assert!(stderr.starts_with("to stderr\nthread 'whatever' panicked at 'oops'"));
 }
 
 // FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
@@ -134,15 +203,7 @@ fn f() {
         panic!();
     }
     let desc = TestDescAndFn {
-        desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: false,
-            should_panic: ShouldPanic::Yes,
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
-        },
+        desc: TestDesc { should_panic: ShouldPanic::Yes, ..test_desc("whatever") },
         testfn: DynTestFn(Box::new(f)),
     };
     let (tx, rx) = channel();
@@ -160,13 +221,8 @@ fn f() {
     }
     let desc = TestDescAndFn {
         desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: false,
             should_panic: ShouldPanic::YesWithMessage("error message"),
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
+            ..test_desc("whatever")
         },
         testfn: DynTestFn(Box::new(f)),
     };
@@ -190,13 +246,8 @@ fn f() {
  expected substring: `"foobar"`"#;
     let desc = TestDescAndFn {
         desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: false,
             should_panic: ShouldPanic::YesWithMessage(expected),
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
+            ..test_desc("whatever")
         },
         testfn: DynTestFn(Box::new(f)),
     };
@@ -206,31 +257,90 @@ fn f() {
     assert_eq!(result, TrFailedMsg(failed_msg.to_string()));
 }
 
+// FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_should_panic_regex() {
+    use crate::tests::TrFailedMsg;
+    fn f() {
+        panic!("error {} in {}", 42, "a message");
+    }
+    let run = |pattern| {
+        let desc = TestDescAndFn {
+            desc: TestDesc {
+                should_panic: ShouldPanic::YesWithRegex(pattern),
+                ..test_desc("whatever")
+            },
+            testfn: DynTestFn(Box::new(f)),
+        };
+        let (tx, rx) = channel();
+        let opts = TestOpts::new();
+        run_test(&opts, false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+        rx.recv().unwrap().result
+    };
+
+    assert_eq!(run(r"^error \d+ in [a-z ]+$"), TrOk);
+    assert_eq!(run(r"in a.*age"), TrOk);
+    let failed_msg = r#"panic did not match expected regex
+ panic message: `"error 42 in a message"`,
+expected regex: `"^in"`"#;
+    assert_eq!(run("^in"), TrFailedMsg(failed_msg.to_string()));
+    assert_eq!(
+        run("error (42)"),
+        TrFailedMsg(
+            "invalid should_panic regex `error (42)`: `(` isn't supported, groups and \
+             alternations aren't"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_should_panic_regex_syntax() {
+    use crate::helpers::regex::Regex;
+
+    let is_match = |pattern, text| Regex::new(pattern).unwrap().is_match(text);
+    assert!(is_match("b.d", "abcde"));
+    assert!(!is_match("^b.d", "abcde"));
+    assert!(is_match("^a.*e$", "abcde"));
+    assert!(!is_match("^a.*d$", "abcde"));
+    assert!(is_match("^x?ab+c*d", "abbbd"));
+    assert!(is_match(r"^\d+\s\w+$", "123 is_ok"));
+    assert!(is_match("^[^0-9][0-9a-f]+[]-]$", "x1f]"));
+    assert!(is_match(r"^\[\.\]$", "[.]"));
+    assert!(is_match("", "anything"));
+
+    // Would take forever if the failed attempts weren't remembered.
+    let text = "a".repeat(200);
+    assert!(!is_match("a*a*a*a*a*a*a*a*a*a*a*a*b", &text));
+    assert!(is_match("^a*a*a*a*a*a*a*a*a*a*a*a*$", &text));
+
+    for pattern in &["*a", "a**", "[a-", "[z-a]", "a\\", r"\q", "a|b"] {
+        assert!(Regex::new(pattern).is_err(), "{} should be invalid", pattern);
+    }
+}
+
 // FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
 #[test]
 #[cfg(not(target_os = "emscripten"))]
 fn test_should_panic_non_string_message_type() {
     use crate::tests::TrFailedMsg;
     use std::any::TypeId;
+    struct Payload;
     fn f() {
-        std::panic::panic_any(1i32);
+        std::panic::panic_any(Payload);
     }
     let expected = "foobar";
     let failed_msg = format!(
-        r#"expected panic with string value,
- found non-string value: `{:?}`
-     expected substring: `"foobar"`"#,
-        TypeId::of::<i32>()
+        r#"panic payload was not a string, cannot check expected message
+    payload type id: `{:?}`
+ expected substring: `"foobar"`"#,
+        TypeId::of::<Payload>()
     );
     let desc = TestDescAndFn {
         desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: false,
             should_panic: ShouldPanic::YesWithMessage(expected),
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
+            ..test_desc("whatever")
         },
         testfn: DynTestFn(Box::new(f)),
     };
@@ -240,6 +350,177 @@ fn f() {
     assert_eq!(result, TrFailedMsg(failed_msg));
 }
 
+// FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_should_panic_displayable_message_type() {
+    fn f() {
+        std::panic::panic_any(404u16);
+    }
+    let desc = TestDescAndFn {
+        desc: TestDesc {
+            should_panic: ShouldPanic::YesWithMessage("404"),
+            ..test_desc("whatever")
+        },
+        testfn: DynTestFn(Box::new(f)),
+    };
+    let (tx, rx) = channel();
+    run_test(&TestOpts::new(), false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+    let result = rx.recv().unwrap().result;
+    assert_eq!(result, TrOk);
+}
+
+// FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_allowed_panic_message() {
+    fn f() {
+        panic!("failed to connect: Connection refused");
+    }
+    fn run(allowed: &str) -> CompletedTest {
+        let desc = TestDescAndFn { desc: test_desc("whatever"), testfn: DynTestFn(Box::new(f)) };
+        let mut opts = TestOpts::new();
+        opts.allowed_panic_messages = vec![allowed.to_string()];
+        let (tx, rx) = channel();
+        run_test(&opts, false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+        rx.recv().unwrap()
+    }
+
+    let completed = run("Connection refused");
+    assert_eq!(completed.result, TrOk);
+    let stdout = String::from_utf8(completed.stdout).unwrap();
+    assert!(stdout.contains("note: test passed, its panic message contains `Connection refused`"));
+
+    assert_eq!(run("Connection reset").result, TrFailed);
+}
+
+// FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_retries() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    fn f() {
+        let run = RUNS.fetch_add(1, Ordering::SeqCst) + 1;
+        println!("run {}", run);
+        assert_eq!(run, 3);
+    }
+    fn run(retries: usize) -> CompletedTest {
+        RUNS.store(0, Ordering::SeqCst);
+        let desc = TestDescAndFn { desc: test_desc("whatever"), testfn: StaticTestFn(f) };
+        let opts = TestOpts { retries, ..TestOpts::new() };
+        let (tx, rx) = channel();
+        run_test(&opts, false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+        let completed = rx.recv().unwrap();
+        // Only the last attempt is reported.
+        assert!(rx.try_recv().is_err());
+        completed
+    }
+
+    let completed = run(3);
+    assert_eq!(completed.result, TrOk);
+    assert_eq!(completed.attempt, Some(Attempt { number: 3, max: 4 }));
+    assert_eq!(String::from_utf8(completed.stdout).unwrap(), "run 3\n");
+    assert_eq!(
+        crate::formatters::attempt_note(&completed.result, completed.attempt).unwrap(),
+        "flaky, passed on attempt 3/4"
+    );
+
+    let completed = run(1);
+    assert_ne!(completed.result, TrOk);
+    assert_eq!(completed.attempt, Some(Attempt { number: 2, max: 2 }));
+    assert!(String::from_utf8(completed.stdout).unwrap().starts_with("run 2\n"));
+
+    let completed = run(0);
+    assert_ne!(completed.result, TrOk);
+    assert_eq!(completed.attempt, None);
+}
+
+#[test]
+fn test_kill_on_timeout_in_process() {
+    fn f() {
+        thread::sleep(Duration::from_millis(200));
+    }
+    let desc = TestDescAndFn { desc: test_desc("whatever"), testfn: DynTestFn(Box::new(f)) };
+    let opts = TestOpts { kill_on_timeout: Some(Duration::from_millis(50)), ..TestOpts::new() };
+    let (tx, rx) = channel();
+    run_test(&opts, false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+    let result = rx.recv().unwrap().result;
+    assert_eq!(result, TrFailedMsg("test exceeded timeout of 0.05 seconds".to_string()));
+}
+
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_before_and_after_each_hooks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let record = |event: &'static str| {
+        let events = events.clone();
+        TestHook::new(move |desc: &TestDesc| {
+            events.lock().unwrap().push(format!("{} {}", event, desc.name));
+        })
+    };
+    let opts = TestOpts {
+        before_each: Some(record("before")),
+        after_each: Some(record("after")),
+        ..TestOpts::new()
+    };
+
+    for (name, should_panic) in [("passes", false), ("panics", true)] {
+        let events = events.clone();
+        let f = move || {
+            events.lock().unwrap().push(format!("run {}", name));
+            if should_panic {
+                panic!("test panicked");
+            }
+        };
+        let desc = TestDescAndFn { desc: test_desc(name), testfn: DynTestFn(Box::new(f)) };
+        let (tx, rx) = channel();
+        run_test(&opts, false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+        let result = rx.recv().unwrap().result;
+        assert_eq!(result == TrOk, !should_panic);
+    }
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "before passes",
+            "run passes",
+            "after passes",
+            "before panics",
+            "run panics",
+            "after panics"
+        ]
+    );
+}
+
+#[test]
+#[cfg(not(target_os = "emscripten"))]
+fn test_panicking_hooks() {
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let panicking = TestHook::new(|_: &TestDesc| panic!("oops"));
+    let before_panics = TestOpts { before_each: Some(panicking.clone()), ..TestOpts::new() };
+    let after_panics = TestOpts { after_each: Some(panicking), ..TestOpts::new() };
+
+    for (name, opts, expected) in [
+        ("before_each", &before_panics, "before_each hook panicked: oops"),
+        ("after_each", &after_panics, "after_each hook panicked: oops"),
+    ] {
+        let ran = ran.clone();
+        let desc = TestDescAndFn {
+            desc: test_desc(name),
+            testfn: DynTestFn(Box::new(move || ran.lock().unwrap().push(name))),
+        };
+        let (tx, rx) = channel();
+        run_test(opts, false, TestId(0), desc, RunStrategy::InProcess, tx, Concurrent::No);
+        let result = rx.recv().unwrap().result;
+        assert_eq!(result, TrFailedMsg(expected.to_string()));
+    }
+
+    // The test isn't run when `before_each` panics.
+    assert_eq!(*ran.lock().unwrap(), ["after_each"]);
+}
+
 // FIXME: Re-enable emscripten once it can catch panics again (introduced by #65251)
 #[test]
 #[cfg(not(target_os = "emscripten"))]
@@ -249,15 +530,7 @@ fn test_should_panic_but_succeeds() {
     for &should_panic in should_panic_variants.iter() {
         fn f() {}
         let desc = TestDescAndFn {
-            desc: TestDesc {
-                name: StaticTestName("whatever"),
-                ignore: false,
-                should_panic,
-                allow_fail: false,
-                compile_fail: false,
-                no_run: false,
-                test_type: TestType::Unknown,
-            },
+            desc: TestDesc { should_panic, ..test_desc("whatever") },
             testfn: DynTestFn(Box::new(f)),
         };
         let (tx, rx) = channel();
@@ -280,20 +553,21 @@ fn f() {}
     }
 }
 
+#[test]
+#[cfg(not(bootstrap))]
+fn test_subprocess_env_overrides() {
+    use std::ffi::OsStr;
+
+    let desc = TestDesc { env: &[("RUST_LOG", "debug")], ..test_desc("whatever") };
+    let command = test_subprocess_command("progname", &desc, false);
+    let envs = command.get_envs().collect::<Vec<_>>();
+    assert!(envs.contains(&(OsStr::new("RUST_LOG"), Some(OsStr::new("debug")))));
+    assert!(envs.contains(&(OsStr::new(SECONDARY_TEST_INVOKER_VAR), Some(OsStr::new("whatever")))));
+}
+
 fn report_time_test_template(report_time: bool) -> Option<TestExecTime> {
     fn f() {}
-    let desc = TestDescAndFn {
-        desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: false,
-            should_panic: ShouldPanic::No,
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
-        },
-        testfn: DynTestFn(Box::new(f)),
-    };
+    let desc = TestDescAndFn { desc: test_desc("whatever"), testfn: DynTestFn(Box::new(f)) };
     let time_options = if report_time { Some(TestTimeOptions::default()) } else { None };
 
     let test_opts = TestOpts { time_options, ..TestOpts::new() };
@@ -318,15 +592,7 @@ fn test_should_report_time() {
 fn time_test_failure_template(test_type: TestType) -> TestResult {
     fn f() {}
     let desc = TestDescAndFn {
-        desc: TestDesc {
-            name: StaticTestName("whatever"),
-            ignore: false,
-            should_panic: ShouldPanic::No,
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type,
-        },
+        desc: TestDesc { test_type, ..test_desc("whatever") },
         testfn: DynTestFn(Box::new(f)),
     };
     // `Default` will initialize all the thresholds to 0 milliseconds.
@@ -356,22 +622,114 @@ fn test_error_on_exceed() {
     assert_eq!(result, TestResult::TrOk);
 }
 
-fn typed_test_desc(test_type: TestType) -> TestDesc {
+fn test_desc(name: &'static str) -> TestDesc {
     TestDesc {
-        name: StaticTestName("whatever"),
+        name: StaticTestName(name),
         ignore: false,
+        #[cfg(not(bootstrap))]
+        ignore_message: None,
         should_panic: ShouldPanic::No,
         allow_fail: false,
         compile_fail: false,
         no_run: false,
-        test_type,
+        test_type: TestType::Unknown,
+        #[cfg(not(bootstrap))]
+        env: &[],
+        #[cfg(not(bootstrap))]
+        source_file: "",
+        #[cfg(not(bootstrap))]
+        start_line: 0,
+        #[cfg(not(bootstrap))]
+        start_col: 0,
+        #[cfg(not(bootstrap))]
+        end_line: 0,
+        #[cfg(not(bootstrap))]
+        end_col: 0,
     }
 }
 
+fn typed_test_desc(test_type: TestType) -> TestDesc {
+    TestDesc { test_type, ..test_desc("whatever") }
+}
+
 fn test_exec_time(millis: u64) -> TestExecTime {
     TestExecTime(Duration::from_millis(millis))
 }
 
+#[test]
+#[cfg(unix)]
+fn test_subprocess_abnormal_termination() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    let desc = typed_test_desc(TestType::Unknown);
+
+    // A wait status holding only a signal number is the one of a process killed by
+    // that signal, as happens to a test process that overflows its stack.
+    let result = get_result_from_exit_status(&desc, ExitStatus::from_raw(11), &None, &None);
+    assert_eq!(
+        result,
+        TestResult::TrFailedMsg("stack overflow or abnormal termination (signal 11)".to_string())
+    );
+
+    let status = ExitStatus::from_raw(test_result::TR_OK << 8);
+    assert_eq!(get_result_from_exit_status(&desc, status, &None, &None), TestResult::TrOk);
+    let status = ExitStatus::from_raw(test_result::TR_FAILED << 8);
+    assert_eq!(get_result_from_exit_status(&desc, status, &None, &None), TestResult::TrFailed);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_subprocess_timeout_report() {
+    let mut command = Command::new("sh");
+    command.args(&["-c", "sleep 1; echo done"]);
+
+    // The slow subprocess is reported (by `run_tests`), but left running.
+    let timeout = Duration::from_millis(100);
+    let (output, killed, max_rss) =
+        run_test_subprocess(command, TimeoutAction::Report, timeout, false).unwrap();
+    assert!(!killed);
+    assert_eq!(max_rss, None);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"done\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_subprocess_timeout_kill() {
+    let mut command = Command::new("sh");
+    command.args(&["-c", "echo started; exec sleep 10"]);
+    command.stdout(process::Stdio::piped());
+
+    let timeout = Duration::from_millis(100);
+    let (output, killed, _) =
+        run_test_subprocess(command, TimeoutAction::Kill, timeout, false).unwrap();
+    assert!(killed);
+    assert!(!output.status.success());
+    // The output is kept up to the point where the subprocess was killed.
+    assert_eq!(output.stdout, b"started\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_subprocess_report_memory() {
+    let mut command = Command::new("sh");
+    command.args(&["-c", "echo done; exit 3"]);
+    command.stdout(process::Stdio::piped());
+
+    let timeout = Duration::from_secs(60);
+    let (output, killed, max_rss) =
+        run_test_subprocess(command, TimeoutAction::Report, timeout, true).unwrap();
+    assert!(!killed);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"done\n");
+    if cfg!(target_os = "linux") {
+        assert!(max_rss.unwrap() > 0);
+    } else {
+        assert_eq!(max_rss, None);
+    }
+}
+
 #[test]
 fn test_time_options_threshold() {
     let unit = TimeThreshold::new(Duration::from_millis(50), Duration::from_millis(100));
@@ -408,101 +766,585 @@ fn test_time_options_threshold() {
 }
 
 #[test]
-fn parse_ignored_flag() {
-    let args = vec!["progname".to_string(), "filter".to_string(), "--ignored".to_string()];
-    let opts = parse_opts(&args).unwrap().unwrap();
-    assert_eq!(opts.run_ignored, RunIgnored::Only);
+fn test_time_precision() {
+    let exec_time = TestExecTime(Duration::from_micros(1_234_567));
+    assert_eq!(time::fmt_time(&exec_time, None), "1.235s");
+    assert_eq!(time::fmt_time(&exec_time, Some(0)), "1s");
+    assert_eq!(time::fmt_time(&exec_time, Some(2)), "1.23s");
+    assert_eq!(time::fmt_time(&exec_time, Some(4)), "1.2346s");
+
+    let suite_exec_time = TestSuiteExecTime(Duration::from_micros(1_234_567));
+    assert_eq!(time::fmt_time(&suite_exec_time, None), "1.23s");
+    assert_eq!(time::fmt_time(&suite_exec_time, Some(4)), "1.2346s");
+
+    let bs = BenchSamples {
+        ns_iter_summ: crate::stats::Summary::new(&[1234.5, 1236.5]),
+        mb_s: 0,
+        percentiles: vec![],
+        allocs_per_iter: None,
+        metrics: MetricMap::new(),
+    };
+    assert_eq!(fmt_bench_samples_with_precision(&bs, 0), "      1,235 ns/iter (+/- 2)");
+    assert_eq!(fmt_bench_samples_with_precision(&bs, 2), "   1,235.50 ns/iter (+/- 2.00)");
+    assert_eq!(fmt_bench_samples_with_precision(&bs, 4), " 1,235.5000 ns/iter (+/- 2.0000)");
 }
 
 #[test]
-fn parse_show_output_flag() {
-    let args = vec!["progname".to_string(), "filter".to_string(), "--show-output".to_string()];
-    let opts = parse_opts(&args).unwrap().unwrap();
-    assert!(opts.options.display_output);
-}
+fn should_warn_about_noisy_benchmarks() {
+    use crate::formatters::{JsonFormatter, OutputFormatter};
+
+    let desc = test_desc("b");
+    let st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+    let bench_result = |samples: &[f64]| {
+        TestResult::TrBench(BenchSamples {
+            ns_iter_summ: crate::stats::Summary::new(samples),
+            mb_s: 0,
+            percentiles: vec![],
+            allocs_per_iter: None,
+            metrics: MetricMap::new(),
+        })
+    };
+    let pretty_output = |result: &TestResult| {
+        let mut output = Vec::new();
+        let mut out = PrettyFormatter::new(
+            OutputLocation::Raw(&mut output),
+            false,
+            false,
+            ColorTheme::DEFAULT,
+            10,
+            true,
+            None,
+        );
+        out.write_result(&desc, result, None, None, None, &[], &[], &st).unwrap();
+        drop(out);
+        String::from_utf8(output).unwrap()
+    };
 
-#[test]
-fn parse_include_ignored_flag() {
-    let args = vec!["progname".to_string(), "filter".to_string(), "--include-ignored".to_string()];
-    let opts = parse_opts(&args).unwrap().unwrap();
-    assert_eq!(opts.run_ignored, RunIgnored::Yes);
+    // The deviation is 40% of the median.
+    let noisy = bench_result(&[800.0, 1000.0, 1200.0]);
+    assert!(pretty_output(&noisy).ends_with("ns/iter (+/- 400) \u{26a0} high variance\n"));
+    let mut json = Vec::new();
+    let mut out = JsonFormatter::new(OutputLocation::Raw(&mut json), false);
+    out.write_result(&desc, &noisy, None, None, None, &[], &[], &st).unwrap();
+    drop(out);
+    assert!(String::from_utf8(json).unwrap().contains(r#""noisy": true"#));
+
+    // The deviation is 2% of the median.
+    let stable = bench_result(&[990.0, 1000.0, 1010.0]);
+    assert!(pretty_output(&stable).ends_with("ns/iter (+/- 20)\n"));
 }
 
 #[test]
-pub fn filter_for_ignored_option() {
-    // When we run ignored tests the test filter should filter out all the
-    // unignored tests and flip the ignore flag on the rest to false
-
-    let mut opts = TestOpts::new();
-    opts.run_tests = true;
-    opts.run_ignored = RunIgnored::Only;
-
-    let tests = one_ignored_one_unignored_test();
-    let filtered = filter_tests(&opts, tests);
+fn should_emit_one_json_object_per_line() {
+    use crate::formatters::{JsonFormatter, OutputFormatter};
+
+    let desc = test_desc("a");
+    let st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+    let exec_time = time::TestExecTime(Duration::from_millis(3));
+    let bench = TestResult::TrBench(BenchSamples {
+        ns_iter_summ: crate::stats::Summary::new(&[990.0, 1000.0, 1010.0]),
+        mb_s: 0,
+        percentiles: vec![],
+        allocs_per_iter: None,
+        metrics: MetricMap::new(),
+    });
 
-    assert_eq!(filtered.len(), 1);
-    assert_eq!(filtered[0].desc.name.to_string(), "1");
-    assert!(!filtered[0].desc.ignore);
+    let mut json = Vec::new();
+    let mut out = JsonFormatter::new(OutputLocation::Raw(&mut json), false);
+    out.write_result(&desc, &TrOk, Some(&exec_time), None, None, b"ignored", &[], &st).unwrap();
+    let stdout = b"line 1\nline \"2\"\n";
+    out.write_result(&desc, &TrFailed, None, None, None, stdout, b"oops\n", &st).unwrap();
+    out.write_result(&desc, &bench, None, None, None, &[], &[], &st).unwrap();
+    out.write_run_finish(&st).unwrap();
+    drop(out);
+
+    let json = String::from_utf8(json).unwrap();
+    assert_eq!(
+        json.lines().collect::<Vec<_>>(),
+        [
+            r#"{ "type": "test", "name": "a", "event": "ok", "exec_time": 0.003 }"#,
+            concat!(
+                r#"{ "type": "test", "name": "a", "event": "failed", "#,
+                r#""stdout": "line 1\nline \"2\"\n", "stderr": "oops\n" }"#
+            ),
+            r#"{ "type": "bench", "name": "a", "median": 1000, "deviation": 20, "noisy": false }"#,
+            concat!(
+                r#"{ "type": "suite", "event": "ok", "passed": 0, "failed": 0, "#,
+                r#""allowed_fail": 0, "ignored": 0, "measured": 0, "filtered_out": 0 }"#
+            ),
+        ]
+    );
 }
 
 #[test]
-pub fn run_include_ignored_option() {
-    // When we "--include-ignored" tests, the ignore flag should be set to false on
-    // all tests and no test filtered out
+fn should_report_max_rss() {
+    use crate::formatters::{JsonFormatter, OutputFormatter};
+
+    let desc = test_desc("a");
+    let st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+    let max_rss = Some(3 * 1024 * 1024 / 2);
+
+    let mut output = Vec::new();
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(&mut output),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        true,
+        None,
+    );
+    out.write_result(&desc, &TrOk, None, None, max_rss, &[], &[], &st).unwrap();
+    drop(out);
+    assert_eq!(String::from_utf8(output).unwrap(), "test a ... ok (max rss: 1.5 MiB)\n");
+
+    let mut json = Vec::new();
+    let mut out = JsonFormatter::new(OutputLocation::Raw(&mut json), false);
+    out.write_result(&desc, &TrOk, None, None, max_rss, &[], &[], &st).unwrap();
+    drop(out);
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "{ \"type\": \"test\", \"name\": \"a\", \"event\": \"ok\", \"max_rss\": 1572864 }\n"
+    );
+}
 
-    let mut opts = TestOpts::new();
-    opts.run_tests = true;
-    opts.run_ignored = RunIgnored::Yes;
+#[test]
+fn parse_time_precision_flag() {
+    let args = vec!["progname".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.options.time_precision, None);
 
-    let tests = one_ignored_one_unignored_test();
-    let filtered = filter_tests(&opts, tests);
+    let args = vec![
+        "progname".to_string(),
+        "-Zunstable-options".to_string(),
+        "--time-precision".to_string(),
+        "4".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.options.time_precision, Some(4));
 
-    assert_eq!(filtered.len(), 2);
-    assert!(!filtered[0].desc.ignore);
-    assert!(!filtered[1].desc.ignore);
+    let args = vec!["progname".to_string(), "--time-precision".to_string(), "4".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
 }
 
 #[test]
-pub fn exclude_should_panic_option() {
-    let mut opts = TestOpts::new();
-    opts.run_tests = true;
-    opts.exclude_should_panic = true;
+fn parse_allow_panic_message_flag() {
+    let args = vec![
+        "progname".to_string(),
+        "filter".to_string(),
+        "--allow-panic-message".to_string(),
+        "broken pipe".to_string(),
+        "--allow-panic-message".to_string(),
+        "timed out".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.allowed_panic_messages, ["broken pipe", "timed out"]);
 
-    let mut tests = one_ignored_one_unignored_test();
-    tests.push(TestDescAndFn {
-        desc: TestDesc {
-            name: StaticTestName("3"),
-            ignore: false,
-            should_panic: ShouldPanic::Yes,
-            allow_fail: false,
-            compile_fail: false,
-            no_run: false,
-            test_type: TestType::Unknown,
-        },
-        testfn: DynTestFn(Box::new(move || {})),
-    });
+    let args = vec!["progname".to_string(), "--allow-panic-message=x".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
 
-    let filtered = filter_tests(&opts, tests);
+#[test]
+fn parse_tests_from_stdin_flag() {
+    let args = vec![
+        "progname".to_string(),
+        "--tests-from-stdin".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.tests_from_stdin);
+    // The names are only read from stdin by `test_main`.
+    assert_eq!(opts.selected_tests, None);
 
-    assert_eq!(filtered.len(), 2);
-    assert!(filtered.iter().all(|test| test.desc.should_panic == ShouldPanic::No));
+    let args = vec!["progname".to_string(), "--tests-from-stdin".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
 }
 
 #[test]
-pub fn exact_filter_match() {
-    fn tests() -> Vec<TestDescAndFn> {
+fn parse_ignored_flag() {
+    let args = vec!["progname".to_string(), "filter".to_string(), "--ignored".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.run_ignored, RunIgnored::Only);
+}
+
+#[test]
+fn parse_test_threads_env_values() {
+    use crate::helpers::concurrency::parse_concurrency;
+
+    assert_eq!(parse_concurrency("3", 8), Some(3));
+    assert_eq!(parse_concurrency("-1", 8), Some(7));
+    assert_eq!(parse_concurrency("-8", 8), Some(1));
+    assert_eq!(parse_concurrency("auto/2", 8), Some(4));
+    assert_eq!(parse_concurrency("auto/3", 2), Some(1));
+    for invalid in &["0", "-0", "auto/0", "auto", "many", "1.5", ""] {
+        assert_eq!(parse_concurrency(invalid, 8), None, "{:?} should be invalid", invalid);
+    }
+}
+
+#[test]
+fn parse_force_subprocess_flag() {
+    let args = vec![
+        "progname".to_string(),
+        "--force-subprocess".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.force_subprocess);
+
+    let args = vec!["progname".to_string(), "--force-subprocess".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+
+    let args = vec![
+        "progname".to_string(),
+        "--force-subprocess".to_string(),
+        "--force-run-in-process".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+fn track_tests_running_in_process() {
+    use crate::helpers::exit_guard::{running_tests, RunningTest};
+
+    let name = "track_tests_running_in_process";
+    let running_test = RunningTest::new(name);
+    assert!(running_tests().iter().any(|running| running == name));
+    drop(running_test);
+    assert!(!running_tests().iter().any(|running| running == name));
+}
+
+#[test]
+fn parse_show_output_flag() {
+    let args = vec!["progname".to_string(), "filter".to_string(), "--show-output".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.options.display_output);
+}
+
+#[test]
+fn parse_multiple_filters() {
+    let args = vec![
+        "progname".to_string(),
+        "base".to_string(),
+        "--skip".to_string(),
+        "test1".to_string(),
+        "other".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.filters, vec!["base".to_string(), "other".to_string()]);
+
+    let tests = vec!["base::test", "base::test1", "other::test", "unrelated"]
+        .into_iter()
+        .map(|name| TestDescAndFn {
+            desc: test_desc(name),
+            testfn: DynTestFn(Box::new(move || {})),
+        })
+        .collect();
+    let filtered = filter_tests(&opts, tests);
+    let names: Vec<_> = filtered.iter().map(|t| t.desc.name.to_string()).collect();
+    assert_eq!(names, ["base::test", "other::test"]);
+}
+
+#[test]
+fn parse_include_ignored_flag() {
+    let args = vec!["progname".to_string(), "filter".to_string(), "--include-ignored".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.run_ignored, RunIgnored::Yes);
+}
+
+#[test]
+fn parse_bench_percentiles_flag() {
+    let args = vec!["progname".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.bench_percentiles, vec![50.0, 90.0, 99.0]);
+
+    let args = vec![
+        "progname".to_string(),
+        "-Zunstable-options".to_string(),
+        "--bench-percentiles".to_string(),
+        "0,99.9,100".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.bench_percentiles, vec![0.0, 99.9, 100.0]);
+
+    let args = vec![
+        "progname".to_string(),
+        "-Zunstable-options".to_string(),
+        "--bench-percentiles".to_string(),
+        "50,101".to_string(),
+    ];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+fn dump_opts_shows_resolved_values() {
+    let args = vec![
+        "progname".to_string(),
+        "filter".to_string(),
+        "-Zunstable-options".to_string(),
+        "--dump-opts".to_string(),
+        "--test-threads".to_string(),
+        "3".to_string(),
+        "--color".to_string(),
+        "never".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.dump_opts);
+
+    let dump = opts.dump();
+    assert!(dump.contains(r#""filter""#));
+    assert!(dump.contains("NeverColor"));
+    assert!(dump.contains("effective test threads: 3\n"));
+    assert!(dump.contains("effective use color: false\n"));
+
+    let args = vec!["progname".to_string(), "--dump-opts".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+pub fn filter_for_ignored_option() {
+    // When we run ignored tests the test filter should filter out all the
+    // unignored tests and flip the ignore flag on the rest to false
+
+    let mut opts = TestOpts::new();
+    opts.run_tests = true;
+    opts.run_ignored = RunIgnored::Only;
+
+    let tests = one_ignored_one_unignored_test();
+    let filtered = filter_tests(&opts, tests);
+
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].desc.name.to_string(), "1");
+    assert!(!filtered[0].desc.ignore);
+}
+
+#[test]
+pub fn run_include_ignored_option() {
+    // When we "--include-ignored" tests, the ignore flag should be set to false on
+    // all tests and no test filtered out
+
+    let mut opts = TestOpts::new();
+    opts.run_tests = true;
+    opts.run_ignored = RunIgnored::Yes;
+
+    let tests = one_ignored_one_unignored_test();
+    let filtered = filter_tests(&opts, tests);
+
+    assert_eq!(filtered.len(), 2);
+    assert!(!filtered[0].desc.ignore);
+    assert!(!filtered[1].desc.ignore);
+}
+
+#[test]
+pub fn exclude_should_panic_option() {
+    let mut opts = TestOpts::new();
+    opts.run_tests = true;
+    opts.exclude_should_panic = true;
+
+    let mut tests = one_ignored_one_unignored_test();
+    tests.push(TestDescAndFn {
+        desc: TestDesc { should_panic: ShouldPanic::Yes, ..test_desc("3") },
+        testfn: DynTestFn(Box::new(move || {})),
+    });
+
+    let filtered = filter_tests(&opts, tests);
+
+    assert_eq!(filtered.len(), 2);
+    assert!(filtered.iter().all(|test| test.desc.should_panic == ShouldPanic::No));
+}
+
+#[test]
+pub fn only_should_panic_option() {
+    let mut opts = TestOpts::new();
+    opts.run_tests = true;
+    opts.only_should_panic = true;
+
+    let mut tests = one_ignored_one_unignored_test();
+    tests.push(TestDescAndFn {
+        desc: TestDesc { should_panic: ShouldPanic::Yes, ..test_desc("3") },
+        testfn: DynTestFn(Box::new(move || {})),
+    });
+
+    let filtered = filter_tests(&opts, tests);
+
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].desc.name.to_string(), "3");
+}
+
+#[test]
+fn parse_only_should_panic_flag() {
+    let args = vec![
+        "progname".to_string(),
+        "--only-should-panic".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.only_should_panic);
+
+    let args = vec!["progname".to_string(), "--only-should-panic".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+
+    let args = vec![
+        "progname".to_string(),
+        "--only-should-panic".to_string(),
+        "--exclude-should-panic".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+pub fn list_tests_as_json() {
+    fn f(_: &mut Bencher) {}
+
+    let mut tests = one_ignored_one_unignored_test();
+    tests.push(TestDescAndFn {
+        desc: TestDesc { should_panic: ShouldPanic::YesWithMessage("boom"), ..test_desc("bench") },
+        testfn: StaticBenchFn(f),
+    });
+
+    let opts = TestOpts { format: OutputFormat::Json, ..TestOpts::new() };
+    let mut output = Vec::new();
+    console::list_tests(&opts, tests, &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap().lines().collect::<Vec<_>>(),
+        [
+            concat!(
+                r#"{ "type": "test", "name": "1", "ignored": true, "kind": "test", "#,
+                r#""should_panic": "no" }"#
+            ),
+            concat!(
+                r#"{ "type": "test", "name": "2", "ignored": false, "kind": "test", "#,
+                r#""should_panic": "no" }"#
+            ),
+            concat!(
+                r#"{ "type": "test", "name": "bench", "ignored": false, "kind": "bench", "#,
+                r#""should_panic": "yes_with_message" }"#
+            ),
+        ]
+    );
+}
+
+#[test]
+#[cfg(not(bootstrap))]
+pub fn should_report_test_location_in_json() {
+    use crate::formatters::{JsonFormatter, OutputFormatter};
+
+    let desc = TestDesc {
+        test_type: TestType::UnitTest,
+        source_file: "src/lib.rs",
+        start_line: 12,
+        start_col: 4,
+        end_line: 12,
+        end_col: 11,
+        ..test_desc("located")
+    };
+    let tests = vec![TestDescAndFn { desc: desc.clone(), testfn: DynTestFn(Box::new(move || {})) }];
+    let opts = TestOpts { format: OutputFormat::Json, ..TestOpts::new() };
+    let mut output = Vec::new();
+    console::list_tests(&opts, tests, &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        concat!(
+            r#"{ "type": "test", "name": "located", "ignored": false, "kind": "test", "#,
+            r#""should_panic": "no", "source_file": "src/lib.rs", "start_line": 12, "#,
+            r#""start_col": 4, "end_line": 12, "end_col": 11 }"#,
+            "\n"
+        )
+    );
+
+    let mut json = Vec::new();
+    let mut out = JsonFormatter::new(OutputLocation::Raw(&mut json), false);
+    out.write_test_start(&desc).unwrap();
+    drop(out);
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        concat!(
+            r#"{ "type": "test", "event": "started", "name": "located", "#,
+            r#""source_file": "src/lib.rs", "start_line": 12, "start_col": 4, "end_line": 12, "#,
+            r#""end_col": 11 }"#,
+            "\n"
+        )
+    );
+
+    // The location of the tests created at runtime is unknown.
+    let desc = TestDesc { source_file: "", ..desc };
+    assert_eq!(desc.source_location(), None);
+}
+
+#[test]
+pub fn summarize_filter_counts_kept_tests() {
+    fn f(_: &mut Bencher) {}
+
+    let mut tests = one_ignored_one_unignored_test();
+    tests.push(TestDescAndFn { desc: test_desc("bench"), testfn: StaticBenchFn(f) });
+
+    let summary = summarize_filter(&TestOpts::new(), &tests);
+    assert_eq!(
+        summary,
+        FilterSummary { total: 3, kept: 3, ignored: 1, benches: 1, filtered_out: 0 }
+    );
+
+    let opts = TestOpts { skip: vec!["2".into()], ..TestOpts::new() };
+    let summary = summarize_filter(&opts, &tests);
+    assert_eq!(
+        summary,
+        FilterSummary { total: 3, kept: 2, ignored: 1, benches: 1, filtered_out: 1 }
+    );
+    assert_eq!(filter_tests(&opts, tests).len(), summary.kept);
+
+    let opts = TestOpts { run_ignored: RunIgnored::Only, ..TestOpts::new() };
+    let summary = summarize_filter(&opts, &one_ignored_one_unignored_test());
+    assert_eq!(
+        summary,
+        FilterSummary { total: 2, kept: 1, ignored: 0, benches: 0, filtered_out: 1 }
+    );
+}
+
+#[test]
+pub fn bench_only_option() {
+    fn f(_: &mut Bencher) {}
+
+    let mut tests = one_ignored_one_unignored_test();
+    tests.push(TestDescAndFn { desc: test_desc("bench"), testfn: StaticBenchFn(f) });
+
+    let args =
+        vec!["progname".to_string(), "--bench-only".to_string(), "-Zunstable-options".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.bench_only);
+    assert!(opts.bench_benchmarks);
+    assert!(!opts.run_tests);
+
+    // The tests are counted as filtered out, instead of ignored.
+    let summary = summarize_filter(&opts, &tests);
+    assert_eq!(
+        summary,
+        FilterSummary { total: 3, kept: 1, ignored: 0, benches: 1, filtered_out: 2 }
+    );
+    let filtered = filter_tests(&opts, tests);
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].desc.name.to_string(), "bench");
+
+    let args = vec!["progname".to_string(), "--bench-only".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+
+    let args = vec![
+        "progname".to_string(),
+        "--bench-only".to_string(),
+        "--test".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+pub fn exact_filter_match() {
+    fn tests() -> Vec<TestDescAndFn> {
         vec!["base", "base::test", "base::test1", "base::test2"]
             .into_iter()
             .map(|name| TestDescAndFn {
-                desc: TestDesc {
-                    name: StaticTestName(name),
-                    ignore: false,
-                    should_panic: ShouldPanic::No,
-                    allow_fail: false,
-                    compile_fail: false,
-                    no_run: false,
-                    test_type: TestType::Unknown,
-                },
+                desc: test_desc(name),
                 testfn: DynTestFn(Box::new(move || {})),
             })
             .collect()
@@ -565,6 +1407,33 @@ fn tests() -> Vec<TestDescAndFn> {
     assert_eq!(exact.len(), 2);
 }
 
+#[test]
+pub fn tests_from_stdin_match() {
+    use crate::cli::read_test_names;
+
+    let tests = || {
+        vec!["base", "base::test", "base::test1", "base::test2"]
+            .into_iter()
+            .map(|name| TestDescAndFn {
+                desc: test_desc(name),
+                testfn: DynTestFn(Box::new(move || {})),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // What an external selection tool would pipe to the harness.
+    let stdin: &[u8] = b"base::test1\r\nbase::test\n\nbase::test3\n";
+    let names = read_test_names(stdin).unwrap();
+    assert_eq!(names, ["base::test1", "base::test", "base::test3"]);
+
+    let selected =
+        filter_tests(&TestOpts { selected_tests: Some(names.clone()), ..TestOpts::new() }, tests());
+    let selected = selected.iter().map(|test| test.desc.name.as_slice()).collect::<Vec<_>>();
+    assert_eq!(selected, ["base::test", "base::test1"]);
+
+    assert_eq!(unknown_test_names(&names, &tests()), ["base::test3"]);
+}
+
 #[test]
 pub fn sort_tests() {
     let mut opts = TestOpts::new();
@@ -588,15 +1457,7 @@ fn testfn() {}
         let mut tests = Vec::new();
         for name in &names {
             let test = TestDescAndFn {
-                desc: TestDesc {
-                    name: DynTestName((*name).clone()),
-                    ignore: false,
-                    should_panic: ShouldPanic::No,
-                    allow_fail: false,
-                    compile_fail: false,
-                    no_run: false,
-                    test_type: TestType::Unknown,
-                },
+                desc: TestDesc { name: DynTestName((*name).clone()), ..test_desc("") },
                 testfn: DynTestFn(Box::new(testfn)),
             };
             tests.push(test);
@@ -624,6 +1485,48 @@ fn testfn() {}
     }
 }
 
+#[test]
+pub fn shuffle_tests_with_seed() {
+    let tests = |names: &[&'static str]| {
+        fn testfn() {}
+        names
+            .iter()
+            .map(|name| TestDescAndFn { desc: test_desc(name), testfn: StaticTestFn(testfn) })
+            .collect::<Vec<_>>()
+    };
+    let names = |tests: Vec<TestDescAndFn>| {
+        tests.iter().map(|test| test.desc.name.to_string()).collect::<Vec<_>>()
+    };
+    let shuffled = |seed, input: &[&'static str]| {
+        let opts = TestOpts { shuffle_seed: Some(seed), ..TestOpts::new() };
+        names(filter_tests(&opts, tests(input)))
+    };
+
+    let sorted = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+    let reversed = ["j", "i", "h", "g", "f", "e", "d", "c", "b", "a"];
+
+    // The order only depends on the seed.
+    assert_eq!(shuffled(42, &sorted), shuffled(42, &reversed));
+    assert_ne!(shuffled(42, &sorted), shuffled(43, &sorted));
+    assert_ne!(shuffled(42, &sorted), sorted);
+
+    let mut order = shuffled(42, &sorted);
+    order.sort();
+    assert_eq!(order, sorted);
+
+    // The seed is reported before the tests run.
+    let opts = TestOpts { shuffle_seed: Some(42), ..TestOpts::new() };
+    let mut seeds = Vec::new();
+    run_tests(&opts, tests(&sorted), |event| {
+        if let TestEvent::TeShuffleSeed(seed) = event {
+            seeds.push(seed);
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(seeds, [42]);
+}
+
 #[test]
 pub fn test_metricmap_compare() {
     let mut m1 = MetricMap::new();
@@ -661,24 +1564,114 @@ fn f(b: &mut Bencher) {
     bench::run_once(f);
 }
 
+#[test]
+pub fn test_bench_once_iter_batched() {
+    fn f(b: &mut Bencher) {
+        b.iter_batched(|| vec![3, 1, 2], |mut v| v.sort(), 10)
+    }
+    bench::run_once(f);
+}
+
+#[test]
+pub fn test_bench_once_iter_once() {
+    fn f(b: &mut Bencher) {
+        b.iter_once(|| {})
+    }
+    bench::run_once(f);
+}
+
+#[test]
+pub fn test_bench_iter_batched_excludes_setup() {
+    let mut setups = 0;
+    let mut routines = 0;
+    let (ns, _) = bench::ns_iter_batched(
+        &mut || {
+            setups += 1;
+            thread::sleep(Duration::from_millis(10));
+            vec![3, 1, 2]
+        },
+        &mut |mut v: Vec<i32>| {
+            routines += 1;
+            v.sort();
+            v
+        },
+        2,
+        5,
+    );
+    assert_eq!((setups, routines), (5, 5));
+    assert!(ns < Duration::from_millis(10).as_nanos() as u64);
+}
+
 #[test]
 pub fn test_bench_no_iter() {
     fn f(_: &mut Bencher) {}
 
     let (tx, rx) = channel();
 
-    let desc = TestDesc {
-        name: StaticTestName("f"),
-        ignore: false,
-        should_panic: ShouldPanic::No,
-        allow_fail: false,
-        compile_fail: false,
-        no_run: false,
-        test_type: TestType::Unknown,
+    let desc = test_desc("f");
+
+    crate::bench::benchmark(
+        TestId(0),
+        desc,
+        tx,
+        true,
+        &[],
+        false,
+        Duration::new(0, 0),
+        DEFAULT_BENCH_SAMPLES,
+        f,
+    );
+    rx.recv().unwrap();
+}
+
+#[test]
+pub fn test_bench_report_metric() {
+    fn f(b: &mut Bencher) {
+        b.iter(|| {});
+        b.report_metric("bytes_per_sec", 512.0, -2.0);
+    }
+
+    let (tx, rx) = channel();
+
+    let desc = test_desc("f");
+
+    crate::bench::benchmark(
+        TestId(0),
+        desc,
+        tx,
+        true,
+        &[],
+        false,
+        Duration::new(0, 0),
+        DEFAULT_BENCH_SAMPLES,
+        f,
+    );
+    let bs = match rx.recv().unwrap().result {
+        TestResult::TrBench(bs) => bs,
+        result => panic!("unexpected result: {:?}", result),
     };
+    let mut metrics = MetricMap::new();
+    metrics.insert_metric("bytes_per_sec", 512.0, -2.0);
+    assert_eq!(bs.metrics, metrics);
+    assert!(crate::bench::fmt_bench_samples(&bs).ends_with(" [bytes_per_sec: 512 (+/- -2)]"));
+}
 
-    crate::bench::benchmark(TestId(0), desc, tx, true, f);
-    rx.recv().unwrap();
+#[test]
+#[should_panic(expected = "metric `ratio` must be finite, got NaN (+/- 0)")]
+pub fn test_bench_report_metric_nan() {
+    fn f(b: &mut Bencher) {
+        b.report_metric("ratio", f64::NAN, 0.0);
+    }
+    bench::run_once(f);
+}
+
+#[test]
+#[should_panic(expected = "metric `ratio` must be finite, got 1 (+/- inf)")]
+pub fn test_bench_report_metric_infinite_noise() {
+    fn f(b: &mut Bencher) {
+        b.report_metric("ratio", 1.0, f64::INFINITY);
+    }
+    bench::run_once(f);
 }
 
 #[test]
@@ -689,43 +1682,199 @@ fn f(b: &mut Bencher) {
 
     let (tx, rx) = channel();
 
-    let desc = TestDesc {
-        name: StaticTestName("f"),
-        ignore: false,
-        should_panic: ShouldPanic::No,
-        allow_fail: false,
-        compile_fail: false,
-        no_run: false,
-        test_type: TestType::Unknown,
-    };
-
-    crate::bench::benchmark(TestId(0), desc, tx, true, f);
+    let desc = test_desc("f");
+
+    crate::bench::benchmark(
+        TestId(0),
+        desc,
+        tx,
+        true,
+        &[],
+        false,
+        Duration::new(0, 0),
+        DEFAULT_BENCH_SAMPLES,
+        f,
+    );
     rx.recv().unwrap();
 }
 
 #[test]
-fn should_sort_failures_before_printing_them() {
-    let test_a = TestDesc {
-        name: StaticTestName("a"),
-        ignore: false,
-        should_panic: ShouldPanic::No,
-        allow_fail: false,
-        compile_fail: false,
-        no_run: false,
-        test_type: TestType::Unknown,
-    };
+pub fn test_bench_iter_once() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-    let test_b = TestDesc {
-        name: StaticTestName("b"),
-        ignore: false,
-        should_panic: ShouldPanic::No,
-        allow_fail: false,
-        compile_fail: false,
-        no_run: false,
-        test_type: TestType::Unknown,
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    fn f(b: &mut Bencher) {
+        b.iter_once(|| {
+            RUNS.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(10));
+        })
+    }
+
+    let (tx, rx) = channel();
+
+    let desc = test_desc("f");
+
+    let warmup = Duration::from_millis(100);
+    crate::bench::benchmark(
+        TestId(0),
+        desc,
+        tx,
+        true,
+        &[50.0],
+        false,
+        warmup,
+        DEFAULT_BENCH_SAMPLES,
+        f,
+    );
+    assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+
+    let bs = match rx.recv().unwrap().result {
+        TrBench(bs) => bs,
+        result => panic!("unexpected result {:?}", result),
     };
+    let ns = bs.ns_iter_summ.median;
+    assert!(ns >= Duration::from_millis(10).as_nanos() as f64);
+    assert_eq!(bs.ns_iter_summ.min, bs.ns_iter_summ.max);
+    assert_eq!(bs.percentiles, [(50.0, ns)]);
+}
+
+#[test]
+pub fn test_bench_warmup() {
+    let warmup = Duration::from_millis(10);
+    let mut iterations = 0;
+    let start = Instant::now();
+    crate::bench::warm_up(
+        &mut |k| {
+            iterations += k;
+            (0, 0)
+        },
+        warmup,
+    );
+    assert!(start.elapsed() >= warmup);
+    assert!(iterations > 0);
+
+    let args = vec![
+        "progname".to_string(),
+        "--bench-warmup-ms=20".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.bench_warmup, Duration::from_millis(20));
+
+    let args = vec!["progname".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.bench_warmup, crate::cli::DEFAULT_BENCH_WARMUP);
+
+    let args = vec!["progname".to_string(), "--bench-warmup-ms=20".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+pub fn test_bench_samples() {
+    let (_, samples, alloc_samples) =
+        crate::bench::measure(&mut |k| (k, 0), Duration::new(0, 0), 7);
+    assert_eq!((samples.len(), alloc_samples.len()), (7, 7));
+
+    let args = vec![
+        "progname".to_string(),
+        "--bench-samples=7".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.bench_samples, 7);
+
+    let args = vec!["progname".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.bench_samples, DEFAULT_BENCH_SAMPLES);
+
+    let args = vec![
+        "progname".to_string(),
+        "--bench-samples=0".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    assert!(parse_opts(&args).unwrap().is_err());
+
+    let args = vec!["progname".to_string(), "--bench-samples=7".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+fn terse_output_is_flushed_every_interval() {
+    struct CountFlushes {
+        written: Vec<u8>,
+        flushes: Vec<usize>,
+    }
 
-    let mut out = PrettyFormatter::new(OutputLocation::Raw(Vec::new()), false, 10, false, None);
+    impl io::Write for CountFlushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.push(self.written.len());
+            Ok(())
+        }
+    }
+
+    let out = CountFlushes { written: Vec::new(), flushes: Vec::new() };
+    let mut terse = TerseFormatter::new(
+        OutputLocation::Raw(out),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        3,
+    );
+    for _ in 0..7 {
+        terse.write_ok().unwrap();
+    }
+    match terse.output_location() {
+        OutputLocation::Raw(out) => {
+            assert_eq!(out.written, b".......");
+            assert_eq!(out.flushes, [3, 6]);
+        }
+        OutputLocation::Pretty(_) => unreachable!(),
+    }
+
+    let args = vec![
+        "progname".to_string(),
+        "--terse-flush-interval=10".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.terse_flush_interval, 10);
+
+    let args = vec!["progname".to_string()];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.terse_flush_interval, DEFAULT_TERSE_FLUSH_INTERVAL);
+
+    let args = vec![
+        "progname".to_string(),
+        "--terse-flush-interval=0".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    assert!(parse_opts(&args).unwrap().is_err());
+
+    let args = vec!["progname".to_string(), "--terse-flush-interval=1".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+fn should_sort_failures_before_printing_them() {
+    let test_a = test_desc("a");
+
+    let test_b = test_desc("b");
+
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
 
     let st = console::ConsoleTestState {
         log_out: None,
@@ -736,12 +1885,17 @@ fn should_sort_failures_before_printing_them() {
         allowed_fail: 0,
         filtered_out: 0,
         measured: 0,
+        not_run: 0,
         exec_time: None,
         metrics: MetricMap::new(),
         failures: vec![(test_b, Vec::new()), (test_a, Vec::new())],
         options: Options::new(),
         not_failures: Vec::new(),
         time_failures: Vec::new(),
+        large_outputs: Vec::new(),
+        exec_times: Vec::new(),
+        outcomes: BTreeMap::new(),
+        run_ignored: RunIgnored::No,
     };
 
     out.write_failures(&st).unwrap();
@@ -754,3 +1908,599 @@ fn should_sort_failures_before_printing_them() {
     let bpos = s.find("b").unwrap();
     assert!(apos < bpos);
 }
+
+#[test]
+fn should_colorize_only_failures_with_errors_only_color() {
+    use crate::formatters::OutputFormatter;
+    use term::terminfo::{parser::compiled::msys_terminfo, TerminfoTerminal};
+
+    let output = |theme| {
+        let term = TerminfoTerminal::new_with_terminfo(Vec::new(), msys_terminfo());
+        let mut out = PrettyFormatter::new(
+            OutputLocation::Pretty(Box::new(term)),
+            true,
+            true,
+            theme,
+            0,
+            true,
+            None,
+        );
+        let st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+        out.write_result(&test_desc("passing"), &TrOk, None, None, None, &[], &[], &st).unwrap();
+        out.write_result(&test_desc("failing"), &TrFailed, None, None, None, &[], &[], &st)
+            .unwrap();
+        match out.output_location() {
+            &OutputLocation::Raw(_) => unreachable!(),
+            &OutputLocation::Pretty(ref term) => {
+                String::from_utf8_lossy(&term.get_ref()[..]).into_owned()
+            }
+        }
+    };
+
+    assert_eq!(
+        output(ColorTheme::DEFAULT),
+        "test passing ... ok\ntest failing ... \x1B[31mFAILED\x1B[0m\n"
+    );
+    // The passes aren't colorized even when they have the color of the failures.
+    let theme = ColorTheme { ok: term::color::RED, ..ColorTheme::DEFAULT };
+    assert_eq!(output(theme), "test passing ... ok\ntest failing ... \x1B[31mFAILED\x1B[0m\n");
+}
+
+#[test]
+fn should_colorize_with_custom_theme() {
+    use crate::formatters::OutputFormatter;
+    use term::terminfo::{parser::compiled::msys_terminfo, TerminfoTerminal};
+
+    let desc = test_desc("failing");
+    let theme = ColorTheme { failed: term::color::MAGENTA, ..ColorTheme::DEFAULT };
+
+    let term = TerminfoTerminal::new_with_terminfo(Vec::new(), msys_terminfo());
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Pretty(Box::new(term)),
+        true,
+        false,
+        theme,
+        0,
+        true,
+        None,
+    );
+    let st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+    out.write_result(&desc, &TrFailed, None, None, None, &[], &[], &st).unwrap();
+
+    let s = match out.output_location() {
+        &OutputLocation::Raw(_) => unreachable!(),
+        &OutputLocation::Pretty(ref term) => String::from_utf8_lossy(&term.get_ref()[..]),
+    };
+    assert_eq!(s, "test failing ... \x1B[35mFAILED\x1B[0m\n");
+}
+
+#[test]
+fn test_use_color() {
+    use crate::cli::use_color;
+    use std::ffi::OsStr;
+
+    let no_color = Some(OsStr::new("1"));
+    let empty_no_color = Some(OsStr::new(""));
+
+    // `--color=auto` colors the output of a terminal, unless `NO_COLOR` is set
+    // to a non-empty value, or the output of the tests isn't captured.
+    assert!(use_color(AutoColor, None, false, true));
+    assert!(use_color(AutoColor, empty_no_color, false, true));
+    assert!(!use_color(AutoColor, no_color, false, true));
+    assert!(!use_color(AutoColor, None, true, true));
+    assert!(!use_color(AutoColor, None, false, false));
+
+    // The other values of `--color` take precedence over all of them.
+    assert!(use_color(AlwaysColor, no_color, true, false));
+    assert!(use_color(ErrorsOnlyColor, no_color, true, false));
+    assert!(!use_color(NeverColor, None, false, true));
+}
+
+#[test]
+fn should_highlight_assertion_values_in_failures() {
+    use term::terminfo::{parser::compiled::msys_terminfo, TerminfoTerminal};
+
+    let output = "thread 'failing' panicked at 'assertion failed: `(left == right)`\n  \
+                  left: `1`,\n right: `2`', src/lib.rs:4:9\nnote: run with `RUST_BACKTRACE=1`\n";
+
+    let desc = test_desc("failing");
+    let mut st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+    st.failures.push((desc.clone(), output.as_bytes().to_vec()));
+    st.failures.push((desc, b"thread 'failing' panicked at 'explicit panic'\n".to_vec()));
+
+    let term = TerminfoTerminal::new_with_terminfo(Vec::new(), msys_terminfo());
+    // Both values are colorized as failures, even with `--color=errors-only`.
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Pretty(Box::new(term)),
+        true,
+        true,
+        ColorTheme::DEFAULT,
+        0,
+        true,
+        None,
+    );
+    out.write_failures(&st).unwrap();
+
+    let s = match out.output_location() {
+        &OutputLocation::Raw(_) => unreachable!(),
+        &OutputLocation::Pretty(ref term) => String::from_utf8_lossy(&term.get_ref()[..]),
+    };
+    assert!(s.contains(
+        "\x1B[31m  left: `1`,\x1B[0m\n\x1B[31m right: `2`', src/lib.rs:4:9\x1B[0m\nnote: run"
+    ));
+    // Other panic messages are written verbatim.
+    assert!(
+        s.contains("\n---- failing stdout ----\nthread 'failing' panicked at 'explicit panic'\n")
+    );
+}
+
+#[test]
+fn should_pass_events_to_reporters() {
+    use crate::event::{CompletedTest, TestEvent};
+    use crate::reporter::Reporter;
+    use std::{cell::Cell, io, rc::Rc};
+
+    struct CountingReporter(Rc<Cell<usize>>);
+
+    impl Reporter for CountingReporter {
+        fn on_event(&mut self, _event: &TestEvent) -> io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    struct FailingReporter;
+
+    impl Reporter for FailingReporter {
+        fn on_event(&mut self, _event: &TestEvent) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Other, "metrics server unreachable"))
+        }
+    }
+
+    let desc = test_desc("a");
+
+    let count = Rc::new(Cell::new(0));
+    let mut reporters: Vec<Box<dyn Reporter>> =
+        vec![Box::new(FailingReporter), Box::new(CountingReporter(count.clone()))];
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+    let mut st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+
+    let events = vec![
+        TestEvent::TeFiltered(vec![desc.clone()]),
+        TestEvent::TeWait(desc.clone()),
+        TestEvent::TeResult(CompletedTest::new(TestId(0), desc, TrOk, None, Vec::new())),
+    ];
+    for event in &events {
+        // The failing reporter doesn't abort the run nor hide the event from the other one.
+        console::on_test_event(event, &mut st, &mut out, &mut reporters).unwrap();
+    }
+
+    assert_eq!(count.get(), 3);
+    assert_eq!(st.passed, 1);
+}
+
+#[test]
+pub fn test_bench_percentiles() {
+    fn f(b: &mut Bencher) {
+        b.iter(|| {})
+    }
+
+    let (tx, rx) = channel();
+
+    let desc = test_desc("f");
+
+    let percentiles = [0.0, 50.0, 100.0];
+    crate::bench::benchmark(
+        TestId(0),
+        desc,
+        tx,
+        true,
+        &percentiles,
+        false,
+        Duration::new(0, 0),
+        DEFAULT_BENCH_SAMPLES,
+        f,
+    );
+    match rx.recv().unwrap().result {
+        TestResult::TrBench(bs) => {
+            let pcts = bs.percentiles.iter().map(|&(pct, _)| pct).collect::<Vec<_>>();
+            assert_eq!(pcts, vec![0.0, 50.0, 100.0]);
+            // The samples the percentiles are taken from are the ones summarized.
+            assert_eq!(bs.percentiles[0].1, bs.ns_iter_summ.min);
+            assert_eq!(bs.percentiles[1].1, bs.ns_iter_summ.median);
+            assert_eq!(bs.percentiles[2].1, bs.ns_iter_summ.max);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn test_hashed_name() {
+    use crate::formatters::hashed_name;
+
+    // The hashes must not change across runs nor releases.
+    assert_eq!(hashed_name(""), "cbf29ce484222325");
+    assert_eq!(hashed_name("a"), "af63dc4c8601ec8c");
+    assert_eq!(hashed_name("tests::it_works"), "cb78325bdb23689e");
+}
+
+#[test]
+fn should_hash_names_in_json_output_only() {
+    use crate::formatters::{JsonFormatter, OutputFormatter};
+
+    let desc = test_desc("tests::it_works");
+    let st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+
+    let mut json = Vec::new();
+    let mut out = JsonFormatter::new(OutputLocation::Raw(&mut json), true);
+    out.write_test_start(&desc).unwrap();
+    out.write_result(&desc, &TrOk, None, None, None, &[], &[], &st).unwrap();
+    drop(out);
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains(r#""name": "cb78325bdb23689e""#));
+    assert!(!json.contains("it_works"));
+
+    let mut pretty = Vec::new();
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(&mut pretty),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+    out.write_test_start(&desc).unwrap();
+    out.write_result(&desc, &TrOk, None, None, None, &[], &[], &st).unwrap();
+    drop(out);
+    assert_eq!(String::from_utf8(pretty).unwrap(), "test tests::it_works ... ok\n");
+}
+
+#[test]
+fn should_escape_failures_in_junit_output() {
+    use crate::formatters::{JunitFormatter, OutputFormatter};
+
+    let desc = |name, ignore| TestDesc { ignore, test_type: TestType::UnitTest, ..test_desc(name) };
+    let failing = desc("tests::failing", false);
+    let ignored = desc("tests::ignored", true);
+    let mut st = console::ConsoleTestState::new(&TestOpts::new()).unwrap();
+    st.total = 2;
+    st.failed = 1;
+    st.ignored = 1;
+
+    let mut xml = Vec::new();
+    let mut out = JunitFormatter::new(OutputLocation::Raw(&mut xml), false);
+    out.write_run_start(2).unwrap();
+    let result = TestResult::TrFailedMsg("expected <a> & \"b\"".to_string());
+    let stdout = b"thread 'tests::failing' panicked at 'a < b'\n";
+    out.write_result(&failing, &result, None, None, None, stdout, &[], &st).unwrap();
+    out.write_result(&ignored, &TrIgnored, None, None, None, &[], &[], &st).unwrap();
+    out.write_run_finish(&st).unwrap();
+    drop(out);
+    let xml = String::from_utf8(xml).unwrap();
+
+    assert!(xml.contains(
+        "<testcase classname=\"tests\" name=\"failing\" time=\"0\">\
+         <failure message=\"expected &lt;a&gt; &amp; &quot;b&quot;\" type=\"assert\">\
+         thread &apos;tests::failing&apos; panicked at &apos;a &lt; b&apos;&#10;\
+         </failure></testcase>"
+    ));
+    assert!(xml.contains(
+        "<testcase classname=\"tests\" name=\"ignored\" time=\"0\"><skipped/></testcase>"
+    ));
+    assert!(!xml.contains('\n'));
+}
+
+#[test]
+fn should_report_large_outputs() {
+    use crate::event::{CompletedTest, TestEvent};
+    use crate::formatters::large_outputs;
+
+    let mut opts = TestOpts::new();
+    opts.options = opts.options.report_output_size(true).max_output_warn(100);
+    let mut st = console::ConsoleTestState::new(&opts).unwrap();
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+
+    let chatty = CompletedTest::new(TestId(0), test_desc("chatty"), TrOk, None, vec![b'x'; 101]);
+    let quiet = CompletedTest::new(TestId(1), test_desc("quiet"), TrOk, None, vec![b'x'; 100]);
+    for completed_test in vec![chatty, quiet] {
+        let event = TestEvent::TeResult(completed_test);
+        console::on_test_event(&event, &mut st, &mut out, &mut []).unwrap();
+    }
+
+    assert_eq!(
+        large_outputs(&st),
+        "\ntests with large output (over 100 bytes):\n    chatty: 101 bytes\n"
+    );
+}
+
+#[test]
+fn should_report_slowest_tests() {
+    use crate::event::{CompletedTest, TestEvent};
+    use crate::formatters::slowest_tests;
+
+    let args = vec![
+        "progname".to_string(),
+        "--report-slowest=2".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert_eq!(opts.options.report_slowest, Some(2));
+    let mut st = console::ConsoleTestState::new(&opts).unwrap();
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+
+    let bench = BenchSamples {
+        ns_iter_summ: crate::stats::Summary::new(&[1000.0]),
+        mb_s: 0,
+        percentiles: Vec::new(),
+        allocs_per_iter: None,
+        metrics: MetricMap::new(),
+    };
+    let results = vec![
+        ("fast", TrOk, 10),
+        ("slow", TrFailed, 300),
+        ("medium", TrOk, 200),
+        ("bench", TestResult::TrBench(bench), 1000),
+    ];
+    for (id, (name, result, millis)) in results.into_iter().enumerate() {
+        let mut completed_test =
+            CompletedTest::new(TestId(id), test_desc(name), result, None, Vec::new());
+        completed_test.exec_time = Some(test_exec_time(millis));
+        let event = TestEvent::TeResult(completed_test);
+        console::on_test_event(&event, &mut st, &mut out, &mut []).unwrap();
+    }
+
+    assert_eq!(slowest_tests(&st), "\nslowest tests:\n    slow: 0.300s\n    medium: 0.200s\n");
+
+    let args = vec!["progname".to_string(), "--report-slowest=2".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+fn test_run_tests_with_observer() {
+    fn pass() {}
+    fn fail() {
+        panic!();
+    }
+
+    let tests = vec![
+        TestDescAndFn { desc: test_desc("fail"), testfn: StaticTestFn(fail) },
+        TestDescAndFn { desc: test_desc("pass"), testfn: StaticTestFn(pass) },
+    ];
+
+    let mut results = Vec::new();
+    let opts = TestOpts { run_tests: true, test_threads: Some(1), ..TestOpts::new() };
+    crate::run_tests_with_observer(&opts, tests, &mut |completed_test| {
+        results.push((completed_test.desc.name.to_string(), completed_test.result.clone()));
+    })
+    .unwrap();
+
+    assert_eq!(results, [("fail".to_string(), TrFailed), ("pass".to_string(), TrOk)]);
+}
+
+#[test]
+fn should_stop_after_first_failure_with_fail_fast() {
+    use crate::formatters::OutputFormatter;
+
+    fn pass() {}
+    fn fail() {
+        panic!();
+    }
+
+    let tests = vec![
+        TestDescAndFn { desc: test_desc("a"), testfn: StaticTestFn(pass) },
+        TestDescAndFn { desc: test_desc("b"), testfn: StaticTestFn(fail) },
+        TestDescAndFn { desc: test_desc("c"), testfn: StaticTestFn(pass) },
+        TestDescAndFn { desc: test_desc("d"), testfn: StaticTestFn(pass) },
+    ];
+
+    let args = vec![
+        "progname".to_string(),
+        "--fail-fast".to_string(),
+        "--test-threads=1".to_string(),
+        "-Zunstable-options".to_string(),
+    ];
+    let opts = parse_opts(&args).unwrap().unwrap();
+    assert!(opts.fail_fast);
+
+    let mut st = console::ConsoleTestState::new(&opts).unwrap();
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+    run_tests(&opts, tests, |event| console::on_test_event(&event, &mut st, &mut out, &mut []))
+        .unwrap();
+    assert_eq!((st.passed, st.failed, st.not_run), (1, 1, 2));
+    // The run still reports how long it took, without `--report-time`.
+    assert!(st.exec_time.is_some());
+
+    out.write_run_finish(&st).unwrap();
+    let s = match out.output_location() {
+        &OutputLocation::Raw(ref m) => String::from_utf8_lossy(&m[..]),
+        &OutputLocation::Pretty(_) => unreachable!(),
+    };
+    assert!(s.contains("; aborted after first failure; 2 tests not run; finished in "));
+
+    let args = vec!["progname".to_string(), "--fail-fast".to_string()];
+    assert!(parse_opts(&args).unwrap().is_err());
+}
+
+#[test]
+fn should_flag_flaky_tests_over_repeated_runs() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FLAKY_RUNS: AtomicUsize = AtomicUsize::new(0);
+    fn flaky() {
+        assert_ne!(FLAKY_RUNS.fetch_add(1, Ordering::SeqCst), 1);
+    }
+    fn broken() {
+        panic!();
+    }
+
+    let tests = vec![
+        TestDescAndFn { desc: test_desc("broken"), testfn: StaticTestFn(broken) },
+        TestDescAndFn { desc: test_desc("flaky"), testfn: StaticTestFn(flaky) },
+    ];
+
+    let opts = TestOpts {
+        run_tests: true,
+        repeat: 3,
+        test_threads: Some(1),
+        flakiness_report: Some(PathBuf::from("flakiness.json")),
+        ..TestOpts::new()
+    };
+    let mut st = console::ConsoleTestState::new(&opts).unwrap();
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+    run_tests(&opts, tests, |event| console::on_test_event(&event, &mut st, &mut out, &mut []))
+        .unwrap();
+
+    assert_eq!(st.total, 6);
+    assert_eq!(
+        console::flakiness_report(&st),
+        "{ \"tests\": [\n\
+         \x20 { \"name\": \"broken\", \"passed\": 0, \"failed\": 3, \"flaky\": false },\n\
+         \x20 { \"name\": \"flaky\", \"passed\": 2, \"failed\": 1, \"flaky\": true }\n\
+         ] }\n"
+    );
+}
+
+#[test]
+fn test_server() {
+    fn f() {}
+
+    let tests = ["a", "b"]
+        .iter()
+        .map(|name| TestDescAndFn { desc: test_desc(name), testfn: StaticTestFn(f) })
+        .collect();
+
+    let input: &[u8] = b"{ \"command\": \"list\" }\n\
+                         { \"command\": \"run\", \"name\": \"b\" }\n\
+                         { \"command\": \"run\", \"name\": \"c\" }\n";
+    let mut output = Vec::new();
+    crate::server::serve(&TestOpts::new(), tests, input, &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            r#"{ "type": "list", "tests": ["a", "b"] }"#,
+            r#"{ "type": "suite", "event": "started", "test_count": 1 }"#,
+            r#"{ "type": "test", "event": "started", "name": "b" }"#,
+            r#"{ "type": "test", "name": "b", "event": "ok" }"#,
+            concat!(
+                r#"{ "type": "suite", "event": "ok", "passed": 1, "failed": 0, "#,
+                r#""allowed_fail": 0, "ignored": 0, "measured": 0, "filtered_out": 0 }"#
+            ),
+            r#"{ "type": "error", "message": "no test named `c`" }"#,
+        ]
+    );
+}
+
+#[test]
+fn test_rerun_command() {
+    use crate::formatters::rerun_command;
+
+    assert_eq!(
+        rerun_command("target/debug/deps/foo-1234", "module::test_name", RunIgnored::No),
+        "target/debug/deps/foo-1234 --exact 'module::test_name' --nocapture"
+    );
+    assert_eq!(
+        rerun_command("/path with spaces/foo", "it's - (line 3)", RunIgnored::Only),
+        r"'/path with spaces/foo' --exact 'it'\''s - (line 3)' --nocapture --ignored"
+    );
+    assert_eq!(
+        rerun_command("foo", "bar", RunIgnored::Yes),
+        "foo --exact 'bar' --nocapture --include-ignored"
+    );
+}
+
+#[test]
+fn should_print_rerun_commands_for_failures() {
+    use crate::formatters::{rerun_command, OutputFormatter};
+
+    let failed = typed_test_desc(TestType::UnitTest);
+    let mut passed = typed_test_desc(TestType::UnitTest);
+    passed.name = StaticTestName("passed");
+
+    let mut out = PrettyFormatter::new(
+        OutputLocation::Raw(Vec::new()),
+        false,
+        false,
+        ColorTheme::DEFAULT,
+        10,
+        false,
+        None,
+    );
+
+    let st = console::ConsoleTestState {
+        log_out: None,
+        total: 2,
+        passed: 1,
+        failed: 1,
+        ignored: 0,
+        allowed_fail: 0,
+        filtered_out: 0,
+        measured: 0,
+        not_run: 0,
+        exec_time: None,
+        metrics: MetricMap::new(),
+        failures: vec![(failed, Vec::new())],
+        options: Options::new().print_rerun(true),
+        not_failures: vec![(passed, Vec::new())],
+        time_failures: Vec::new(),
+        large_outputs: Vec::new(),
+        exec_times: Vec::new(),
+        outcomes: BTreeMap::new(),
+        run_ignored: RunIgnored::No,
+    };
+
+    out.write_run_finish(&st).unwrap();
+    let s = match out.output_location() {
+        &OutputLocation::Raw(ref m) => String::from_utf8_lossy(&m[..]),
+        &OutputLocation::Pretty(_) => unreachable!(),
+    };
+
+    let binary = env::args().next().unwrap();
+    let expected = format!("    {}", rerun_command(&binary, "whatever", RunIgnored::No));
+    assert!(expected.ends_with(" --exact 'whatever' --nocapture"));
+    let rerun_lines = s.lines().filter(|line| line.contains(" --exact ")).collect::<Vec<_>>();
+    assert_eq!(rerun_lines, vec![&*expected]);
+}
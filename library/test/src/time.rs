@@ -61,23 +61,38 @@ pub fn get_default_test_timeout() -> Instant {
     Instant::now() + Duration::from_secs(TEST_WARN_TIMEOUT_S)
 }
 
+/// Formats `time` with `precision` decimals if one was requested via
+/// `--time-precision`, and with the default precision of its type otherwise.
+pub fn fmt_time<T: fmt::Display>(time: &T, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*}", precision, time),
+        None => time.to_string(),
+    }
+}
+
 /// The measured execution time of a unit test.
+///
+/// Displayed in seconds with 3 decimals, unless another precision is given
+/// (e.g. `{:.1}`).
 #[derive(Debug, Clone, PartialEq)]
 pub struct TestExecTime(pub Duration);
 
 impl fmt::Display for TestExecTime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:.3}s", self.0.as_secs_f64())
+        write!(f, "{:.*}s", f.precision().unwrap_or(3), self.0.as_secs_f64())
     }
 }
 
 /// The measured execution time of the whole test suite.
+///
+/// Displayed in seconds with 2 decimals, unless another precision is given
+/// (e.g. `{:.1}`).
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct TestSuiteExecTime(pub Duration);
 
 impl fmt::Display for TestSuiteExecTime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:.2}s", self.0.as_secs_f64())
+        write!(f, "{:.*}s", f.precision().unwrap_or(2), self.0.as_secs_f64())
     }
 }
 
@@ -2,6 +2,7 @@
 
 use std::borrow::Cow;
 use std::fmt;
+use std::sync::Arc;
 
 use super::bench::Bencher;
 use super::options;
@@ -122,14 +123,82 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 pub struct TestDesc {
     pub name: TestName,
     pub ignore: bool,
+    /// Reason the test is ignored for, given to `#[ignore = "..."]`. It's kept
+    /// when the test is run anyway, with `--ignored` or `--include-ignored`.
+    #[cfg(not(bootstrap))]
+    pub ignore_message: Option<&'static str>,
     pub should_panic: options::ShouldPanic,
     pub allow_fail: bool,
     pub compile_fail: bool,
     pub no_run: bool,
     pub test_type: TestType,
+    /// Environment variables to set for the test when it is run in a subprocess
+    /// (see `RunStrategy::SpawnPrimary`). They are ignored for tests that are run
+    /// in-process, since those can't be isolated from the other tests.
+    #[cfg(not(bootstrap))]
+    pub env: &'static [(&'static str, &'static str)],
+    /// Path of the source file defining the test, as given to the compiler. It's
+    /// empty when unknown, e.g. for tests created at runtime.
+    #[cfg(not(bootstrap))]
+    pub source_file: &'static str,
+    /// Line and column, starting at 1, where the name of the test function
+    /// starts and ends in `source_file`, or 0 when unknown.
+    #[cfg(not(bootstrap))]
+    pub start_line: usize,
+    #[cfg(not(bootstrap))]
+    pub start_col: usize,
+    #[cfg(not(bootstrap))]
+    pub end_line: usize,
+    #[cfg(not(bootstrap))]
+    pub end_col: usize,
+}
+
+/// Location of a test in its source file, see `TestDesc::source_location`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub source_file: &'static str,
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
 }
 
 impl TestDesc {
+    /// Returns the location of the test in its source file, unless it's unknown.
+    #[cfg(not(bootstrap))]
+    pub fn source_location(&self) -> Option<SourceLocation> {
+        if self.source_file.is_empty() {
+            return None;
+        }
+        Some(SourceLocation {
+            source_file: self.source_file,
+            start_line: self.start_line,
+            start_col: self.start_col,
+            end_line: self.end_line,
+            end_col: self.end_col,
+        })
+    }
+
+    /// Returns the location of the test in its source file, which the bootstrap
+    /// compiler doesn't record.
+    #[cfg(bootstrap)]
+    pub fn source_location(&self) -> Option<SourceLocation> {
+        None
+    }
+
+    /// Returns the reason the test is ignored for, if any.
+    #[cfg(not(bootstrap))]
+    pub fn ignore_message(&self) -> Option<&'static str> {
+        self.ignore_message
+    }
+
+    /// Returns the reason the test is ignored for, which the bootstrap compiler
+    /// doesn't record.
+    #[cfg(bootstrap)]
+    pub fn ignore_message(&self) -> Option<&'static str> {
+        None
+    }
+
     pub fn padded_name(&self, column_count: usize, align: NamePadding) -> String {
         let mut name = String::from(self.name.as_slice());
         let fill = column_count.saturating_sub(name.len());
@@ -150,7 +219,9 @@ pub fn test_mode(&self) -> Option<&'static str> {
             return None;
         }
         match self.should_panic {
-            options::ShouldPanic::Yes | options::ShouldPanic::YesWithMessage(_) => {
+            options::ShouldPanic::Yes
+            | options::ShouldPanic::YesWithMessage(_)
+            | options::ShouldPanic::YesWithRegex(_) => {
                 return Some("should panic");
             }
             options::ShouldPanic::No => {}
@@ -173,3 +244,20 @@ pub struct TestDescAndFn {
     pub desc: TestDesc,
     pub testfn: TestFn,
 }
+
+/// Callback run around each test by a custom test harness, see
+/// `TestOpts::before_each` and `TestOpts::after_each`.
+#[derive(Clone)]
+pub struct TestHook(pub Arc<dyn Fn(&TestDesc) + Send + Sync>);
+
+impl TestHook {
+    pub fn new(hook: impl Fn(&TestDesc) + Send + Sync + 'static) -> TestHook {
+        TestHook(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for TestHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TestHook(..)")
+    }
+}
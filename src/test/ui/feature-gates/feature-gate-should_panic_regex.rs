@@ -0,0 +1,8 @@
+// compile-flags: --test
+
+#[test]
+#[should_panic(expected_regex = "^oops: \\d+$")]
+//~^ ERROR `expected_regex` in `#[should_panic]` is unstable
+fn panics() {
+    panic!("oops: {}", 42);
+}
@@ -0,0 +1,69 @@
+// run-pass
+// ignore-emscripten no threads support
+
+// Checks the allocations per iteration reported with `--bench-count-allocs`.
+// This has to be its own program, since counting the allocations needs
+// `CountingAlloc` to be the global allocator.
+
+#![feature(test)]
+
+extern crate test;
+
+use test::bench::CountingAlloc;
+use test::test::parse_opts;
+use test::{black_box, Bencher, ShouldPanic, StaticBenchFn, StaticTestName, TestDesc};
+use test::{TestDescAndFn, TestResult, TestType};
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc(std::alloc::System);
+
+fn allocates(b: &mut Bencher) {
+    b.iter(|| Box::new(black_box(1u8)))
+}
+
+fn does_not_allocate(b: &mut Bencher) {
+    b.iter(|| black_box(1u8))
+}
+
+fn bench(name: &'static str, f: fn(&mut Bencher)) -> TestDescAndFn {
+    let desc = TestDesc {
+        name: StaticTestName(name),
+        ignore: false,
+        ignore_message: None,
+        should_panic: ShouldPanic::No,
+        allow_fail: false,
+        compile_fail: false,
+        no_run: false,
+        test_type: TestType::IntegrationTest,
+        env: &[],
+        source_file: "",
+        start_line: 0,
+        start_col: 0,
+        end_line: 0,
+        end_col: 0,
+    };
+    TestDescAndFn { desc, testfn: StaticBenchFn(f) }
+}
+
+fn main() {
+    let args = ["--bench", "--bench-count-allocs", "--bench-warmup-ms=0", "-Zunstable-options"];
+    let args = ["progname"].iter().chain(&args).map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let opts = parse_opts(&args).unwrap().unwrap();
+    let tests = vec![bench("allocates", allocates), bench("does_not_allocate", does_not_allocate)];
+
+    let mut allocs_per_iter = Vec::new();
+    test::run_tests_with_observer(&opts, tests, &mut |completed_test| {
+        match completed_test.result {
+            TestResult::TrBench(ref bs) => {
+                allocs_per_iter.push((completed_test.desc.name.to_string(), bs.allocs_per_iter))
+            }
+            ref result => panic!("unexpected result: {:?}", result),
+        }
+    })
+    .unwrap();
+
+    assert_eq!(
+        allocs_per_iter,
+        [("allocates".to_string(), Some(1.0)), ("does_not_allocate".to_string(), Some(0.0))]
+    );
+}
@@ -0,0 +1,23 @@
+// no-prefer-dynamic
+// compile-flags: --test
+// run-flags: --test-threads=1
+// run-pass
+// check-run-results
+// normalize-stdout-test "finished in \d+\.\d+s" -> "finished in $$TIME"
+// ignore-wasm32 no support for `Instant`
+
+// Tests that the reason given to `#[ignore]` is shown by the test harness.
+
+#![cfg(test)]
+
+#[test]
+#[ignore = "flaky on CI"]
+fn ignored_with_reason() {
+    unreachable!();
+}
+
+#[test]
+#[ignore]
+fn ignored_without_reason() {
+    unreachable!();
+}
@@ -0,0 +1,15 @@
+// compile-flags: --test
+// run-fail
+// run-flags: --test-threads=1
+// check-run-results
+// exec-env:RUST_BACKTRACE=0
+// normalize-stdout-test "finished in \d+\.\d+s" -> "finished in $$TIME"
+// ignore-wasm32-bare compiled with panic=abort by default
+// ignore-emscripten no threads support
+#![feature(should_panic_regex)]
+
+#[test]
+#[should_panic(expected_regex = "^foo: \\d+$")]
+pub fn test_foo() {
+    panic!("foo: bar")
+}
@@ -0,0 +1,16 @@
+// run-pass
+// ignore-wasm32-bare compiled with panic=abort by default
+// compile-flags: --test
+#![feature(should_panic_regex)]
+
+#[test]
+#[should_panic(expected_regex = "^foo: \\d+ ba[rz]$")]
+pub fn test_foo() {
+    panic!("foo: 42 bar")
+}
+
+#[test]
+#[should_panic(expected_regex = "f.*o")]
+pub fn test_foo_dynamic() {
+    panic!("{} bar", "foo")
+}
@@ -0,0 +1,23 @@
+// compile-flags: --test
+// run-fail
+// run-flags: --test-threads=1 --show-output
+// check-run-results
+// exec-env:RUST_BACKTRACE=0
+// normalize-stdout-test "finished in \d+\.\d+s" -> "finished in $$TIME"
+// ignore-emscripten no threads support
+
+// Checks that the output of a test to stderr, panic messages included, is shown
+// after its output to stdout, in its own section.
+
+#[test]
+fn prints_and_passes() {
+    eprintln!("to stderr");
+    println!("to stdout");
+}
+
+#[test]
+fn prints_and_fails() {
+    eprintln!("to stderr");
+    println!("to stdout");
+    panic!("oh no");
+}
@@ -0,0 +1,47 @@
+// run-pass
+// run-flags: --force-subprocess -Zunstable-options
+// ignore-wasm no subprocess support
+// ignore-emscripten no subprocess support
+// ignore-sgx no subprocess support
+
+// Checks that the environment variables of a test are set in the process it is
+// run in, and only there.
+
+#![feature(test)]
+
+extern crate test;
+
+use std::env;
+use test::{ShouldPanic, StaticTestFn, StaticTestName, TestDesc, TestDescAndFn, TestType};
+
+const VAR: &str = "TEST_SUBPROCESS_ENV";
+
+fn sees_its_env() {
+    assert_eq!(env::var(VAR).as_deref(), Ok("overridden"));
+}
+
+const TEST: TestDescAndFn = TestDescAndFn {
+    desc: TestDesc {
+        name: StaticTestName("sees_its_env"),
+        ignore: false,
+        ignore_message: None,
+        should_panic: ShouldPanic::No,
+        allow_fail: false,
+        compile_fail: false,
+        no_run: false,
+        test_type: TestType::IntegrationTest,
+        env: &[(VAR, "overridden")],
+        source_file: "",
+        start_line: 0,
+        start_col: 0,
+        end_line: 0,
+        end_col: 0,
+    },
+    testfn: StaticTestFn(sees_its_env),
+};
+
+fn main() {
+    // The test itself fails if it's run in this process.
+    assert!(env::var_os(VAR).is_none());
+    test::test_main_static(&[&TEST]);
+}
@@ -1,7 +1,7 @@
 use clippy_utils::{
     can_move_expr_to_closure_no_visit,
     diagnostics::span_lint_and_sugg,
-    is_expr_final_block_expr, is_expr_used_or_unified, match_def_path, paths, peel_hir_expr_while,
+    is_expr_final_block_expr, is_expr_used_or_unified, is_from_proc_macro, match_def_path, paths, peel_hir_expr_while,
     source::{reindent_multiline, snippet_indent, snippet_with_applicability, snippet_with_context},
     SpanlessEq,
 };
@@ -73,7 +73,13 @@ fn check_expr(&mut self, cx: &LateContext<'tcx>, expr: &'tcx Expr<'_>) {
             None => return,
         };
 
-        let mut app = Applicability::MachineApplicable;
+        // Inside of a macro, the suggestion would replace the code of its definition with the snippets
+        // of one of its calls.
+        let mut app = if expr.span.from_expansion() {
+            Applicability::MaybeIncorrect
+        } else {
+            Applicability::MachineApplicable
+        };
         let map_str = snippet_with_context(cx, contains_expr.map.span, contains_expr.call_ctxt, "..", &mut app).0;
         let key_str = snippet_with_context(cx, contains_expr.key.span, contains_expr.call_ctxt, "..", &mut app).0;
         let sugg = if let Some(else_expr) = else_expr {
@@ -430,9 +436,11 @@ fn visit_expr(&mut self, expr: &'tcx Expr<'_>) {
         match try_parse_insert(self.cx, expr) {
             Some(insert_expr) if SpanlessEq::new(self.cx).eq_expr(self.map, insert_expr.map) => {
                 // Multiple inserts, inserts with a different key, and inserts from a macro can't use the entry api.
+                // Neither can values generated by a proc macro, their snippets being unreliable.
                 if self.is_map_used
                     || !SpanlessEq::new(self.cx).eq_expr(self.key, insert_expr.key)
                     || expr.span.ctxt() != self.ctxt
+                    || is_from_proc_macro(self.cx, insert_expr.value.span)
                 {
                     self.can_use_entry = false;
                     return;
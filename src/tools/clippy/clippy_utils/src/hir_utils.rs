@@ -13,7 +13,8 @@
 use rustc_lexer::{tokenize, TokenKind};
 use rustc_lint::LateContext;
 use rustc_middle::ty::TypeckResults;
-use rustc_span::Symbol;
+use rustc_span::hygiene::ExpnKind;
+use rustc_span::{Span, Symbol};
 use std::hash::{Hash, Hasher};
 
 /// Type used to check whether two ast are the same. This is different from the
@@ -27,6 +28,7 @@ pub struct SpanlessEq<'a, 'tcx> {
     cx: &'a LateContext<'tcx>,
     maybe_typeck_results: Option<&'tcx TypeckResults<'tcx>>,
     allow_side_effects: bool,
+    ignore_desugarings: bool,
     expr_fallback: Option<Box<dyn FnMut(&Expr<'_>, &Expr<'_>) -> bool + 'a>>,
 }
 
@@ -36,6 +38,7 @@ pub fn new(cx: &'a LateContext<'tcx>) -> Self {
             cx,
             maybe_typeck_results: cx.maybe_typeck_results(),
             allow_side_effects: true,
+            ignore_desugarings: false,
             expr_fallback: None,
         }
     }
@@ -48,6 +51,16 @@ pub fn deny_side_effects(self) -> Self {
         }
     }
 
+    /// Consider the expressions coming from a desugaring, e.g. of `?` or `for`, to be in the
+    /// macro context of the code the desugaring comes from, when checking that two expressions
+    /// without side effects come from the same context.
+    pub fn ignore_desugarings(self) -> Self {
+        Self {
+            ignore_desugarings: true,
+            ..self
+        }
+    }
+
     pub fn expr_fallback(self, expr_fallback: impl FnMut(&Expr<'_>, &Expr<'_>) -> bool + 'a) -> Self {
         Self {
             expr_fallback: Some(Box::new(expr_fallback)),
@@ -174,9 +187,17 @@ pub fn eq_body(&mut self, left: BodyId, right: BodyId) -> bool {
         eval_const(left) == eval_const(right)
     }
 
+    fn differing_contexts(&self, left: Span, right: Span) -> bool {
+        if self.inner.ignore_desugarings {
+            differing_macro_contexts(outside_desugarings(left), outside_desugarings(right))
+        } else {
+            differing_macro_contexts(left, right)
+        }
+    }
+
     #[allow(clippy::similar_names)]
     pub fn eq_expr(&mut self, left: &Expr<'_>, right: &Expr<'_>) -> bool {
-        if !self.inner.allow_side_effects && differing_macro_contexts(left.span, right.span) {
+        if !self.inner.allow_side_effects && self.differing_contexts(left.span, right.span) {
             return false;
         }
 
@@ -501,6 +522,30 @@ pub fn eq_expr_value(cx: &LateContext<'_>, left: &Expr<'_>, right: &Expr<'_>) ->
     SpanlessEq::new(cx).deny_side_effects().eq_expr(left, right)
 }
 
+/// Same as `eq_expr_value`, but the expressions coming from a desugaring are considered to be in
+/// the macro context of the code the desugaring comes from, so that an expression is still equal
+/// to its desugared copy.
+///
+/// This can consider expressions equal although they come from different desugarings of the same
+/// code. Prefer `eq_expr_value` unless the desugarings are known to be the same.
+pub fn eq_expr_value_macro_aware(cx: &LateContext<'_>, left: &Expr<'_>, right: &Expr<'_>) -> bool {
+    SpanlessEq::new(cx)
+        .deny_side_effects()
+        .ignore_desugarings()
+        .eq_expr(left, right)
+}
+
+// Walks `span` up the desugarings it comes from, to the span of the code they desugar.
+fn outside_desugarings(mut span: Span) -> Span {
+    loop {
+        let data = span.ctxt().outer_expn_data();
+        match data.kind {
+            ExpnKind::Desugaring(_) => span = data.call_site,
+            _ => return span,
+        }
+    }
+}
+
 /// Type used to hash an ast element. This is different from the `Hash` trait
 /// on ast types as this
 /// trait would consider IDs and spans.
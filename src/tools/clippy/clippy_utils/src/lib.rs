@@ -23,6 +23,7 @@
 extern crate rustc_infer;
 extern crate rustc_lexer;
 extern crate rustc_lint;
+extern crate rustc_metadata;
 extern crate rustc_middle;
 extern crate rustc_mir;
 extern crate rustc_session;
@@ -56,7 +57,7 @@
 pub mod visitors;
 
 pub use self::attrs::*;
-pub use self::hir_utils::{both, count_eq, eq_expr_value, over, SpanlessEq, SpanlessHash};
+pub use self::hir_utils::{both, count_eq, eq_expr_value, eq_expr_value_macro_aware, over, SpanlessEq, SpanlessHash};
 
 use std::collections::hash_map::Entry;
 use std::hash::BuildHasherDefault;
@@ -75,6 +76,7 @@
     PathSegment, QPath, Stmt, StmtKind, TraitItem, TraitItemKind, TraitRef, TyKind, UnOp,
 };
 use rustc_lint::{LateContext, Level, Lint, LintContext};
+use rustc_metadata::creader::CStore;
 use rustc_middle::hir::exports::Export;
 use rustc_middle::hir::map::Map;
 use rustc_middle::ty as rustc_ty;
@@ -254,6 +256,35 @@ pub fn in_macro(span: Span) -> bool {
     }
 }
 
+/// Returns `true` if this `span` was expanded by a procedural macro: an attribute or derive macro,
+/// including the built-in ones such as `#[derive(Debug)]`, or a function-like macro defined in a
+/// proc-macro crate.
+///
+/// The spans of the code generated by these macros may point anywhere, e.g. at the whole item a
+/// derive is applied to, so their snippets can't be used in suggestions. `is_present_in_source`
+/// only catches the generated code whose snippet is empty, not all of it.
+#[must_use]
+pub fn is_from_proc_macro(cx: &LateContext<'_>, mut span: Span) -> bool {
+    while span.from_expansion() {
+        let data = span.ctxt().outer_expn_data();
+        match data.kind {
+            ExpnKind::Macro(MacroKind::Attr | MacroKind::Derive, _) => return true,
+            // Proc macros can't be used in the crate defining them. The dependency kind can't tell
+            // them apart: `#[no_link]` crates are loaded for their macros only, too.
+            ExpnKind::Macro(MacroKind::Bang, _)
+                if data.macro_def_id.map_or(false, |def_id| {
+                    !def_id.is_local() && CStore::from_tcx(cx.tcx).is_proc_macro_crate_untracked(def_id.krate)
+                }) =>
+            {
+                return true;
+            },
+            _ => {},
+        }
+        span = data.call_site;
+    }
+    false
+}
+
 /// Checks if given pattern is a wildcard (`_`)
 pub fn is_wild<'tcx>(pat: &impl std::ops::Deref<Target = Pat<'tcx>>) -> bool {
     matches!(pat.kind, PatKind::Wild)
@@ -647,16 +678,27 @@ pub fn method_calls<'tcx>(
 /// containing the `Expr`s for
 /// `.bar()` and `.baz()`
 pub fn method_chain_args<'a>(expr: &'a Expr<'_>, methods: &[&str]) -> Option<Vec<&'a [Expr<'a>]>> {
+    method_chain_args_and_spans(expr, methods).map(|(matched, _)| matched)
+}
+
+/// Same as `method_chain_args`, but also returns the spans of the matched method calls, from the
+/// method name to the closing parenthesis, e.g. the span of `bar()` in `foo.bar()`.
+pub fn method_chain_args_and_spans<'a>(
+    expr: &'a Expr<'_>,
+    methods: &[&str],
+) -> Option<(Vec<&'a [Expr<'a>]>, Vec<Span>)> {
     let mut current = expr;
     let mut matched = Vec::with_capacity(methods.len());
+    let mut spans = Vec::with_capacity(methods.len());
     for method_name in methods.iter().rev() {
         // method chains are stored last -> first
-        if let ExprKind::MethodCall(path, _, args, _) = current.kind {
+        if let ExprKind::MethodCall(path, span, args, _) = current.kind {
             if path.ident.name.as_str() == *method_name {
                 if args.iter().any(|e| e.span.from_expansion()) {
                     return None;
                 }
                 matched.push(args); // build up `matched` backwards
+                spans.push(span);
                 current = &args[0]; // go to parent expression
             } else {
                 return None;
@@ -665,9 +707,10 @@ pub fn method_chain_args<'a>(expr: &'a Expr<'_>, methods: &[&str]) -> Option<Vec
             return None;
         }
     }
-    // Reverse `matched` so that it is in the same order as `methods`.
+    // Reverse `matched` and `spans` so that they are in the same order as `methods`.
     matched.reverse();
-    Some(matched)
+    spans.reverse();
+    Some((matched, spans))
 }
 
 /// Returns `true` if the provided `def_id` is an entrypoint to a program.
@@ -752,6 +795,63 @@ fn nested_visit_map(&mut self) -> hir::intravisit::NestedVisitorMap<Self::Map> {
     visitor.found
 }
 
+/// Returns `true` if evaluating `expr` may have an observable side effect, e.g. if it calls a
+/// function, assigns to a place or leaves the current control flow.
+///
+/// Literals, paths, field accesses and built-in operators applied to them don't have any. This
+/// errs on the side of `true`, so e.g. indexing, which may panic, or loops, which may not
+/// terminate, are considered to have side effects.
+pub fn has_side_effect<'tcx>(cx: &LateContext<'tcx>, expr: &'tcx Expr<'tcx>) -> bool {
+    struct SideEffectFinder<'a, 'tcx> {
+        cx: &'a LateContext<'tcx>,
+        found: bool,
+    }
+
+    impl<'a, 'tcx> Visitor<'tcx> for SideEffectFinder<'a, 'tcx> {
+        type Map = Map<'tcx>;
+
+        fn visit_expr(&mut self, expr: &'tcx Expr<'_>) {
+            if self.found {
+                return;
+            }
+            match expr.kind {
+                // Overloaded operators call the methods of their traits.
+                ExprKind::Unary(..) | ExprKind::Binary(..) if self.cx.typeck_results().is_method_call(expr) => {
+                    self.found = true;
+                },
+                ExprKind::Lit(_)
+                | ExprKind::Path(_)
+                | ExprKind::Field(..)
+                | ExprKind::Unary(..)
+                | ExprKind::Binary(..)
+                | ExprKind::AddrOf(..)
+                | ExprKind::Cast(..)
+                | ExprKind::Type(..)
+                | ExprKind::DropTemps(_)
+                | ExprKind::Tup(_)
+                | ExprKind::Array(_)
+                | ExprKind::Repeat(..)
+                | ExprKind::Struct(..)
+                | ExprKind::ConstBlock(_)
+                | ExprKind::Block(..)
+                | ExprKind::If(..)
+                | ExprKind::Match(..) => walk_expr(self, expr),
+                // The body of a closure isn't evaluated when the closure is created.
+                ExprKind::Closure(..) => {},
+                _ => self.found = true,
+            }
+        }
+
+        fn nested_visit_map(&mut self) -> NestedVisitorMap<Self::Map> {
+            NestedVisitorMap::None
+        }
+    }
+
+    let mut visitor = SideEffectFinder { cx, found: false };
+    visitor.visit_expr(expr);
+    visitor.found
+}
+
 struct FindMacroCalls<'a, 'b> {
     names: &'a [&'b str],
     result: Vec<Span>,
@@ -841,6 +941,32 @@ pub fn get_enclosing_block<'tcx>(cx: &LateContext<'tcx>, hir_id: HirId) -> Optio
     })
 }
 
+/// Gets the `DefId` of the function, method or associated function whose body contains the node,
+/// if any. Closures aren't functions here, so the function containing a closure is returned for
+/// the nodes in it.
+pub fn enclosing_fn_def_id(cx: &LateContext<'_>, hir_id: HirId) -> Option<DefId> {
+    let map = cx.tcx.hir();
+    for (id, node) in map.parent_iter(hir_id) {
+        match node {
+            Node::Item(Item {
+                kind: ItemKind::Fn(..), ..
+            })
+            | Node::ImplItem(ImplItem {
+                kind: ImplItemKind::Fn(..),
+                ..
+            })
+            | Node::TraitItem(TraitItem {
+                kind: TraitItemKind::Fn(..),
+                ..
+            }) => return Some(map.local_def_id(id).to_def_id()),
+            // The node is in another kind of item, e.g. in the body of a constant.
+            Node::Item(_) | Node::ImplItem(_) | Node::TraitItem(_) | Node::ForeignItem(_) => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
 /// Gets the loop or closure enclosing the given expression, if any.
 pub fn get_enclosing_loop_or_closure(tcx: TyCtxt<'tcx>, expr: &Expr<'_>) -> Option<&'tcx Expr<'tcx>> {
     let map = tcx.hir();
@@ -909,16 +1035,20 @@ pub fn is_integer_const(cx: &LateContext<'_>, e: &Expr<'_>, value: u128) -> bool
     if is_integer_literal(e, value) {
         return true;
     }
+    matches!(as_const(cx, e), Some(Constant::Int(v)) if v == value)
+}
+
+/// Returns the value of the given expression if it can be evaluated at compile time, e.g. a
+/// boolean, character, number or string.
+///
+/// Unlike `consts::constant`, this uses the `TypeckResults` of the body the expression is in, so
+/// it can be used outside of that body.
+pub fn as_const<'tcx>(cx: &LateContext<'tcx>, e: &Expr<'_>) -> Option<Constant> {
     let map = cx.tcx.hir();
     let parent_item = map.get_parent_item(e.hir_id);
-    if let Some((Constant::Int(v), _)) = map
-        .maybe_body_owned_by(parent_item)
+    map.maybe_body_owned_by(parent_item)
         .and_then(|body_id| constant(cx, cx.tcx.typeck_body(body_id), e))
-    {
-        value == v
-    } else {
-        false
-    }
+        .map(|(c, _)| c)
 }
 
 /// Checks whether the given expression is a constant literal of the given value.
@@ -946,6 +1076,9 @@ pub fn is_adjusted(cx: &LateContext<'_>, e: &Expr<'_>) -> bool {
 /// Returns the pre-expansion span if is this comes from an expansion of the
 /// macro `name`.
 /// See also `is_direct_expn_of`.
+///
+/// Macros are only matched by name, so a macro of another crate with the same
+/// name matches too. Prefer `expn_macro_def_id` to match a specific macro.
 #[must_use]
 pub fn is_expn_of(mut span: Span, name: &str) -> Option<Span> {
     loop {
@@ -975,6 +1108,8 @@ pub fn is_expn_of(mut span: Span, name: &str) -> Option<Span> {
 /// `42` is considered expanded from `foo!` and `bar!` by `is_expn_of` but only
 /// `bar!` by
 /// `is_direct_expn_of`.
+///
+/// Like `is_expn_of`, this matches any macro named `name`.
 #[must_use]
 pub fn is_direct_expn_of(span: Span, name: &str) -> Option<Span> {
     if span.from_expansion() {
@@ -991,6 +1126,25 @@ pub fn is_direct_expn_of(span: Span, name: &str) -> Option<Span> {
     None
 }
 
+/// Returns the `DefId` of the bang macro the span directly comes from an
+/// expansion of, as `is_direct_expn_of` would find it.
+///
+/// Unlike the name given to `is_direct_expn_of`, the `DefId` tells apart the
+/// macros of different crates with the same name, e.g. the `vec!` of `std`
+/// and a `vec!` defined by the user. Compare it with the `DefId` of a known
+/// macro, e.g. with `match_def_path` or `is_diagnostic_item`.
+#[must_use]
+pub fn expn_macro_def_id(span: Span) -> Option<DefId> {
+    if span.from_expansion() {
+        let data = span.ctxt().outer_expn_data();
+        if let ExpnKind::Macro(MacroKind::Bang, _) = data.kind {
+            return data.macro_def_id;
+        }
+    }
+
+    None
+}
+
 /// Convenience function to get the return type of a function.
 pub fn return_ty<'tcx>(cx: &LateContext<'tcx>, fn_item: hir::HirId) -> Ty<'tcx> {
     let fn_def_id = cx.tcx.hir().local_def_id(fn_item);
@@ -1160,6 +1314,26 @@ fn is_err(cx: &LateContext<'_>, arm: &Arm<'_>) -> bool {
     None
 }
 
+/// Returns the number of `?` operators applied in a row to the innermost operand of `expr`, e.g.
+/// 2 for `x??`. Returns 0 if `expr` isn't desugared from a `?` operator.
+pub fn try_chain_depth<'tcx>(mut expr: &'tcx Expr<'tcx>) -> usize {
+    let mut depth = 0;
+    loop {
+        if_chain! {
+            if let ExprKind::Match(scrutinee, _, MatchSource::TryDesugar) = expr.kind;
+            // `Try::branch(<expr>)`
+            if let ExprKind::Call(branch, [operand]) = scrutinee.kind;
+            if let ExprKind::Path(QPath::LangItem(LangItem::TryTraitBranch, _)) = branch.kind;
+            then {
+                depth += 1;
+                expr = operand;
+                continue;
+            }
+        }
+        return depth;
+    }
+}
+
 /// Returns `true` if the lint is allowed in the current context
 ///
 /// Useful for skipping long running code when it's unnecessary
@@ -283,11 +283,16 @@ pub fn snippet_with_context(
     let (span, is_macro_call) = walk_span_to_context(span, outer).map_or_else(
         || {
             // The span is from a macro argument, and the outer context is the macro using the argument
-            if *applicability != Applicability::Unspecified {
-                *applicability = Applicability::MaybeIncorrect;
-            }
-            // TODO: get the argument span.
-            (span, false)
+            walk_span_to_macro_arg(span, outer).map_or_else(
+                || {
+                    // The argument can't be recovered, so the snippet is the expansion
+                    if *applicability != Applicability::Unspecified {
+                        *applicability = Applicability::MaybeIncorrect;
+                    }
+                    (span, false)
+                },
+                |arg_span| (arg_span, span.ctxt() != arg_span.ctxt()),
+            )
         },
         |outer_span| (outer_span, span.ctxt() != outer),
     );
@@ -329,6 +334,29 @@ pub fn walk_span_to_context(span: Span, outer: SyntaxContext) -> Option<Span> {
     (outer_span.ctxt() == outer).then(|| outer_span)
 }
 
+/// Walks the span of a macro argument up to the context the argument was written in, i.e. the
+/// context of the call site of one of the macros the target context is expanded from. Returns
+/// `None` if the span isn't from an argument of any of these macros.
+///
+/// Given the following
+///
+/// ```rust,ignore
+/// macro_rules! m { ($e:expr) => { f($e) }; }
+/// m!(vec![])
+/// ```
+///
+/// If called with a span of the expansion of `vec![]` and the context of the call to `f`, this will
+/// return a span containing `vec![]`.
+fn walk_span_to_macro_arg(span: Span, mut outer: SyntaxContext) -> Option<Span> {
+    while outer != SyntaxContext::root() {
+        outer = outer.outer_expn_data().call_site.ctxt();
+        if let Some(arg_span) = walk_span_to_context(span, outer) {
+            return Some(arg_span);
+        }
+    }
+    None
+}
+
 /// Removes block comments from the given `Vec` of lines.
 ///
 /// # Examples
@@ -245,6 +245,15 @@ pub fn is_type_diagnostic_item(cx: &LateContext<'_>, ty: Ty<'_>, diag_item: Symb
     }
 }
 
+/// Checks if the type is equal to a diagnostic item once all references on it are peeled off,
+/// e.g. `&Vec<T>` or `&mut &String`. Returns the number of references removed if it is.
+///
+/// Use `is_type_diagnostic_item` to only match the type itself.
+pub fn is_ref_type_diagnostic_item(cx: &LateContext<'_>, ty: Ty<'_>, diag_item: Symbol) -> Option<usize> {
+    let (ty, count) = peel_mid_ty_refs(ty);
+    is_type_diagnostic_item(cx, ty, diag_item).then(|| count)
+}
+
 /// Checks if the type is equal to a lang item
 pub fn is_type_lang_item(cx: &LateContext<'_>, ty: Ty<'_>, lang_item: hir::LangItem) -> bool {
     match ty.kind() {
@@ -323,6 +332,18 @@ fn inner(ty: Ty<'_>, depth: usize) -> (Ty<'_>, usize) {
     inner(ty, 0)
 }
 
+/// Returns the base type for references and the mutability of each reference removed, from the
+/// outermost to the innermost.
+/// `&mut &&mut u8` would return `(u8, vec![Mut, Not, Mut])`.
+pub fn ref_mutability_chain(mut ty: Ty<'_>) -> (Ty<'_>, Vec<Mutability>) {
+    let mut chain = Vec::new();
+    while let ty::Ref(_, inner, mutability) = *ty.kind() {
+        chain.push(mutability);
+        ty = inner;
+    }
+    (ty, chain)
+}
+
 /// Returns `true` if types `a` and `b` are same types having same `Const` generic args,
 /// otherwise returns `false`
 pub fn same_type_and_consts(a: Ty<'tcx>, b: Ty<'tcx>) -> bool {
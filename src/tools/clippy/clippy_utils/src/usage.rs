@@ -199,3 +199,56 @@ pub fn contains_return_break_continue_macro(expression: &Expr<'_>) -> bool {
     recursive_visitor.visit_expr(expression);
     recursive_visitor.seen_return_break_continue
 }
+
+/// Returns the locals of the enclosing scopes which are referred to by the body of a closure,
+/// i.e. the variables it captures.
+///
+/// The captures computed by typeck are used when they're known. Otherwise, these are the locals
+/// referred to by the body, including by the closures nested in it, which it doesn't bind.
+pub fn captured_locals<'tcx>(cx: &LateContext<'tcx>, closure_body: &'tcx hir::Body<'tcx>) -> HirIdSet {
+    let closure_def_id = cx.tcx.hir().body_owner_def_id(closure_body.id());
+    if let Some(captures) = cx
+        .tcx
+        .typeck(closure_def_id)
+        .closure_min_captures
+        .get(&closure_def_id.to_def_id())
+    {
+        return captures.keys().copied().collect();
+    }
+
+    let mut visitor = CapturedLocalsVisitor {
+        cx,
+        used: HirIdSet::default(),
+        bound: HirIdSet::default(),
+    };
+    visitor.visit_body(closure_body);
+    visitor.used.difference(&visitor.bound).copied().collect()
+}
+
+struct CapturedLocalsVisitor<'a, 'tcx> {
+    cx: &'a LateContext<'tcx>,
+    used: HirIdSet,
+    bound: HirIdSet,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for CapturedLocalsVisitor<'a, 'tcx> {
+    type Map = Map<'tcx>;
+
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        if let Some(id) = utils::path_to_local(expr) {
+            self.used.insert(id);
+        }
+        intravisit::walk_expr(self, expr);
+    }
+
+    fn visit_pat(&mut self, pat: &'tcx hir::Pat<'tcx>) {
+        if let hir::PatKind::Binding(_, id, ..) = pat.kind {
+            self.bound.insert(id);
+        }
+        intravisit::walk_pat(self, pat);
+    }
+
+    fn nested_visit_map(&mut self) -> NestedVisitorMap<Self::Map> {
+        NestedVisitorMap::OnlyBodies(self.cx.tcx.hir())
+    }
+}
@@ -0,0 +1,18 @@
+// run-rustfix
+// aux-build:macro_rules.rs
+
+#![warn(clippy::map_entry)]
+
+#[macro_use]
+extern crate macro_rules;
+
+use std::collections::HashMap;
+
+// A value generated by a `macro_rules!` macro of another crate isn't mistaken for the output of a
+// proc macro, and is suggested as written.
+fn main() {
+    let mut m: HashMap<u32, u32> = HashMap::new();
+    if !m.contains_key(&1) {
+        m.insert(1, map_entry_value!());
+    }
+}
@@ -0,0 +1,22 @@
+// run-rustfix
+
+#![warn(clippy::map_entry)]
+
+use std::collections::HashMap;
+
+macro_rules! insert_if_absent {
+    ($map:expr, $key:expr, $val:expr) => {
+        if !$map.contains_key(&$key) {
+            $map.insert($key, $val);
+        }
+    };
+}
+
+// The suggestions use the macro arguments as they are written.
+fn main() {
+    let mut m: HashMap<u32, Vec<u32>> = HashMap::new();
+    insert_if_absent!(m, 1, vec![2, 3]);
+
+    let mut m: HashMap<u32, String> = HashMap::new();
+    insert_if_absent!(m, 1, format!("{}", 2));
+}
@@ -490,13 +490,27 @@ fn configure_lldb(config: &Config) -> Option<Config> {
 pub fn test_opts(config: &Config) -> test::TestOpts {
     test::TestOpts {
         exclude_should_panic: false,
+        #[cfg(not(bootstrap))]
+        only_should_panic: false,
         filters: config.filters.clone(),
         filter_exact: config.filter_exact,
+        #[cfg(not(bootstrap))]
+        tests_from_stdin: false,
+        #[cfg(not(bootstrap))]
+        selected_tests: None,
+        #[cfg(not(bootstrap))]
+        allow_unknown_tests: false,
         run_ignored: if config.run_ignored { test::RunIgnored::Yes } else { test::RunIgnored::No },
         format: if config.quiet { test::OutputFormat::Terse } else { test::OutputFormat::Pretty },
+        #[cfg(not(bootstrap))]
+        terse_flush_interval: test::DEFAULT_TERSE_FLUSH_INTERVAL,
         logfile: config.logfile.clone(),
+        #[cfg(not(bootstrap))]
+        logfile_format: test::LogfileFormat::Plain,
         run_tests: true,
         bench_benchmarks: true,
+        #[cfg(not(bootstrap))]
+        bench_only: false,
         nocapture: match env::var("RUST_TEST_NOCAPTURE") {
             Ok(val) => &val != "0",
             Err(_) => false,
@@ -504,10 +518,50 @@ pub fn test_opts(config: &Config) -> test::TestOpts {
         color: config.color,
         test_threads: None,
         skip: vec![],
+        #[cfg(not(bootstrap))]
+        allowed_panic_messages: vec![],
+        #[cfg(not(bootstrap))]
+        timeout_action: test::TimeoutAction::Report,
         list: false,
+        #[cfg(not(bootstrap))]
+        dump_opts: false,
+        #[cfg(not(bootstrap))]
+        server: false,
         options: test::Options::new(),
         time_options: None,
+        #[cfg(not(bootstrap))]
+        bench_percentiles: vec![],
+        #[cfg(not(bootstrap))]
+        bench_count_allocs: false,
+        #[cfg(not(bootstrap))]
+        report_memory: false,
+        #[cfg(not(bootstrap))]
+        bench_warmup: std::time::Duration::new(0, 0),
+        #[cfg(not(bootstrap))]
+        bench_samples: test::DEFAULT_BENCH_SAMPLES,
+        #[cfg(not(bootstrap))]
+        hash_names: false,
+        #[cfg(not(bootstrap))]
+        hash_map: None,
+        #[cfg(not(bootstrap))]
+        kill_on_timeout: None,
+        #[cfg(not(bootstrap))]
+        shuffle_seed: None,
+        #[cfg(not(bootstrap))]
+        retries: 0,
+        #[cfg(not(bootstrap))]
+        repeat: 1,
+        #[cfg(not(bootstrap))]
+        fail_fast: false,
+        #[cfg(not(bootstrap))]
+        flakiness_report: None,
+        #[cfg(not(bootstrap))]
+        before_each: None,
+        #[cfg(not(bootstrap))]
+        after_each: None,
         force_run_in_process: false,
+        #[cfg(not(bootstrap))]
+        force_subprocess: false,
     }
 }
 